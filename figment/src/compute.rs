@@ -4,7 +4,7 @@ use once_cell::sync::Lazy;
 use std::iter::Peekable;
 use std::time::Duration;
 
-pub(crate) mod rate_limiter;
+pub mod rate_limiter;
 pub(crate) mod running_average;
 mod timer;
 
@@ -2,9 +2,11 @@ use crate::compute::running_average::RunningAverage;
 use std::cmp::min;
 use std::time::{Duration, Instant};
 
-pub(crate) struct RateLimiter {
+pub struct RateLimiter {
     pub(crate) max_duration: Option<Duration>,
     pub(crate) average_duration: RunningAverage<Duration>, // TODO : This should be passed as argument in functions that need it..
+    /// Last time `ready` allowed a run.
+    last_run: Option<Instant>,
 }
 
 impl Default for RateLimiter {
@@ -12,6 +14,7 @@ impl Default for RateLimiter {
         Self {
             max_duration: None,
             average_duration: RunningAverage::<Duration>::new(60),
+            last_run: None,
         }
     }
 }
@@ -53,6 +56,45 @@ impl RateLimiter {
     pub fn record_duration(&mut self, duration: Duration) {
         self.average_duration.record(duration);
     }
+
+    /// Whether a subsystem limited to the maximum rate may run now, ie. once per frame, next to compute and render.
+    /// Always true without a maximum rate.
+    pub fn ready(&mut self) -> bool {
+        self.ready_at(Instant::now())
+    }
+
+    pub fn ready_at(&mut self, now: Instant) -> bool {
+        let ready = match (self.max_duration, self.last_run) {
+            (Some(period), Some(last)) => now.saturating_duration_since(last) >= period,
+            _ => true,
+        };
+        if ready {
+            self.last_run = Some(now);
+        }
+        ready
+    }
 }
 
-//TODO : test timers behavior (via injection of deterministic timer...)
+#[cfg(test)]
+mod tests {
+    use crate::compute::rate_limiter::RateLimiter;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn check_ready_at_maximum_rate() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut limiter = RateLimiter::default().with_maximum_duration(Duration::from_millis(100));
+
+        assert!(limiter.ready_at(at(0)));
+        assert!(!limiter.ready_at(at(50)));
+        assert!(limiter.ready_at(at(100)));
+        assert!(!limiter.ready_at(at(150)));
+        assert!(limiter.ready_at(at(450)));
+        assert!(!limiter.ready_at(at(500)));
+
+        let mut unlimited = RateLimiter::default();
+        assert!(unlimited.ready_at(at(0)));
+        assert!(unlimited.ready_at(at(0)));
+    }
+}
@@ -1,26 +1,48 @@
-use crate::graphics::quad::{Drawable, Updatable};
+use crate::graphics::backend::Backend;
+use crate::graphics::camera::Camera;
 use macroquad::color::RED;
-use macroquad::math::IVec2;
-use macroquad::prelude::{clear_background, get_fps, get_frame_time, next_frame, Color, Image};
+use macroquad::math::{IVec2, Vec2};
+use macroquad::prelude::{
+    get_fps, get_frame_time, next_frame, screen_height, screen_width, set_camera,
+    set_default_camera, Color,
+};
 use macroquad::ui;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::ops::Deref;
+use std::path::Path;
 use std::time::Duration;
 
 // mod ui;
-// mod scene;
-mod color;
-mod image;
+pub mod backend;
+pub mod bitmap;
+pub mod camera;
+pub mod color;
+pub mod dirty;
+pub mod golden;
+pub mod image;
 pub(crate) mod quad;
-mod rect;
+pub mod rect;
+pub mod scene;
 pub mod sprite;
-mod texture;
+pub mod texture;
+pub mod tiled;
 pub(crate) mod view;
 
+pub use quad::{Drawable, Placed, Updatable};
+
 const DEFAULT_BACKGROUND: Color = RED;
 
+thread_local! {
+    static LAST_FRAME_UPLOAD: Cell<u64> = const { Cell::new(0) };
+}
+
 pub trait Viewable {
-    fn render(&self) -> &RefCell<Image>;
+    fn render(&self) -> &RefCell<image::RGBAImage>;
+
+    /// The parts of the rendering changed since the last call, or None if unknown : all of it then.
+    fn take_changes(&self) -> Option<Vec<dirty::DirtyRect>> {
+        None
+    }
 }
 
 pub fn last_frame_time() -> Duration {
@@ -37,23 +59,59 @@ pub fn target_frame_time(target_fps: f32) -> Duration {
     Duration::from_secs_f32(1. / target_fps)
 }
 
+/// Pixels uploaded to the GPU during the last frame, for profiling.
+pub fn last_frame_upload() -> u64 {
+    LAST_FRAME_UPLOAD.with(Cell::get)
+}
+
+/// Updates only what changed in the viewable, when it tells.
 pub fn update(d: &mut impl Updatable, v: &impl Viewable) {
-    d.update(v.render().borrow().deref());
+    let image = v.render().borrow();
+    match v.take_changes() {
+        Some(changed) => d.update_rects(image.deref(), &changed),
+        None => d.update(image.deref()),
+    }
+}
+
+/// Writes the current rendering of a viewable to a PNG file. No window is required.
+pub fn export(v: &impl Viewable, path: impl AsRef<Path>) -> Result<(), bitmap::BitmapError> {
+    bitmap::save_png(v.render().borrow().deref(), path)
 }
 
 //Note : top caller for draw => Same API as Drawable !
+/// Draws a frame : anything drawable, ie. a whole `scene::Scene`, at `pos` on screen.
 pub async fn render(d: &impl Drawable, pos: IVec2) {
     //
     // pub(crate) async fn update(&mut self, viewable: &mut impl Drawable) {
-    clear_background(DEFAULT_BACKGROUND);
+    let mut backend = backend::Macroquad::default();
+    backend.clear(DEFAULT_BACKGROUND);
+
+    d.draw(&mut backend, pos);
+
+    finish_frame(backend.uploaded()).await;
+}
+
+/// Draws a frame, the drawable being placed in the world seen by the camera.
+pub async fn render_with_camera(d: &impl Drawable, camera: &Camera) {
+    let mut backend = backend::Macroquad::default();
+    backend.clear(DEFAULT_BACKGROUND);
+
+    set_camera(&camera.to_camera2d(Vec2::new(screen_width(), screen_height())));
+    d.draw(&mut backend, IVec2::ZERO);
+    set_default_camera();
+
+    finish_frame(backend.uploaded()).await;
+}
 
-    d.draw(pos);
+async fn finish_frame(uploaded: u64) {
+    LAST_FRAME_UPLOAD.with(|last| last.set(uploaded));
 
     //TODO : on screen / window instead of log...
     // println!("FPS: {}", self.current_fps());
 
     //CAREFUL with z order !
     ui::root_ui().label(None, format!("FPS: {}", current_fps()).as_str());
+    ui::root_ui().label(None, format!("Uploaded: {} px", uploaded).as_str());
 
     next_frame().await;
 }
@@ -0,0 +1,104 @@
+//! What drawables draw with : the GPU through macroquad in the game, or the CPU (see `software`)
+//! in tests and on headless servers.
+use crate::graphics::texture::Texture;
+use macroquad::color::Color;
+use macroquad::math::{IVec2, UVec2};
+use macroquad::prelude::{FilterMode, Image, Texture2D};
+
+pub mod software;
+
+pub trait Backend {
+    /// Fills the whole target.
+    fn clear(&mut self, color: Color);
+
+    fn draw_rect(&mut self, position: IVec2, dimensions: UVec2, color: Color);
+
+    /// Draws a texture at its size, its pixels multiplied by `tint`.
+    fn draw_texture(&mut self, texture: &Texture, position: IVec2, tint: Color);
+
+    /// Draws a line of text, `size` pixels high, its top left corner at `position`.
+    fn draw_text(&mut self, text: &str, position: IVec2, size: f32, color: Color);
+}
+
+/// Draws on screen, with macroquad. Textures are uploaded to the GPU when first drawn,
+/// then only their changed parts.
+/// Textures are limited to u16 dimensions, drawing a larger one panics : draw it as a `tiled::TiledTexture`.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Macroquad {
+    uploaded: u64,
+}
+
+impl Macroquad {
+    /// Pixels uploaded to the GPU so far, for profiling.
+    pub fn uploaded(&self) -> u64 {
+        self.uploaded
+    }
+}
+
+impl Backend for Macroquad {
+    fn clear(&mut self, color: Color) {
+        macroquad::prelude::clear_background(color);
+    }
+
+    fn draw_rect(&mut self, position: IVec2, dimensions: UVec2, color: Color) {
+        macroquad::prelude::draw_rectangle(
+            position.x as f32,
+            position.y as f32,
+            dimensions.x as f32,
+            dimensions.y as f32,
+            color,
+        );
+    }
+
+    fn draw_texture(&mut self, texture: &Texture, position: IVec2, tint: Color) {
+        let mut gpu = texture.gpu.borrow_mut();
+        let image = &texture.image;
+        match gpu.as_ref() {
+            None => {
+                let (width, height) = match image.u16_dimensions() {
+                    Ok(dimensions) => dimensions,
+                    Err(e) => panic!("{} : draw it as a TiledTexture", e),
+                };
+                let uploaded = Texture2D::from_rgba8(width, height, image.as_bytes());
+                uploaded.set_filter(FilterMode::Nearest);
+                *gpu = Some(uploaded);
+                texture.take_dirty();
+                self.uploaded += image.width() as u64 * image.height() as u64;
+            }
+            Some(uploaded) => {
+                for rect in texture.take_dirty() {
+                    let (position, dimensions) = (
+                        UVec2::new(rect.x, rect.y),
+                        UVec2::new(rect.width, rect.height),
+                    );
+                    // a part of the texture : no larger than it
+                    let part = image.crop(position, dimensions).map(Image::try_from);
+                    if let Some(Ok(part)) = part {
+                        uploaded.update_part(
+                            &part,
+                            rect.x as i32,
+                            rect.y as i32,
+                            rect.width as i32,
+                            rect.height as i32,
+                        );
+                        self.uploaded += rect.area();
+                    }
+                }
+            }
+        }
+        if let Some(uploaded) = gpu.as_ref() {
+            macroquad::prelude::draw_texture(uploaded, position.x as f32, position.y as f32, tint);
+        }
+    }
+
+    fn draw_text(&mut self, text: &str, position: IVec2, size: f32, color: Color) {
+        // macroquad places text by its baseline
+        macroquad::prelude::draw_text(
+            text,
+            position.x as f32,
+            position.y as f32 + size * 0.75,
+            size,
+            color,
+        );
+    }
+}
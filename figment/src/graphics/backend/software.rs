@@ -0,0 +1,281 @@
+use crate::graphics::backend::Backend;
+use crate::graphics::color::RGBA8;
+use crate::graphics::image::{ops, RGBAImage};
+use crate::graphics::texture::Texture;
+use macroquad::color::Color;
+use macroquad::math::{IVec2, UVec2};
+
+/// Glyphs of the built in font, 3x5 pixels : a row per byte, top first, the leftmost pixel being the highest bit.
+fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        ' ' => [0b000, 0b000, 0b000, 0b000, 0b000],
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '+' => [0b000, 0b010, 0b111, 0b010, 0b000],
+        '=' => [0b000, 0b111, 0b000, 0b111, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        '(' => [0b010, 0b100, 0b100, 0b100, 0b010],
+        ')' => [0b010, 0b001, 0b001, 0b001, 0b010],
+        '!' => [0b010, 0b010, 0b010, 0b000, 0b010],
+        '?' => [0b111, 0b001, 0b010, 0b000, 0b010],
+        '_' => [0b000, 0b000, 0b000, 0b000, 0b111],
+        '\'' => [0b010, 0b010, 0b000, 0b000, 0b000],
+        // unknown
+        _ => [0b111; 5],
+    }
+}
+
+fn channels(color: Color) -> [f32; 4] {
+    [color.r, color.g, color.b, color.a]
+}
+
+/// Draws on the CPU, into an image : no window nor GPU needed.
+/// Colours are blended over the image by their alpha, as macroquad does by default.
+pub struct Software {
+    target: RGBAImage,
+}
+
+impl Software {
+    /// A transparent image to draw on.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            target: RGBAImage::generate(width, height, RGBA8::from([0; 4])),
+        }
+    }
+
+    pub fn image(&self) -> &RGBAImage {
+        &self.target
+    }
+
+    pub fn into_image(self) -> RGBAImage {
+        self.target
+    }
+
+    /// The pixels of a rectangle inside the target, as (x, y) ranges.
+    fn clip(
+        &self,
+        position: IVec2,
+        dimensions: UVec2,
+    ) -> (std::ops::Range<u32>, std::ops::Range<u32>) {
+        let clip = |start: i32, size: u32, max: usize| {
+            let end = (start as i64 + size as i64).clamp(0, max as i64) as u32;
+            (start.max(0) as u32).min(end)..end
+        };
+        (
+            clip(position.x, dimensions.x, self.target.width()),
+            clip(position.y, dimensions.y, self.target.height()),
+        )
+    }
+
+    fn blend(&mut self, x: u32, y: u32, color: [f32; 4]) {
+        let under = self.target.get_pixel(x, y);
+        self.target.set_pixel(x, y, ops::blend(color, under));
+    }
+}
+
+impl Backend for Software {
+    fn clear(&mut self, color: Color) {
+        let color = channels(color).map(|c| (c.clamp(0., 1.) * 255.).round() as u8);
+        for pixel in self.target.get_image_data_mut() {
+            *pixel = RGBA8::from(color);
+        }
+    }
+
+    fn draw_rect(&mut self, position: IVec2, dimensions: UVec2, color: Color) {
+        let (columns, rows) = self.clip(position, dimensions);
+        for y in rows {
+            for x in columns.clone() {
+                self.blend(x, y, channels(color));
+            }
+        }
+    }
+
+    fn draw_texture(&mut self, texture: &Texture, position: IVec2, tint: Color) {
+        let image = &texture.image;
+        let dimensions = UVec2::new(image.width() as u32, image.height() as u32);
+        let (columns, rows) = self.clip(position, dimensions);
+        let tint = channels(tint);
+        for y in rows {
+            for x in columns.clone() {
+                let (u, v) = (x as i64 - position.x as i64, y as i64 - position.y as i64);
+                let texel: [u8; 4] = image.get_pixel(u as u32, v as u32).into();
+                let color = [0, 1, 2, 3].map(|c| texel[c] as f32 / 255. * tint[c]);
+                self.blend(x, y, color);
+            }
+        }
+    }
+
+    /// With a built in 3x5 pixels font, scaled by whole pixels : lower case is drawn upper case.
+    fn draw_text(&mut self, text: &str, position: IVec2, size: f32, color: Color) {
+        // a pixel of space under each line
+        let scale = ((size / 6.).round() as i32).max(1);
+        for (i, c) in text.chars().enumerate() {
+            let left = position.x + i as i32 * 4 * scale;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (0b100 >> col) != 0 {
+                        self.draw_rect(
+                            IVec2::new(left + col * scale, position.y + row as i32 * scale),
+                            UVec2::splat(scale as u32),
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::backend::software::Software;
+    use crate::graphics::backend::Backend;
+    use crate::graphics::image::RGBAImage;
+    use crate::graphics::quad::{Drawable, Placed};
+    use crate::graphics::rect::Rect;
+    use crate::graphics::scene::Scene;
+    use crate::graphics::sprite::Sprite;
+    use crate::graphics::texture::Texture;
+    use macroquad::color::{Color, BLACK, WHITE, YELLOW};
+    use macroquad::math::{IVec2, UVec2};
+
+    const RED: Color = Color::new(1., 0., 0., 1.);
+    const BLUE: Color = Color::new(0., 0., 1., 1.);
+
+    fn pixel(backend: &Software, x: u32, y: u32) -> [u8; 4] {
+        backend.image().get_pixel(x, y).into()
+    }
+
+    fn bytes(color: Color) -> [u8; 4] {
+        [color.r, color.g, color.b, color.a].map(|c| (c * 255.).round() as u8)
+    }
+
+    #[test]
+    fn check_rect_is_clipped() {
+        let mut backend = Software::new(4, 3);
+        backend.clear(BLACK);
+        backend.draw_rect(IVec2::new(-2, 1), UVec2::new(4, 10), RED);
+
+        assert_eq!(pixel(&backend, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&backend, 1, 2), [255, 0, 0, 255]);
+        assert_eq!(pixel(&backend, 2, 1), [0, 0, 0, 255]);
+        // out of the target : nothing to do
+        backend.draw_rect(IVec2::new(10, 10), UVec2::new(4, 4), RED);
+        backend.draw_rect(IVec2::new(-10, 0), UVec2::new(4, 4), RED);
+    }
+
+    #[test]
+    fn check_alpha_blending() {
+        let mut backend = Software::new(2, 1);
+        backend.clear(WHITE);
+        backend.draw_rect(IVec2::ZERO, UVec2::new(1, 1), Color::new(0., 0., 1., 0.5));
+
+        assert_eq!(pixel(&backend, 0, 0), [128, 128, 255, 255]);
+        assert_eq!(pixel(&backend, 1, 0), [255, 255, 255, 255]);
+
+        // over nothing, the colour is kept
+        let mut backend = Software::new(1, 1);
+        backend.draw_rect(IVec2::ZERO, UVec2::new(1, 1), Color::new(1., 0., 0., 0.5));
+        assert_eq!(pixel(&backend, 0, 0), [255, 0, 0, 128]);
+    }
+
+    #[test]
+    fn check_texture_is_tinted() {
+        let mut image = RGBAImage::generate(2, 2, WHITE.into());
+        image.set_pixel(1, 1, BLUE.into());
+        let texture = Texture::new(&image, WHITE);
+
+        let mut backend = Software::new(3, 3);
+        backend.clear(BLACK);
+        backend.draw_texture(&texture, IVec2::new(1, 1), Color::new(1., 0., 1., 1.));
+
+        assert_eq!(pixel(&backend, 0, 0), [0, 0, 0, 255]);
+        assert_eq!(pixel(&backend, 1, 1), [255, 0, 255, 255]);
+        assert_eq!(pixel(&backend, 2, 2), [0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn check_scene_renders_headless() {
+        let mut scene = Scene::default();
+        let sprite = scene.add(
+            scene.root(),
+            Sprite::from_image(&RGBAImage::generate(3, 3, WHITE.into())),
+        );
+        let rect = scene.add(sprite, Rect::new(RED, UVec2::new(1, 1)));
+        scene[rect].set_position(IVec2::new(1, 1));
+        // under the sprite
+        scene[rect].set_z(-1);
+
+        let mut backend = Software::new(4, 4);
+        backend.clear(BLACK);
+        scene.draw(&mut backend, IVec2::new(1, 0));
+        // sprites are tinted yellow
+        assert_eq!(pixel(&backend, 2, 1), bytes(YELLOW));
+        assert_eq!(pixel(&backend, 0, 0), [0, 0, 0, 255]);
+
+        scene[rect].set_z(1);
+        scene.draw(&mut backend, IVec2::new(1, 0));
+        assert_eq!(pixel(&backend, 2, 1), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn check_text() {
+        let mut backend = Software::new(16, 8);
+        backend.clear(BLACK);
+        backend.draw_text("1-", IVec2::new(1, 1), 6., WHITE);
+
+        let row = |y: u32| -> Vec<bool> {
+            (0..8)
+                .map(|x| pixel(&backend, x, y) == [255, 255, 255, 255])
+                .collect()
+        };
+        // the top of 1, and the middle of -
+        assert_eq!(
+            row(1),
+            vec![false, false, true, false, false, false, false, false]
+        );
+        assert_eq!(
+            row(3),
+            vec![false, false, true, false, false, true, true, true]
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use crate::graphics::image::RGBAImage;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// Errors occurring while moving images in and out of PNG bitmaps.
+#[derive(Debug)]
+pub enum BitmapError {
+    Decoding(png::DecodingError),
+    Encoding(png::EncodingError),
+}
+
+impl Display for BitmapError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BitmapError::Decoding(e) => write!(f, "PNG decoding failed: {}", e),
+            BitmapError::Encoding(e) => write!(f, "PNG encoding failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for BitmapError {}
+
+impl From<png::DecodingError> for BitmapError {
+    fn from(value: png::DecodingError) -> Self {
+        BitmapError::Decoding(value)
+    }
+}
+
+impl From<png::EncodingError> for BitmapError {
+    fn from(value: png::EncodingError) -> Self {
+        BitmapError::Encoding(value)
+    }
+}
+
+/// Decodes a PNG stream into an RGBA image.
+/// Any PNG colour type is accepted, it is normalized to 8 bits per component first.
+pub fn read_png(reader: impl Read) -> Result<RGBAImage, BitmapError> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+
+    let mut png_reader = decoder.read_info()?;
+    let mut buffer = vec![0u8; png_reader.output_buffer_size()];
+    let info = png_reader.next_frame(&mut buffer)?;
+
+    let (width, height) = (info.width, info.height);
+
+    let pixels = width as usize * height as usize;
+    let mut bytes: Vec<u8> = Vec::with_capacity(pixels * 4);
+    for y in 0..height as usize {
+        let line = &buffer[y * info.line_size..(y + 1) * info.line_size];
+        match info.color_type {
+            png::ColorType::Rgba => bytes.extend_from_slice(&line[..width as usize * 4]),
+            png::ColorType::Rgb => line
+                .chunks_exact(3)
+                .take(width as usize)
+                .for_each(|c| bytes.extend_from_slice(&[c[0], c[1], c[2], 255])),
+            png::ColorType::GrayscaleAlpha => line
+                .chunks_exact(2)
+                .take(width as usize)
+                .for_each(|c| bytes.extend_from_slice(&[c[0], c[0], c[0], c[1]])),
+            png::ColorType::Grayscale => line
+                .iter()
+                .take(width as usize)
+                .for_each(|g| bytes.extend_from_slice(&[*g, *g, *g, 255])),
+            // EXPAND transformation guarantees we never get indexed data here.
+            png::ColorType::Indexed => unreachable!("indexed PNG should have been expanded"),
+        }
+    }
+
+    Ok(RGBAImage::from_bytes(width, height, bytes))
+}
+
+/// Encodes an RGBA image as a PNG stream.
+pub fn write_png(image: &RGBAImage, writer: impl Write) -> Result<(), BitmapError> {
+    let mut encoder = png::Encoder::new(writer, image.width() as u32, image.height() as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut png_writer = encoder.write_header()?;
+    png_writer.write_image_data(image.as_bytes())?;
+    png_writer.finish()?;
+    Ok(())
+}
+
+pub fn load_png(path: impl AsRef<Path>) -> Result<RGBAImage, BitmapError> {
+    let file = File::open(path).map_err(png::DecodingError::IoError)?;
+    read_png(BufReader::new(file))
+}
+
+/// Note : unlike macroquad's `Image::export_png`, the image is written as is (no flip), and errors are returned.
+pub fn save_png(image: &RGBAImage, path: impl AsRef<Path>) -> Result<(), BitmapError> {
+    let file = File::create(path).map_err(png::EncodingError::IoError)?;
+    write_png(image, BufWriter::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::bitmap::{read_png, write_png};
+    use crate::graphics::image::RGBAImage;
+    use macroquad::color::{BLACK, WHITE};
+
+    #[test]
+    fn check_png_roundtrip() {
+        let mut img = RGBAImage::generate(3, 2, WHITE.into());
+        img.set_pixel(2, 1, BLACK.into());
+
+        let mut encoded: Vec<u8> = vec![];
+        write_png(&img, &mut encoded).unwrap();
+        let decoded = read_png(encoded.as_slice()).unwrap();
+
+        assert_eq!(decoded.width(), 3);
+        assert_eq!(decoded.height(), 2);
+        assert_eq!(decoded, img);
+        assert_eq!(decoded.get_image_data()[5], [0u8, 0, 0, 255]);
+    }
+
+    #[test]
+    fn check_grayscale_png_expanded_to_rgba() {
+        let mut encoded: Vec<u8> = vec![];
+        {
+            let mut encoder = png::Encoder::new(&mut encoded, 2, 1);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&[0u8, 255u8]).unwrap();
+        }
+
+        let decoded = read_png(encoded.as_slice()).unwrap();
+
+        assert_eq!(
+            decoded.get_image_data(),
+            [[0u8, 0, 0, 255], [255u8, 255, 255, 255]]
+        );
+    }
+
+    #[test]
+    fn check_garbage_is_an_error() {
+        assert!(read_png([1u8, 2, 3].as_slice()).is_err());
+    }
+}
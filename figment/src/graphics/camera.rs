@@ -0,0 +1,219 @@
+use macroquad::camera::Camera2D;
+use macroquad::input::{
+    is_key_down, is_key_pressed, is_mouse_button_down, mouse_position, mouse_wheel, KeyCode,
+    MouseButton,
+};
+use macroquad::math::{Rect, Vec2};
+use std::time::Duration;
+
+const MIN_ZOOM: f32 = 1. / 16.;
+const MAX_ZOOM: f32 = 64.;
+/// Zoom factor of one mouse wheel notch, with Shift held.
+const FINE_ZOOM: f32 = 1.1;
+/// Keyboard panning speed, in screen pixels per second.
+const PAN_SPEED: f32 = 512.;
+
+/// What part of the world is shown on screen, and how big : `zoom` screen pixels per world unit,
+/// the world point `origin` being at the top left corner of the screen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Camera {
+    origin: Vec2,
+    zoom: f32,
+    /// Screen position of the mouse at the previous frame of a drag.
+    drag: Option<Vec2>,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            origin: Vec2::ZERO,
+            zoom: 1.,
+            drag: None,
+        }
+    }
+}
+
+impl Camera {
+    pub fn with_origin(self, origin: Vec2) -> Self {
+        Self { origin, ..self }
+    }
+
+    pub fn with_zoom(self, zoom: f32) -> Self {
+        Self {
+            zoom: zoom.clamp(MIN_ZOOM, MAX_ZOOM),
+            ..self
+        }
+    }
+
+    pub fn origin(&self) -> Vec2 {
+        self.origin
+    }
+
+    /// Screen pixels per world unit.
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    pub fn world_to_screen(&self, world: Vec2) -> Vec2 {
+        (world - self.origin) * self.zoom
+    }
+
+    pub fn screen_to_world(&self, screen: Vec2) -> Vec2 {
+        screen / self.zoom + self.origin
+    }
+
+    /// The world area shown on a screen of this size.
+    pub fn visible_world(&self, screen_size: Vec2) -> Rect {
+        let size = screen_size / self.zoom;
+        Rect::new(self.origin.x, self.origin.y, size.x, size.y)
+    }
+
+    /// Moves the view by a screen displacement : the world follows, as when dragged.
+    pub fn pan(&mut self, screen_displacement: Vec2) {
+        self.origin -= screen_displacement / self.zoom;
+    }
+
+    /// Multiplies the zoom, the world point under `focus` (on screen) staying there.
+    pub fn zoom_at(&mut self, factor: f32, focus: Vec2) {
+        self.set_zoom_at((self.zoom * factor).clamp(MIN_ZOOM, MAX_ZOOM), focus);
+    }
+
+    fn set_zoom_at(&mut self, zoom: f32, focus: Vec2) {
+        let world = self.screen_to_world(focus);
+        self.zoom = zoom;
+        self.origin = world - focus / self.zoom;
+    }
+
+    /// Goes `steps` zoom levels in (or out, if negative), focused on `focus` :
+    /// integer zooms from 1 up, halves below, so that world units stay whole pixels when zoomed in.
+    /// A fractional zoom goes to the next level first.
+    pub fn step_zoom_at(&mut self, steps: i32, focus: Vec2) {
+        let mut zoom = self.zoom;
+        for _ in 0..steps.unsigned_abs() {
+            zoom = match (steps > 0, zoom >= 1.) {
+                (true, true) => zoom.floor() + 1.,
+                (false, true) if zoom > 1. => zoom.ceil() - 1.,
+                // from 1 or below, levels are powers of 2
+                (true, false) => 2f32.powf(zoom.log2().floor() + 1.),
+                (false, _) => 2f32.powf(zoom.log2().ceil() - 1.),
+            };
+        }
+        self.set_zoom_at(zoom.clamp(MIN_ZOOM, MAX_ZOOM), focus);
+    }
+
+    /// A macroquad camera drawing the world as this camera shows it, on a screen of this size.
+    pub fn to_camera2d(&self, screen_size: Vec2) -> Camera2D {
+        Camera2D::from_display_rect(self.visible_world(screen_size))
+    }
+
+    /// Mouse and keyboard bindings : dragging with the middle button or W A S D pans,
+    /// the mouse wheel zooms in and out by levels around the cursor (finely, with Shift), Home resets.
+    pub fn control(&mut self, elapsed: Duration) {
+        let mouse = Vec2::from(mouse_position());
+
+        if is_mouse_button_down(MouseButton::Middle) {
+            if let Some(previous) = self.drag {
+                self.pan(mouse - previous);
+            }
+            self.drag = Some(mouse);
+        } else {
+            self.drag = None;
+        }
+
+        let direction = [
+            (KeyCode::A, Vec2::X),
+            (KeyCode::D, -Vec2::X),
+            (KeyCode::W, Vec2::Y),
+            (KeyCode::S, -Vec2::Y),
+        ]
+        .iter()
+        .filter(|(key, _)| is_key_down(*key))
+        .map(|(_, direction)| *direction)
+        .sum::<Vec2>();
+        self.pan(direction * PAN_SPEED * elapsed.as_secs_f32());
+
+        let wheel = mouse_wheel().1;
+        if wheel != 0. {
+            if is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) {
+                self.zoom_at(FINE_ZOOM.powf(wheel.signum()), mouse);
+            } else {
+                self.step_zoom_at(wheel.signum() as i32, mouse);
+            }
+        }
+
+        if is_key_pressed(KeyCode::Home) {
+            *self = Self::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::camera::Camera;
+    use macroquad::math::{Rect, Vec2};
+
+    #[test]
+    fn check_conversions() {
+        let camera = Camera::default()
+            .with_origin(Vec2::new(-5., -10.))
+            .with_zoom(2.);
+        assert_eq!(
+            camera.world_to_screen(Vec2::new(1., 3.)),
+            Vec2::new(12., 26.)
+        );
+        assert_eq!(
+            camera.screen_to_world(Vec2::new(12., 26.)),
+            Vec2::new(1., 3.)
+        );
+        assert_eq!(
+            camera.visible_world(Vec2::new(800., 600.)),
+            Rect::new(-5., -10., 400., 300.)
+        );
+    }
+
+    #[test]
+    fn check_pan_follows_the_mouse() {
+        let mut camera = Camera::default().with_zoom(4.);
+        let grabbed = camera.screen_to_world(Vec2::new(100., 100.));
+        camera.pan(Vec2::new(40., -20.));
+        assert_eq!(camera.world_to_screen(grabbed), Vec2::new(140., 80.));
+    }
+
+    #[test]
+    fn check_zoom_keeps_the_focus_in_place() {
+        let mut camera = Camera::default().with_origin(Vec2::new(3., 7.));
+        let focus = Vec2::new(200., 120.);
+        let under = camera.screen_to_world(focus);
+
+        camera.zoom_at(1.5, focus);
+        assert_eq!(camera.zoom(), 1.5);
+        assert!((camera.world_to_screen(under) - focus).length() < 1e-3);
+
+        camera.step_zoom_at(3, focus);
+        assert!((camera.world_to_screen(under) - focus).length() < 1e-3);
+    }
+
+    #[test]
+    fn check_zoom_levels() {
+        let mut camera = Camera::default();
+        let levels = |camera: &mut Camera, steps: i32| {
+            camera.step_zoom_at(steps, Vec2::ZERO);
+            camera.zoom()
+        };
+        assert_eq!(levels(&mut camera, 1), 2.);
+        assert_eq!(levels(&mut camera, 2), 4.);
+        assert_eq!(levels(&mut camera, -3), 1.);
+        assert_eq!(levels(&mut camera, -2), 0.25);
+        assert_eq!(levels(&mut camera, 1), 0.5);
+        assert_eq!(levels(&mut camera, -100), 1. / 16.);
+        assert_eq!(levels(&mut camera, 1000), 64.);
+
+        // from a fractional zoom, to the next level
+        let mut camera = Camera::default().with_zoom(2.5);
+        assert_eq!(levels(&mut camera, 1), 3.);
+        let mut camera = Camera::default().with_zoom(2.5);
+        assert_eq!(levels(&mut camera, -1), 2.);
+        let mut camera = Camera::default().with_zoom(0.3);
+        assert_eq!(levels(&mut camera, 1), 0.5);
+    }
+}
@@ -7,74 +7,74 @@ pub(crate) mod rgba;
 pub use rgb::{RGB32, RGB8};
 pub use rgba::{RGBA32, RGBA8};
 
-pub(crate) trait Channel: Default + Copy + PartialEq {
+pub trait Channel: Default + Copy + PartialEq {
     /// bits per component / color channel
     fn bpc() -> u32;
 }
 
-impl const Channel for ColorByte {
+impl Channel for ColorByte {
     fn bpc() -> u32 {
         ColorByte::BITS
     }
 }
 
-impl const Channel for Monochrome {
+impl Channel for Monochrome {
     fn bpc() -> u32 {
         32
     }
 }
 
-pub(crate) trait Pixel<C: Channel, const COMPONENTS: u8>:
-    Default + Copy + PartialEq
-{
+/// What images are made of : `COMPONENTS` channels per pixel.
+pub trait Pixel: Default + Copy + PartialEq {
+    type Channel: Channel;
+    const COMPONENTS: u8;
+
     /// bits per pixel
     fn bpp() -> u32 {
-        C::bpc() * COMPONENTS as u32
+        Self::Channel::bpc() * Self::COMPONENTS as u32
     }
-
-    // fn as_array(self) -> [C; components as usize]
-    // where Self: Sized;
 }
 
-impl Pixel<ColorByte, 1> for ColorByte {
-    // fn as_array(self) -> [ColorByte; 1] {
-    //     [self]
-    // }
+impl Pixel for ColorByte {
+    type Channel = ColorByte;
+    const COMPONENTS: u8 = 1;
 }
 
-impl Pixel<Monochrome, 1> for Monochrome {
-    // fn as_array(self) -> [Monochrome; 1] {
-    //     [self]
-    // }
+impl Pixel for Monochrome {
+    type Channel = Monochrome;
+    const COMPONENTS: u8 = 1;
 }
 
-impl Pixel<ColorByte, 3> for RGB8 {
-    // fn as_array(self) -> [ColorByte; 3] {
-    //     self.into()
-    // }
+impl Pixel for RGB8 {
+    type Channel = ColorByte;
+    const COMPONENTS: u8 = 3;
 }
 
-impl Pixel<Monochrome, 3> for RGB32 {
-    // fn as_array(self) -> [Monochrome; 3] {
-    //     self.into()
-    // }
+impl Pixel for RGB32 {
+    type Channel = Monochrome;
+    const COMPONENTS: u8 = 3;
 }
 
-impl Pixel<ColorByte, 4> for RGBA8 {
-    // fn as_array(self) -> [ColorByte; 4] {
-    //     self.into()
-    // }
+impl Pixel for RGBA8 {
+    type Channel = ColorByte;
+    const COMPONENTS: u8 = 4;
 }
 
-impl Pixel<Monochrome, 4> for RGBA32 {
-    // fn as_array(self) -> [Monochrome; 4] {
-    //     self.into()
-    // }
+impl Pixel for RGBA32 {
+    type Channel = Monochrome;
+    const COMPONENTS: u8 = 4;
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::graphics::color::{ColorByte, Monochrome, Pixel, RGB8, RGBA32, RGBA8};
 
-    // #[test]
-    // fn check_gray_u8_from_u8() {}
+    #[test]
+    fn check_bits_per_pixel() {
+        assert_eq!(ColorByte::bpp(), 8);
+        assert_eq!(RGB8::bpp(), 24);
+        assert_eq!(RGBA8::bpp(), 32);
+        assert_eq!(Monochrome::bpp(), 32);
+        assert_eq!(RGBA32::bpp(), 128);
+    }
 }
@@ -1,4 +1,5 @@
 use crate::graphics::color::monochrome::{ColorByte, Monochrome};
+use macroquad::color::Color;
 use macroquad::math::{UVec4, Vec4};
 use std::fmt::Debug;
 use std::num::TryFromIntError;
@@ -87,6 +88,22 @@ impl From<[Monochrome; 4]> for RGBA32 {
     }
 }
 
+//macroquad, rounding as macroquad does
+
+impl From<Color> for RGBA8 {
+    #[inline]
+    fn from(value: Color) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<RGBA8> for Color {
+    #[inline]
+    fn from(value: RGBA8) -> Self {
+        value.0.into()
+    }
+}
+
 //(U)Vec
 
 impl From<RGBA8> for UVec4 {
@@ -160,6 +177,13 @@ mod tests {
     use macroquad::math::UVec4;
     use macroquad::prelude::Vec4;
 
+    #[test]
+    fn check_macroquad_color_roundtrip() {
+        let c = RGBA8::from(macroquad::color::Color::new(1., 0.5, 0., 1.));
+        assert_eq!(c, [255u8, 127u8, 0u8, 255u8]);
+        assert_eq!(RGBA8::from(macroquad::color::Color::from(c)), c);
+    }
+
     //From & Into Array
 
     #[test]
@@ -0,0 +1,210 @@
+//! What changed in an image since it was last rendered or uploaded, as a few rectangles.
+
+/// Past this many rectangles, the closest ones are merged : a larger upload costs less than many small ones.
+/// Scattered changes stay apart, instead of making one box of (nearly) the whole image.
+const MAX_RECTS: usize = 32;
+
+/// A rectangle of pixels (or cells).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DirtyRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl DirtyRect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn pixel(x: u32, y: u32) -> Self {
+        Self::new(x, y, 1, 1)
+    }
+
+    pub fn area(&self) -> u64 {
+        self.width as u64 * self.height as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+
+    fn right(&self) -> u32 {
+        self.x + self.width
+    }
+
+    fn bottom(&self) -> u32 {
+        self.y + self.height
+    }
+
+    /// Overlapping, or side by side : worth merging.
+    fn touches(&self, other: &DirtyRect) -> bool {
+        self.x <= other.right()
+            && other.x <= self.right()
+            && self.y <= other.bottom()
+            && other.y <= self.bottom()
+    }
+
+    /// The bounding box of both.
+    pub fn union(&self, other: &DirtyRect) -> DirtyRect {
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        DirtyRect::new(
+            x,
+            y,
+            self.right().max(other.right()) - x,
+            self.bottom().max(other.bottom()) - y,
+        )
+    }
+
+    /// The part inside an image of this size, if any.
+    pub fn clip(&self, width: u32, height: u32) -> Option<DirtyRect> {
+        let (right, bottom) = (self.right().min(width), self.bottom().min(height));
+        let clipped = DirtyRect::new(
+            self.x,
+            self.y,
+            right.saturating_sub(self.x),
+            bottom.saturating_sub(self.y),
+        );
+        (!clipped.is_empty()).then_some(clipped)
+    }
+}
+
+/// Accumulates changed rectangles, merging those that touch.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DirtyRects {
+    rects: Vec<DirtyRect>,
+}
+
+impl DirtyRects {
+    /// All of an image of this size.
+    pub fn all(width: u32, height: u32) -> Self {
+        let mut dirty = Self::default();
+        dirty.add(DirtyRect::new(0, 0, width, height));
+        dirty
+    }
+
+    pub fn add(&mut self, rect: DirtyRect) {
+        if rect.is_empty() {
+            return;
+        }
+        self.insert(rect);
+        while self.rects.len() > MAX_RECTS {
+            let (i, j) = self.closest_pair();
+            let merged = self.rects[i].union(&self.rects[j]);
+            self.rects.swap_remove(j);
+            self.rects.swap_remove(i);
+            self.insert(merged);
+        }
+    }
+
+    /// Merges a rectangle with the ones it touches, and theirs, so that none overlap.
+    fn insert(&mut self, mut rect: DirtyRect) {
+        while let Some(i) = self.rects.iter().position(|r| r.touches(&rect)) {
+            rect = rect.union(&self.rects.swap_remove(i));
+        }
+        self.rects.push(rect);
+    }
+
+    /// The two rectangles adding the least area once merged, the first one first.
+    fn closest_pair(&self) -> (usize, usize) {
+        let waste =
+            |a: &DirtyRect, b: &DirtyRect| a.union(b).area().saturating_sub(a.area() + b.area());
+        let n = self.rects.len();
+        (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .min_by_key(|(i, j)| waste(&self.rects[*i], &self.rects[*j]))
+            .expect("at least two rectangles")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rects.is_empty()
+    }
+
+    pub fn rects(&self) -> &[DirtyRect] {
+        &self.rects
+    }
+
+    /// Total pixels (or cells) to update. Rectangles never overlap.
+    pub fn area(&self) -> u64 {
+        self.rects.iter().map(DirtyRect::area).sum()
+    }
+
+    /// The rectangles, this being clean afterwards.
+    pub fn take(&mut self) -> Vec<DirtyRect> {
+        std::mem::take(&mut self.rects)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::dirty::{DirtyRect, DirtyRects, MAX_RECTS};
+
+    #[test]
+    fn check_touching_rects_merge() {
+        let mut dirty = DirtyRects::default();
+        dirty.add(DirtyRect::pixel(1, 1));
+        dirty.add(DirtyRect::pixel(2, 1));
+        assert_eq!(dirty.rects(), &[DirtyRect::new(1, 1, 2, 1)]);
+
+        // apart
+        dirty.add(DirtyRect::pixel(10, 10));
+        assert_eq!(dirty.rects().len(), 2);
+        assert_eq!(dirty.area(), 3);
+
+        // bridging both
+        dirty.add(DirtyRect::new(3, 2, 7, 8));
+        assert_eq!(dirty.rects(), &[DirtyRect::new(1, 1, 10, 10)]);
+
+        // inside, and empty
+        dirty.add(DirtyRect::pixel(5, 5));
+        dirty.add(DirtyRect::new(50, 50, 0, 3));
+        assert_eq!(dirty.take(), vec![DirtyRect::new(1, 1, 10, 10)]);
+        assert!(dirty.is_empty());
+    }
+
+    #[test]
+    fn check_too_many_rects_merge_the_closest() {
+        let mut dirty = DirtyRects::default();
+        for i in 0..MAX_RECTS as u32 {
+            dirty.add(DirtyRect::pixel(i * 10, 0));
+        }
+        // next to the first one
+        dirty.add(DirtyRect::pixel(0, 2));
+        assert_eq!(dirty.rects().len(), MAX_RECTS);
+        assert!(dirty.rects().contains(&DirtyRect::new(0, 0, 1, 3)));
+        assert_eq!(dirty.area(), MAX_RECTS as u64 + 2);
+    }
+
+    #[test]
+    fn check_scattered_rects_stay_apart() {
+        let mut dirty = DirtyRects::default();
+        // activity in the four corners of a large board
+        for i in 0..MAX_RECTS as u32 * 4 {
+            let (x, y) = ((i % 4) * 20_000, (i / 4 % 2) * 20_000);
+            dirty.add(DirtyRect::pixel(x + i * 3, y + i * 5));
+        }
+        assert!(dirty.rects().len() <= MAX_RECTS);
+        assert!(dirty.area() < 40_000 * 40_000 / 100, "{}", dirty.area());
+        // never overlapping
+        let rects = dirty.rects();
+        for (i, a) in rects.iter().enumerate() {
+            for b in &rects[i + 1..] {
+                assert!(!a.touches(b));
+            }
+        }
+    }
+
+    #[test]
+    fn check_clip() {
+        let rect = DirtyRect::new(2, 3, 10, 10);
+        assert_eq!(rect.clip(5, 20), Some(DirtyRect::new(2, 3, 3, 10)));
+        assert_eq!(rect.clip(2, 20), None);
+        assert_eq!(DirtyRects::all(4, 2).area(), 8);
+    }
+}
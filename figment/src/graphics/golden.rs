@@ -0,0 +1,265 @@
+//! Golden image testing : renderings are compared to PNG fixtures, pixel by pixel.
+//!
+//! When a rendering changes on purpose, run the tests with `FIGMENT_BLESS=1` to overwrite the goldens
+//! with the new renderings, and review the PNGs before committing them.
+//! On a mismatch, the rendering and a diff image are written next to the golden,
+//! as `<name>.actual.png` and `<name>.diff.png`.
+use crate::graphics::backend::software::Software;
+use crate::graphics::backend::Backend;
+use crate::graphics::bitmap;
+use crate::graphics::image::RGBAImage;
+use crate::graphics::quad::Drawable;
+use macroquad::color::Color;
+use macroquad::math::IVec2;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+
+/// Set (to anything but 0) to write goldens instead of checking them.
+pub const BLESS_VAR: &str = "FIGMENT_BLESS";
+
+/// How a rendering differs from its golden.
+#[derive(Debug)]
+pub enum Mismatch {
+    Size {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+    Pixels {
+        /// Number of pixels with a channel further than the tolerance.
+        differing: usize,
+        /// The largest channel difference.
+        worst: u8,
+        /// Differing pixels in red, brighter the further they are, over the golden in faded grey.
+        diff: RGBAImage,
+    },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mismatch::Size { expected, actual } => write!(
+                f,
+                "expected a {}x{} image, rendered {}x{}",
+                expected.0, expected.1, actual.0, actual.1
+            ),
+            Mismatch::Pixels {
+                differing, worst, ..
+            } => write!(
+                f,
+                "{} pixels differ, by up to {} on a channel",
+                differing, worst
+            ),
+        }
+    }
+}
+
+/// Draws a drawable at the top left corner of an image of this size, on a background, without a window.
+pub fn render(d: &impl Drawable, width: u32, height: u32, background: Color) -> RGBAImage {
+    let mut backend = Software::new(width, height);
+    backend.clear(background);
+    d.draw(&mut backend, IVec2::ZERO);
+    backend.into_image()
+}
+
+/// Pixels match when none of their channels differ by more than `tolerance`.
+pub fn compare(expected: &RGBAImage, actual: &RGBAImage, tolerance: u8) -> Result<(), Mismatch> {
+    let size = |image: &RGBAImage| (image.width(), image.height());
+    if size(expected) != size(actual) {
+        return Err(Mismatch::Size {
+            expected: size(expected),
+            actual: size(actual),
+        });
+    }
+
+    let mut differing = 0;
+    let mut worst = 0;
+    let mut diff = Vec::with_capacity(expected.as_bytes().len());
+    for (e, a) in expected
+        .as_bytes()
+        .chunks_exact(4)
+        .zip(actual.as_bytes().chunks_exact(4))
+    {
+        let distance = e
+            .iter()
+            .zip(a)
+            .map(|(e, a)| e.abs_diff(*a))
+            .max()
+            .unwrap_or(0);
+        worst = worst.max(distance);
+        if distance > tolerance {
+            differing += 1;
+            diff.extend_from_slice(&[128 + distance / 2, 0, 0, 255]);
+        } else {
+            let grey = ((e[0] as u16 + e[1] as u16 + e[2] as u16) / 12) as u8;
+            diff.extend_from_slice(&[grey, grey, grey, 255]);
+        }
+    }
+
+    if differing == 0 {
+        return Ok(());
+    }
+    Err(Mismatch::Pixels {
+        differing,
+        worst,
+        diff: RGBAImage::from_bytes(expected.width() as u32, expected.height() as u32, diff),
+    })
+}
+
+fn blessing() -> bool {
+    std::env::var(BLESS_VAR).is_ok_and(|v| !v.is_empty() && v != "0")
+}
+
+/// `fixtures/golden/foo.png` => `fixtures/golden/foo.<suffix>.png`
+fn sibling(golden: &Path, suffix: &str) -> PathBuf {
+    let stem = golden.file_stem().unwrap_or_default().to_string_lossy();
+    golden.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+/// Checks a rendering against the golden PNG at `path`, or overwrites it when blessing.
+/// # Panics
+/// If the golden is missing or does not match, after writing the rendering and the diff next to it.
+pub fn assert_golden(path: impl AsRef<Path>, actual: &RGBAImage, tolerance: u8) {
+    let path = path.as_ref();
+    if blessing() {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).expect("creating the goldens directory");
+        }
+        bitmap::save_png(actual, path).expect("writing the golden");
+        return;
+    }
+
+    let expected = bitmap::load_png(path).unwrap_or_else(|e| {
+        panic!(
+            "no golden at {} ({}) : run with {}=1 to create it",
+            path.display(),
+            e,
+            BLESS_VAR
+        )
+    });
+    if let Err(mismatch) = compare(&expected, actual, tolerance) {
+        let actual_path = sibling(path, "actual");
+        bitmap::save_png(actual, &actual_path).expect("writing the rendering");
+        if let Mismatch::Pixels { diff, .. } = &mismatch {
+            bitmap::save_png(diff, sibling(path, "diff")).expect("writing the diff");
+        }
+        panic!(
+            "rendering does not match {} : {}. See {} ; run with {}=1 if the change is intended",
+            path.display(),
+            mismatch,
+            actual_path.display(),
+            BLESS_VAR
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::golden::{assert_golden, compare, render, Mismatch};
+    use crate::graphics::image::RGBAImage;
+    use crate::graphics::quad::{Quad, Updatable};
+    use crate::graphics::rect::Rect;
+    use crate::graphics::scene::Scene;
+    use crate::graphics::sprite::Sprite;
+    use crate::graphics::texture::Texture;
+    use crate::graphics::Placed;
+    use macroquad::color::{Color, BLACK, WHITE};
+    use macroquad::math::{IVec2, UVec2};
+    use std::path::PathBuf;
+
+    const RED: Color = Color::new(1., 0., 0., 1.);
+    const GREEN: Color = Color::new(0., 1., 0., 1.);
+    const BLUE: Color = Color::new(0., 0., 1., 1.);
+
+    fn golden(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures/golden")
+            .join(format!("{}.png", name))
+    }
+
+    /// A small pattern, so that flips and offsets show.
+    fn checker() -> RGBAImage {
+        let mut image = RGBAImage::generate(4, 4, WHITE.into());
+        image.set_pixel(0, 0, RED.into());
+        image.set_pixel(3, 0, GREEN.into());
+        image.set_pixel(0, 3, BLUE.into());
+        image.set_pixel(1, 1, BLACK.into());
+        image.set_pixel(2, 2, Color::new(0., 0., 0., 0.5).into());
+        image
+    }
+
+    #[test]
+    fn check_compare() {
+        let expected = RGBAImage::generate(2, 2, WHITE.into());
+        let mut actual = RGBAImage::generate(2, 2, WHITE.into());
+        actual.set_pixel(0, 0, [250, 255, 255, 255].into());
+        assert!(compare(&expected, &actual, 5).is_ok());
+
+        match compare(&expected, &actual, 4) {
+            Err(Mismatch::Pixels {
+                differing,
+                worst,
+                diff,
+            }) => {
+                assert_eq!((differing, worst), (1, 5));
+                assert_eq!(diff.get_pixel(0, 0), [128 + 2, 0, 0, 255]);
+                assert_eq!(diff.get_pixel(1, 0), [63, 63, 63, 255]);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(matches!(
+            compare(&expected, &RGBAImage::generate(2, 3, WHITE.into()), 255),
+            Err(Mismatch::Size { .. })
+        ));
+    }
+
+    #[test]
+    fn check_rect_golden() {
+        let mut scene = Scene::default();
+        let back = scene.add(scene.root(), Rect::new(BLUE, UVec2::new(12, 6)));
+        scene[back].set_position(IVec2::new(2, 2));
+        let front = scene.add(
+            scene.root(),
+            Rect::new(Color::new(1., 0., 0., 0.5), UVec2::new(6, 8)),
+        );
+        scene[front].set_position(IVec2::new(8, 5));
+        // clipped
+        let out = scene.add(scene.root(), Rect::new(GREEN, UVec2::new(4, 4)));
+        scene[out].set_position(IVec2::new(-2, 12));
+
+        assert_golden(golden("rect"), &render(&scene, 16, 16, BLACK), 0);
+    }
+
+    #[test]
+    fn check_sprite_golden() {
+        let mut scene = Scene::default();
+        // no image yet : a plain rectangle
+        let mut plain = Sprite::default();
+        plain.set_background(GREEN);
+        plain.set_dimensions(UVec2::new(12, 10));
+        let plain = scene.add(scene.root(), plain);
+        scene[plain].set_position(IVec2::new(2, 3));
+        let mut sprite = Sprite::from_image(&checker());
+        let textured = scene.add(scene.root(), Sprite::from_image(&checker()));
+        scene[textured].set_position(IVec2::new(18, 4));
+        // updated in place, then replaced by a larger image
+        sprite.update(&RGBAImage::generate(4, 4, RED.into()));
+        sprite.update(&RGBAImage::generate(6, 3, WHITE.into()));
+        let updated = scene.add(scene.root(), sprite);
+        scene[updated].set_position(IVec2::new(18, 12));
+
+        assert_golden(golden("sprite"), &render(&scene, 24, 16, BLACK), 0);
+    }
+
+    #[test]
+    fn check_texture_golden() {
+        let mut scene = Scene::default();
+        scene.add(scene.root(), Texture::new(&checker(), WHITE));
+        let shifted = scene.add(scene.root(), Texture::new(&checker(), WHITE));
+        scene[shifted].set_position(IVec2::new(5, 2));
+        let clipped = scene.add(scene.root(), Texture::new(&checker(), WHITE));
+        scene[clipped].set_position(IVec2::new(8, 6));
+
+        assert_golden(golden("texture"), &render(&scene, 10, 8, BLUE), 0);
+    }
+}
@@ -1,45 +1,202 @@
-mod grayscale;
-mod rgb;
+pub(crate) mod ops;
 mod rgba;
 
-use crate::graphics::color::{Channel, Pixel};
+use crate::graphics::color::{ColorByte, Pixel, RGB8, RGBA8};
 use macroquad::math::Rect;
+use std::fmt::{Display, Formatter};
 
-// Note : A trait is a safe way (avoids maintenance overhead) to keep implementations similar.
-// But if they are *exactly* identical, then maybe we should have a generic struct instead ?
-pub(crate) trait Image<C: Channel, const COMPONENTS: u8> {
-    type P: Pixel<C, COMPONENTS>;
+/// Pixels row by row, from the top left corner.
+/// Dimensions are u32, larger than macroquad's own images and textures (u16) : see `TooLarge`,
+/// and `tiled::TiledTexture` to draw them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image<P: Pixel> {
+    pixels: Vec<P>,
+    width: u32,
+    height: u32,
+}
 
-    fn empty() -> Self;
+/// An image larger than a destination limited to u16 dimensions, ie. macroquad images and textures.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TooLarge {
+    pub width: usize,
+    pub height: usize,
+}
 
-    fn generate(width: u16, height: u16, pixel: Self::P) -> Self;
+impl Display for TooLarge {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} image does not fit in u16 dimensions",
+            self.width, self.height
+        )
+    }
+}
 
-    fn update(&mut self, pixel_slice: &[Self::P]);
+impl std::error::Error for TooLarge {}
 
-    fn width(&self) -> usize;
-    fn height(&self) -> usize;
-    fn get_image_data(&self) -> &[Self::P];
-    fn get_image_data_mut(&mut self) -> &mut [Self::P];
-    fn set_pixel(&mut self, x: u32, y: u32, pixel: Self::P);
+pub type GrayscaleImage = Image<ColorByte>;
+pub type RGBImage = Image<RGB8>;
+pub type RGBAImage = Image<RGBA8>;
 
-    fn get_pixel(&self, x: u32, y: u32) -> Self::P;
-    fn sub_image(&self, rect: Rect) -> Self;
+impl<P: Pixel> Default for Image<P> {
+    fn default() -> Self {
+        Self::empty()
+    }
 }
 
-// TODO : in a separate texture module ?
-// pub(crate) struct RGBTexture<T: Pixel<Monochrome, 3>> {
-//     pub bytes: Vec<T>,
-//     pub width: u16,
-//     pub height: u16,
-// }
-//
-// pub(crate) struct RGBATexture<T: Pixel<Monochrome, 4>> {
-//     pub bytes: Vec<T>,
-//     pub width: u16,
-//     pub height: u16,
-// }
-
-//TODO : conversion with Macroquad structs (Image, etc.)
+impl<P: Pixel> Image<P> {
+    pub fn empty() -> Self {
+        Self {
+            pixels: vec![],
+            width: 0,
+            height: 0,
+        }
+    }
+
+    pub fn generate(width: u32, height: u32, pixel: P) -> Self {
+        Self {
+            pixels: vec![pixel; width as usize * height as usize],
+            width,
+            height,
+        }
+    }
+
+    /// # Panics
+    /// If there are not exactly `width * height` pixels.
+    pub fn from_pixels(width: u32, height: u32, pixels: Vec<P>) -> Self {
+        assert_eq!(width as usize * height as usize, pixels.len());
+        Self {
+            pixels,
+            width,
+            height,
+        }
+    }
+
+    /// Replaces all pixels.
+    /// # Panics
+    /// If the slice is not exactly the size of the image.
+    pub fn update(&mut self, pixels: &[P]) {
+        self.pixels.copy_from_slice(pixels);
+    }
+
+    pub fn width(&self) -> usize {
+        self.width as usize
+    }
+
+    pub fn height(&self) -> usize {
+        self.height as usize
+    }
+
+    /// Dimensions as macroquad takes them, if they fit.
+    pub fn u16_dimensions(&self) -> Result<(u16, u16), TooLarge> {
+        match (u16::try_from(self.width), u16::try_from(self.height)) {
+            (Ok(width), Ok(height)) => Ok((width, height)),
+            _ => Err(TooLarge {
+                width: self.width(),
+                height: self.height(),
+            }),
+        }
+    }
+
+    pub fn get_image_data(&self) -> &[P] {
+        &self.pixels
+    }
+
+    pub fn get_image_data_mut(&mut self) -> &mut [P] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<P> {
+        self.pixels
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        self.pixels[y as usize * self.width as usize + x as usize] = pixel;
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> P {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+
+    /// # Panics
+    /// If the rectangle is not inside the image : see `crop`, which checks.
+    pub fn sub_image(&self, rect: Rect) -> Self {
+        let (x, y) = (rect.x as usize, rect.y as usize);
+        let (width, height) = (rect.w as usize, rect.h as usize);
+        let pixels = (y..y + height)
+            .flat_map(|row| {
+                let start = row * self.width as usize + x;
+                &self.pixels[start..start + width]
+            })
+            .copied()
+            .collect();
+        Self::from_pixels(width as u32, height as u32, pixels)
+    }
+}
 
 #[cfg(test)]
-mod tests {}
+mod tests {
+    use crate::graphics::color::RGB8;
+    use crate::graphics::image::{GrayscaleImage, RGBImage, TooLarge};
+    use macroquad::math::Rect;
+
+    #[test]
+    fn check_empty() {
+        let image = GrayscaleImage::empty();
+        assert_eq!((image.width(), image.height()), (0, 0));
+        assert!(image.get_image_data().is_empty());
+    }
+
+    #[test]
+    fn check_pixels_by_rows() {
+        let mut image = GrayscaleImage::generate(3, 2, 128);
+        image.set_pixel(2, 0, 1);
+        image.get_image_data_mut()[3] = 2;
+
+        assert_eq!(image.get_pixel(2, 0), 1);
+        assert_eq!(image.get_pixel(0, 1), 2);
+        assert_eq!(image.get_image_data(), &[128, 128, 1, 2, 128, 128]);
+
+        image.update(&[0; 6]);
+        assert_eq!(image.into_pixels(), vec![0; 6]);
+    }
+
+    #[test]
+    fn check_larger_than_u16() {
+        let mut image = GrayscaleImage::generate(70_000, 2, 0);
+        image.set_pixel(69_999, 1, 7);
+
+        assert_eq!(image.width(), 70_000);
+        assert_eq!(image.get_pixel(69_999, 1), 7);
+        assert_eq!(
+            image.u16_dimensions(),
+            Err(TooLarge {
+                width: 70_000,
+                height: 2
+            })
+        );
+        assert_eq!(
+            GrayscaleImage::generate(3, 2, 0).u16_dimensions(),
+            Ok((3, 2))
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_pixels_must_fill_the_image() {
+        GrayscaleImage::from_pixels(2, 2, vec![0; 3]);
+    }
+
+    #[test]
+    fn check_sub_image() {
+        let image = RGBImage::from_pixels(3, 3, (0..9).map(|i| RGB8::from([i, 0, 0])).collect());
+
+        let sub = image.sub_image(Rect::new(1., 1., 2., 2.));
+
+        assert_eq!((sub.width(), sub.height()), (2, 2));
+        assert_eq!(
+            sub.get_image_data(),
+            &[4, 5, 7, 8].map(|i| RGB8::from([i, 0, 0]))
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! Whole image operations, ie. to compose overlays on the CPU.
+//! Operations that may go outside the image are clipped, except `crop`, which checks.
+use crate::graphics::color::{Pixel, RGBA8};
+use crate::graphics::image::{Image, RGBAImage};
+use macroquad::math::{IVec2, UVec2};
+use std::ops::Range;
+
+impl<P: Pixel> Image<P> {
+    /// The part of a rectangle inside the image, as (x, y) ranges, empty if none.
+    fn clip(&self, position: IVec2, dimensions: UVec2) -> (Range<usize>, Range<usize>) {
+        let clip = |start: i32, size: u32, max: usize| {
+            let end = (start as i64 + size as i64).clamp(0, max as i64) as usize;
+            (start.max(0) as usize).min(end)..end
+        };
+        (
+            clip(position.x, dimensions.x, self.width()),
+            clip(position.y, dimensions.y, self.height()),
+        )
+    }
+
+    /// A new image of the pixels at `(x, y)`, built column by column, row by row.
+    fn map_positions(
+        &self,
+        width: usize,
+        height: usize,
+        source: impl Fn(usize, usize) -> (usize, usize),
+    ) -> Self {
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| source(x, y))
+            .map(|(x, y)| self.get_image_data()[y * self.width() + x])
+            .collect();
+        Self::from_pixels(width as u32, height as u32, pixels)
+    }
+
+    /// Sets the pixels of a rectangle, clipped to the image.
+    pub fn fill_rect(&mut self, position: IVec2, dimensions: UVec2, pixel: P) {
+        let (columns, rows) = self.clip(position, dimensions);
+        let width = self.width();
+        for y in rows {
+            self.get_image_data_mut()[y * width + columns.start..y * width + columns.end]
+                .fill(pixel);
+        }
+    }
+
+    /// Copies a rectangle of another image, at the same place in this one. Clipped to both.
+    pub fn copy_rect(&mut self, source: &Self, position: UVec2, dimensions: UVec2) {
+        let (columns, rows) = self.clip(position.as_ivec2(), dimensions);
+        let columns = columns.start..columns.end.min(source.width());
+        if columns.is_empty() {
+            return;
+        }
+        let width = self.width();
+        for y in rows.start..rows.end.min(source.height()) {
+            self.get_image_data_mut()[y * width + columns.start..y * width + columns.end]
+                .copy_from_slice(
+                    &source.get_image_data()
+                        [y * source.width() + columns.start..y * source.width() + columns.end],
+                );
+        }
+    }
+
+    /// The pixels of a rectangle, or None if it is not entirely inside the image.
+    pub fn crop(&self, position: UVec2, dimensions: UVec2) -> Option<Self> {
+        let inside = |start: u32, size: u32, max: usize| {
+            start
+                .checked_add(size)
+                .is_some_and(|end| end as usize <= max)
+        };
+        if !inside(position.x, dimensions.x, self.width())
+            || !inside(position.y, dimensions.y, self.height())
+        {
+            return None;
+        }
+        Some(
+            self.map_positions(dimensions.x as usize, dimensions.y as usize, |x, y| {
+                (x + position.x as usize, y + position.y as usize)
+            }),
+        )
+    }
+
+    /// Nearest neighbour : each pixel becomes a `factor` x `factor` square.
+    /// # Panics
+    /// If the factor is 0, or the result does not fit in u32 dimensions.
+    pub fn upscale(&self, factor: u32) -> Self {
+        assert!(factor > 0, "upscaling by 0");
+        let (width, height) = (
+            self.width() * factor as usize,
+            self.height() * factor as usize,
+        );
+        assert!(
+            width <= u32::MAX as usize && height <= u32::MAX as usize,
+            "{}x{} upscaled image does not fit in u32 dimensions",
+            width,
+            height
+        );
+        let factor = factor as usize;
+        self.map_positions(width, height, |x, y| (x / factor, y / factor))
+    }
+
+    /// Left to right.
+    pub fn flip_horizontal(&self) -> Self {
+        let width = self.width();
+        self.map_positions(width, self.height(), |x, y| (width - 1 - x, y))
+    }
+
+    /// Upside down.
+    pub fn flip_vertical(&self) -> Self {
+        let height = self.height();
+        self.map_positions(self.width(), height, |x, y| (x, height - 1 - y))
+    }
+
+    /// A quarter turn : the left column becomes the top row.
+    pub fn rotate_clockwise(&self) -> Self {
+        let height = self.height();
+        self.map_positions(height, self.width(), |x, y| (y, height - 1 - x))
+    }
+
+    /// A quarter turn : the top row becomes the left column.
+    pub fn rotate_counterclockwise(&self) -> Self {
+        let width = self.width();
+        self.map_positions(self.height(), width, |x, y| (width - 1 - y, x))
+    }
+
+    pub fn rotate_half(&self) -> Self {
+        let (width, height) = (self.width(), self.height());
+        self.map_positions(width, height, |x, y| (width - 1 - x, height - 1 - y))
+    }
+}
+
+/// `over` composited over `under`, both in straight alpha (Porter-Duff "over") :
+/// a transparent `under` takes the colour of `over`, whatever its own.
+/// `over` channels go from 0 to 1, ie. a tinted texel : the software backend draws with it too.
+pub(crate) fn blend(over: [f32; 4], under: RGBA8) -> RGBA8 {
+    let under: [u8; 4] = under.into();
+    let (over_alpha, under_alpha) = (over[3].clamp(0., 1.), under[3] as f32 / 255.);
+    let under_weight = under_alpha * (1. - over_alpha);
+    let alpha = over_alpha + under_weight;
+    if alpha == 0. {
+        return RGBA8::from([0; 4]);
+    }
+    let mix = |o: f32, u: u8| {
+        ((o.clamp(0., 1.) * over_alpha + u as f32 / 255. * under_weight) / alpha * 255.).round()
+            as u8
+    };
+    RGBA8::from([
+        mix(over[0], under[0]),
+        mix(over[1], under[1]),
+        mix(over[2], under[2]),
+        (alpha * 255.).round() as u8,
+    ])
+}
+
+impl RGBAImage {
+    /// Draws another image over this one, its top left corner at `position`, blended by its alpha
+    /// and clipped to this image.
+    pub fn blit(&mut self, source: &RGBAImage, position: IVec2) {
+        let dimensions = UVec2::new(source.width() as u32, source.height() as u32);
+        let (columns, rows) = self.clip(position, dimensions);
+        for y in rows {
+            for x in columns.clone() {
+                let (u, v) = (x as i64 - position.x as i64, y as i64 - position.y as i64);
+                let over: [u8; 4] = source.get_pixel(u as u32, v as u32).into();
+                let under = self.get_pixel(x as u32, y as u32);
+                let over = over.map(|c| c as f32 / 255.);
+                self.set_pixel(x as u32, y as u32, blend(over, under));
+            }
+        }
+    }
+
+    /// Averages each `factor` x `factor` square into a pixel. Colours are weighted by their alpha,
+    /// so that transparent pixels do not darken their neighbours.
+    /// The last rows and columns are dropped if the image size is not a multiple of the factor.
+    /// # Panics
+    /// If the factor is 0.
+    pub fn downscale(&self, factor: u32) -> Self {
+        assert!(factor > 0, "downscaling by 0");
+        let factor = factor as usize;
+        let (width, height) = (self.width() / factor, self.height() / factor);
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mut sums = [0u64; 4];
+                for v in y * factor..(y + 1) * factor {
+                    for u in x * factor..(x + 1) * factor {
+                        let p: [u8; 4] = self.get_pixel(u as u32, v as u32).into();
+                        let alpha = p[3] as u64;
+                        for c in 0..3 {
+                            sums[c] += p[c] as u64 * alpha;
+                        }
+                        sums[3] += alpha;
+                    }
+                }
+                let count = (factor * factor) as u64;
+                let color = |sum: u64| match sums[3] {
+                    0 => 0,
+                    alpha => ((sum + alpha / 2) / alpha) as u8,
+                };
+                RGBA8::from([
+                    color(sums[0]),
+                    color(sums[1]),
+                    color(sums[2]),
+                    ((sums[3] + count / 2) / count) as u8,
+                ])
+            })
+            .collect();
+        Self::from_pixels(width as u32, height as u32, pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::color::RGBA8;
+    use crate::graphics::image::{GrayscaleImage, RGBAImage};
+    use macroquad::math::{IVec2, UVec2};
+
+    /// 3x2 :
+    /// 1 2 3
+    /// 4 5 6
+    fn numbered() -> GrayscaleImage {
+        GrayscaleImage::from_pixels(3, 2, vec![1, 2, 3, 4, 5, 6])
+    }
+
+    fn rgba(r: u8, g: u8, b: u8, a: u8) -> RGBA8 {
+        RGBA8::from([r, g, b, a])
+    }
+
+    #[test]
+    fn check_fill_rect_is_clipped() {
+        let mut image = numbered();
+        image.fill_rect(IVec2::new(1, -1), UVec2::new(5, 2), 0);
+        assert_eq!(image.get_image_data(), &[1, 0, 0, 4, 5, 6]);
+
+        // entirely outside
+        image.fill_rect(IVec2::new(-5, 0), UVec2::new(5, 5), 9);
+        image.fill_rect(IVec2::new(3, 0), UVec2::new(5, 5), 9);
+        assert_eq!(image.get_image_data(), &[1, 0, 0, 4, 5, 6]);
+    }
+
+    #[test]
+    fn check_copy_rect() {
+        let mut image = GrayscaleImage::generate(3, 2, 0);
+        image.copy_rect(&numbered(), UVec2::new(1, 0), UVec2::new(5, 1));
+        assert_eq!(image.get_image_data(), &[0, 2, 3, 0, 0, 0]);
+
+        // clipped to the smaller source
+        image.copy_rect(
+            &GrayscaleImage::generate(2, 1, 9),
+            UVec2::ZERO,
+            UVec2::new(3, 2),
+        );
+        assert_eq!(image.get_image_data(), &[9, 9, 3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn check_crop_is_checked() {
+        let image = numbered();
+        let crop = image.crop(UVec2::new(1, 0), UVec2::new(2, 2)).unwrap();
+        assert_eq!((crop.width(), crop.height()), (2, 2));
+        assert_eq!(crop.get_image_data(), &[2, 3, 5, 6]);
+
+        assert_eq!(
+            image.crop(UVec2::ZERO, UVec2::new(3, 2)).as_ref(),
+            Some(&image)
+        );
+        assert!(image.crop(UVec2::new(2, 0), UVec2::new(2, 1)).is_none());
+        assert!(image.crop(UVec2::new(0, 2), UVec2::new(1, 1)).is_none());
+        assert!(image
+            .crop(UVec2::new(u32::MAX, 0), UVec2::new(2, 1))
+            .is_none());
+    }
+
+    #[test]
+    fn check_upscale() {
+        let image = numbered().upscale(2);
+        assert_eq!((image.width(), image.height()), (6, 4));
+        assert_eq!(
+            image.get_image_data(),
+            &[
+                1, 1, 2, 2, 3, 3, //
+                1, 1, 2, 2, 3, 3, //
+                4, 4, 5, 5, 6, 6, //
+                4, 4, 5, 5, 6, 6,
+            ]
+        );
+        assert_eq!(numbered().upscale(1), numbered());
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_upscale_overflow() {
+        GrayscaleImage::generate(2, 1, 0).upscale(u32::MAX);
+    }
+
+    #[test]
+    fn check_flips() {
+        assert_eq!(
+            numbered().flip_horizontal().get_image_data(),
+            &[3, 2, 1, 6, 5, 4]
+        );
+        assert_eq!(
+            numbered().flip_vertical().get_image_data(),
+            &[4, 5, 6, 1, 2, 3]
+        );
+    }
+
+    #[test]
+    fn check_rotations() {
+        let clockwise = numbered().rotate_clockwise();
+        assert_eq!((clockwise.width(), clockwise.height()), (2, 3));
+        assert_eq!(clockwise.get_image_data(), &[4, 1, 5, 2, 6, 3]);
+
+        let counterclockwise = numbered().rotate_counterclockwise();
+        assert_eq!(
+            (counterclockwise.width(), counterclockwise.height()),
+            (2, 3)
+        );
+        assert_eq!(counterclockwise.get_image_data(), &[3, 6, 2, 5, 1, 4]);
+
+        assert_eq!(
+            numbered().rotate_half().get_image_data(),
+            &[6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(clockwise.rotate_clockwise(), numbered().rotate_half());
+        assert_eq!(clockwise.rotate_counterclockwise(), numbered());
+    }
+
+    #[test]
+    fn check_blit_blends_and_clips() {
+        let mut image = RGBAImage::generate(3, 3, rgba(0, 0, 255, 255));
+        let mut overlay = RGBAImage::generate(2, 2, rgba(255, 0, 0, 255));
+        overlay.set_pixel(1, 0, rgba(255, 0, 0, 128));
+        overlay.set_pixel(0, 1, rgba(0, 0, 0, 0));
+
+        image.blit(&overlay, IVec2::new(2, -1));
+        // only the bottom row of the overlay is in, and its left pixel is transparent
+        assert_eq!(image.get_pixel(2, 0), rgba(0, 0, 255, 255));
+
+        image.blit(&overlay, IVec2::new(0, 0));
+        assert_eq!(image.get_pixel(0, 0), rgba(255, 0, 0, 255));
+        assert_eq!(image.get_pixel(1, 0), rgba(128, 0, 127, 255));
+        assert_eq!(image.get_pixel(0, 1), rgba(0, 0, 255, 255));
+        assert_eq!(image.get_pixel(1, 1), rgba(255, 0, 0, 255));
+        assert_eq!(image.get_pixel(2, 2), rgba(0, 0, 255, 255));
+
+        // over a transparent image, alpha adds up
+        let mut clear = RGBAImage::generate(1, 1, rgba(0, 0, 0, 0));
+        clear.blit(&RGBAImage::generate(1, 1, rgba(0, 0, 0, 128)), IVec2::ZERO);
+        clear.blit(&RGBAImage::generate(1, 1, rgba(0, 0, 0, 128)), IVec2::ZERO);
+        assert_eq!(clear.get_pixel(0, 0), rgba(0, 0, 0, 192));
+
+        // and colours are not darkened by it
+        let mut clear = RGBAImage::generate(1, 1, rgba(0, 0, 0, 0));
+        clear.blit(
+            &RGBAImage::generate(1, 1, rgba(255, 0, 0, 128)),
+            IVec2::ZERO,
+        );
+        assert_eq!(clear.get_pixel(0, 0), rgba(255, 0, 0, 128));
+        clear.blit(
+            &RGBAImage::generate(1, 1, rgba(0, 0, 255, 128)),
+            IVec2::ZERO,
+        );
+        assert_eq!(clear.get_pixel(0, 0), rgba(85, 0, 170, 192));
+    }
+
+    #[test]
+    fn check_downscale() {
+        let mut image = RGBAImage::generate(5, 2, rgba(0, 0, 0, 255));
+        image.set_pixel(0, 0, rgba(255, 255, 255, 255));
+        image.set_pixel(1, 1, rgba(255, 255, 255, 255));
+        // transparent pixels do not count towards the colour
+        image.set_pixel(2, 0, rgba(255, 0, 0, 255));
+        image.fill_rect(IVec2::new(3, 0), UVec2::new(1, 2), rgba(0, 0, 0, 0));
+
+        let small = image.downscale(2);
+
+        // the last column is dropped
+        assert_eq!((small.width(), small.height()), (2, 1));
+        assert_eq!(small.get_pixel(0, 0), rgba(128, 128, 128, 255));
+        assert_eq!(small.get_pixel(1, 0), rgba(128, 0, 0, 128));
+
+        assert_eq!(image.downscale(1), image);
+        assert_eq!(image.upscale(3).downscale(3), image);
+    }
+}
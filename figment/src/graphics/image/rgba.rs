@@ -1,89 +1,129 @@
 use crate::graphics::color::RGBA8;
-use crate::graphics::image::Image;
-use macroquad::math::Rect;
+use crate::graphics::image::{RGBAImage, TooLarge};
+use std::mem::{align_of, size_of, ManuallyDrop};
 
-pub(crate) struct RGBAImage {
-    pub bytes: Vec<RGBA8>,
-    pub width: u16,
-    pub height: u16,
-}
-
-impl Image<u8, 4> for RGBAImage {
-    type P = RGBA8;
+// What makes reinterpreting bytes as pixels, and back, sound.
+const _: () = assert!(size_of::<RGBA8>() == 4 && align_of::<RGBA8>() == 1);
 
-    fn empty() -> Self {
-        Self {
-            width: 0,
-            height: 0,
-            bytes: vec![],
-        }
+impl RGBAImage {
+    /// The pixels as r, g, b, a bytes, ie. to upload or encode them.
+    pub fn as_bytes(&self) -> &[u8] {
+        let pixels = self.get_image_data();
+        // SAFETY : RGBA8 is a repr(C) [u8; 4], see above.
+        unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const u8, pixels.len() * 4) }
     }
 
-    fn generate(width: u16, height: u16, pixel: Self::P) -> Self {
-        let bytes: Vec<Self::P> = vec![pixel; width as usize * height as usize];
-        Self {
-            width,
-            height,
-            bytes,
-        }
+    /// # Panics
+    /// If there are not exactly `width * height * 4` bytes.
+    pub fn from_bytes(width: u32, height: u32, bytes: Vec<u8>) -> Self {
+        assert_eq!(width as usize * height as usize * 4, bytes.len());
+        Self::from_pixels(width, height, bytes_to_pixels(bytes))
     }
 
-    fn update(&mut self, pixel_slice: &[Self::P]) {
-        assert_eq!(
-            self.width as usize * self.height as usize,
-            pixel_slice.len()
-        );
-
-        for i in 0..pixel_slice.len() {
-            self.bytes[i] = pixel_slice[i];
+    pub fn into_bytes(self) -> Vec<u8> {
+        let mut pixels = ManuallyDrop::new(self.into_pixels());
+        // SAFETY : same allocation, its size and alignment unchanged.
+        unsafe {
+            Vec::from_raw_parts(
+                pixels.as_mut_ptr() as *mut u8,
+                pixels.len() * 4,
+                pixels.capacity() * 4,
+            )
         }
     }
+}
 
-    fn width(&self) -> usize {
-        self.width as usize
+/// Reuses the allocation, unless its capacity is not a whole number of pixels.
+fn bytes_to_pixels(mut bytes: Vec<u8>) -> Vec<RGBA8> {
+    if !bytes.capacity().is_multiple_of(4) {
+        bytes.shrink_to_fit();
     }
-
-    fn height(&self) -> usize {
-        self.height as usize
+    if !bytes.capacity().is_multiple_of(4) {
+        return bytes
+            .chunks_exact(4)
+            .map(|c| RGBA8::from([c[0], c[1], c[2], c[3]]))
+            .collect();
     }
+    let mut bytes = ManuallyDrop::new(bytes);
+    // SAFETY : same allocation, its size and alignment unchanged, and any 4 bytes are a valid RGBA8.
+    unsafe {
+        Vec::from_raw_parts(
+            bytes.as_mut_ptr() as *mut RGBA8,
+            bytes.len() / 4,
+            bytes.capacity() / 4,
+        )
+    }
+}
 
-    fn get_image_data(&self) -> &[Self::P] {
-        self.bytes.as_slice()
+/// Without copying the pixels.
+impl From<macroquad::prelude::Image> for RGBAImage {
+    fn from(image: macroquad::prelude::Image) -> Self {
+        Self::from_bytes(image.width.into(), image.height.into(), image.bytes)
     }
+}
 
-    fn get_image_data_mut(&mut self) -> &mut [Self::P] {
-        self.bytes.as_mut_slice()
+/// Without copying the pixels. Macroquad images are limited to u16 dimensions.
+impl TryFrom<RGBAImage> for macroquad::prelude::Image {
+    type Error = TooLarge;
+
+    fn try_from(image: RGBAImage) -> Result<Self, TooLarge> {
+        let (width, height) = image.u16_dimensions()?;
+        Ok(Self {
+            bytes: image.into_bytes(),
+            width,
+            height,
+        })
     }
-    fn set_pixel(&mut self, x: u32, y: u32, pixel: Self::P) {
-        let width = self.width;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::color::RGBA8;
+    use crate::graphics::image::{RGBAImage, TooLarge};
+    use macroquad::color::{BLACK, WHITE};
+
+    #[test]
+    fn check_macroquad_conversions_do_not_copy() {
+        let mut image = macroquad::prelude::Image::gen_image_color(3, 2, WHITE);
+        image.set_pixel(1, 1, BLACK);
+        let address = image.bytes.as_ptr();
+
+        let figment = RGBAImage::from(image);
+        assert_eq!(figment.get_image_data().as_ptr() as *const u8, address);
+        assert_eq!((figment.width(), figment.height()), (3, 2));
+        assert_eq!(figment.get_pixel(1, 1), RGBA8::from(BLACK));
+        assert_eq!(figment.get_pixel(2, 1), RGBA8::from(WHITE));
 
-        self.get_image_data_mut()[(y * width as u32 + x) as usize] = pixel;
+        let back = macroquad::prelude::Image::try_from(figment).unwrap();
+        assert_eq!(back.bytes.as_ptr(), address);
+        assert_eq!((back.width, back.height), (3, 2));
+        assert_eq!(back.get_pixel(1, 1), BLACK);
     }
 
-    fn get_pixel(&self, x: u32, y: u32) -> Self::P {
-        self.get_image_data()[(y * self.width as u32 + x) as usize]
+    #[test]
+    fn check_too_large_for_macroquad() {
+        let image = RGBAImage::generate(70_000, 1, RGBA8::default());
+        assert_eq!(
+            macroquad::prelude::Image::try_from(image).map(|_| ()),
+            Err(TooLarge {
+                width: 70_000,
+                height: 1
+            })
+        );
     }
 
-    fn sub_image(&self, rect: Rect) -> Self {
-        let width = rect.w as usize;
-        let height = rect.h as usize;
-        let mut bytes: Vec<Self::P> = vec![Self::P::from([0u8; 4]); width * height];
+    #[test]
+    fn check_bytes() {
+        let image = RGBAImage::from_bytes(2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(image.get_pixel(1, 0), [5u8, 6, 7, 8]);
+        assert_eq!(image.as_bytes(), &[1, 2, 3, 4, 5, 6, 7, 8]);
 
-        let x = rect.x as usize;
-        let y = rect.y as usize;
-        let mut n = 0;
-        for y in y..y + height {
-            for x in x..x + width {
-                bytes[n] = self.bytes[y * self.width as usize + x];
-                // bytes[n + 1] = self.bytes[y * self.width as usize * 3 + x * 3 + 1];
-                // bytes[n + 2] = self.bytes[y * self.width as usize * 3 + x * 3 + 2];
-                n += 1;
-            }
-        }
-        Self {
-            width: width as u16,
-            height: height as u16,
-            bytes,
-        }
+        // an allocation of an odd capacity
+        let mut bytes = Vec::with_capacity(9);
+        bytes.extend_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(
+            RGBAImage::from_bytes(1, 1, bytes).into_bytes(),
+            vec![1, 2, 3, 4]
+        );
     }
 }
@@ -1,11 +1,19 @@
-use macroquad::prelude::{Color, IVec2, Image, UVec2};
+use crate::graphics::backend::Backend;
+use crate::graphics::dirty::DirtyRect;
+use crate::graphics::image::RGBAImage;
+use macroquad::prelude::{Color, IVec2, UVec2};
 
 pub trait Drawable {
-    fn draw(&self, position_in_screen: IVec2);
+    fn draw(&self, backend: &mut dyn Backend, position_in_screen: IVec2);
 }
 
 pub trait Updatable {
-    fn update(&mut self, image: &Image);
+    fn update(&mut self, image: &RGBAImage);
+
+    /// When only some parts of the image changed since the previous update.
+    fn update_rects(&mut self, image: &RGBAImage, _changed: &[DirtyRect]) {
+        self.update(image);
+    }
 }
 
 pub(crate) trait Quad {
@@ -1,8 +1,9 @@
+use crate::graphics::backend::Backend;
 use crate::graphics::quad::{Drawable, Quad};
 use macroquad::math::{IVec2, UVec2};
-use macroquad::prelude::{draw_rectangle, Color};
+use macroquad::prelude::Color;
 
-struct Rect {
+pub struct Rect {
     color: Color, // TODO : This ias a quad (dont mix up with macroquad Rect...)
     dimensions: UVec2,
 }
@@ -15,14 +16,8 @@ impl Rect {
 }
 
 impl Drawable for Rect {
-    fn draw(&self, position: IVec2) {
-        draw_rectangle(
-            position[0] as f32,
-            position[1] as f32,
-            self.dimensions[0] as f32,
-            self.dimensions[1] as f32,
-            self.color,
-        );
+    fn draw(&self, backend: &mut dyn Backend, position: IVec2) {
+        backend.draw_rect(position, self.dimensions, self.color);
     }
 }
 
@@ -0,0 +1,360 @@
+use crate::graphics::backend::Backend;
+use crate::graphics::quad::{Drawable, Placed};
+use macroquad::math::IVec2;
+use std::any::Any;
+use std::ops::{AddAssign, Index, IndexMut};
+use std::time::Duration;
+
+/// A drawable we can get back with its own type, ie. to update it.
+trait SceneDrawable: Drawable {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<D: Drawable + Any> SceneDrawable for D {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Called on each `Scene::update`, with the time elapsed since the previous one.
+pub type UpdateHook = Box<dyn FnMut(&mut Node, Duration)>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(usize);
+
+/// An element of a scene : a drawable (or none, to group other nodes), placed relative to its parent.
+pub struct Node {
+    drawable: Option<Box<dyn SceneDrawable>>,
+    position: IVec2,
+    z: i32,
+    visible: bool,
+    update: Option<UpdateHook>,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+}
+
+impl Node {
+    fn new(parent: Option<NodeId>, drawable: Option<Box<dyn SceneDrawable>>) -> Self {
+        Self {
+            drawable,
+            position: IVec2::ZERO,
+            z: 0,
+            visible: true,
+            update: None,
+            parent,
+            children: vec![],
+        }
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    /// In insertion order.
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+
+    /// The drawable of this node, if it is a `D`.
+    pub fn drawable<D: Drawable + Any>(&self) -> Option<&D> {
+        self.drawable.as_ref()?.as_any().downcast_ref()
+    }
+
+    pub fn drawable_mut<D: Drawable + Any>(&mut self) -> Option<&mut D> {
+        self.drawable.as_mut()?.as_any_mut().downcast_mut()
+    }
+
+    pub fn set_drawable(&mut self, drawable: impl Drawable + Any) {
+        self.drawable = Some(Box::new(drawable));
+    }
+
+    pub fn z(&self) -> i32 {
+        self.z
+    }
+
+    /// Nodes of higher z are drawn over the others, whatever their depth in the scene.
+    pub fn set_z(&mut self, z: i32) {
+        self.z = z;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Hiding a node hides its children too.
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    /// Replaces the update hook of this node.
+    pub fn on_update(&mut self, hook: impl FnMut(&mut Node, Duration) + 'static) {
+        self.update = Some(Box::new(hook));
+    }
+}
+
+/// Relative to the parent node.
+impl Placed for Node {
+    fn get_position(&self) -> IVec2 {
+        self.position
+    }
+
+    fn set_position(&mut self, position: IVec2) {
+        self.position = position;
+    }
+
+    fn translate(&mut self, displacement: IVec2) {
+        self.position.add_assign(displacement);
+    }
+}
+
+/// A tree of placed drawables, drawn as one.
+/// Nodes are drawn by increasing z, and in tree order for the same z : a parent before its children,
+/// siblings in insertion order.
+pub struct Scene {
+    /// Indexed by node id, None once removed.
+    nodes: Vec<Option<Node>>,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Some(Node::new(None, None))],
+        }
+    }
+}
+
+impl Scene {
+    /// The node every other one descends from, drawn where the scene is.
+    pub fn root(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    fn insert(&mut self, parent: NodeId, drawable: Option<Box<dyn SceneDrawable>>) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        self[parent].children.push(id);
+        self.nodes.push(Some(Node::new(Some(parent), drawable)));
+        id
+    }
+
+    /// Adds a drawable, at the position of its parent.
+    /// # Panics
+    /// If the parent is not in the scene.
+    pub fn add(&mut self, parent: NodeId, drawable: impl Drawable + Any) -> NodeId {
+        self.insert(parent, Some(Box::new(drawable)))
+    }
+
+    /// Adds a node without drawable, to place, order or hide other nodes together.
+    pub fn group(&mut self, parent: NodeId) -> NodeId {
+        self.insert(parent, None)
+    }
+
+    /// Removes a node, and its children.
+    /// # Panics
+    /// If the node is the root.
+    pub fn remove(&mut self, id: NodeId) {
+        assert_ne!(id, self.root(), "removing the root of a scene");
+        let Some(node) = self.nodes.get_mut(id.0).and_then(Option::take) else {
+            return;
+        };
+        if let Some(parent) = node.parent.and_then(|p| self.node_mut(p)) {
+            parent.children.retain(|c| *c != id);
+        }
+        for child in node.children {
+            self.remove(child);
+        }
+    }
+
+    pub fn node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes.get(id.0)?.as_ref()
+    }
+
+    pub fn node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes.get_mut(id.0)?.as_mut()
+    }
+
+    /// Position of a node relative to the scene, its parents' positions added up.
+    pub fn absolute_position(&self, id: NodeId) -> IVec2 {
+        let mut position = IVec2::ZERO;
+        let mut current = self.node(id);
+        while let Some(node) = current {
+            position += node.position;
+            current = node.parent.and_then(|p| self.node(p));
+        }
+        position
+    }
+
+    /// Runs the update hooks of all nodes, in tree order, hidden ones included.
+    pub fn update(&mut self, elapsed: Duration) {
+        for id in self.tree_order() {
+            let Some(node) = self.node_mut(id) else {
+                continue;
+            };
+            if let Some(mut hook) = node.update.take() {
+                hook(node, elapsed);
+                // unless the hook replaced itself
+                node.update.get_or_insert(hook);
+            }
+        }
+    }
+
+    /// Ids of all nodes, a parent before its children.
+    fn tree_order(&self) -> Vec<NodeId> {
+        let mut order = vec![];
+        let mut stack = vec![self.root()];
+        while let Some(id) = stack.pop() {
+            if let Some(node) = self.node(id) {
+                order.push(id);
+                stack.extend(node.children.iter().rev());
+            }
+        }
+        order
+    }
+
+    /// Visible nodes with a drawable, in draw order, with their position when the scene is drawn at `origin`.
+    pub fn layout(&self, origin: IVec2) -> Vec<(NodeId, IVec2)> {
+        let mut drawn = vec![];
+        let mut stack = vec![(self.root(), origin)];
+        while let Some((id, parent_position)) = stack.pop() {
+            let Some(node) = self.node(id).filter(|n| n.visible) else {
+                continue;
+            };
+            let position = parent_position + node.position;
+            if node.drawable.is_some() {
+                drawn.push((node.z, drawn.len(), id, position));
+            }
+            stack.extend(node.children.iter().rev().map(|c| (*c, position)));
+        }
+        // stable : tree order for the same z
+        drawn.sort_by_key(|(z, order, _, _)| (*z, *order));
+        drawn
+            .into_iter()
+            .map(|(_, _, id, position)| (id, position))
+            .collect()
+    }
+}
+
+impl Index<NodeId> for Scene {
+    type Output = Node;
+
+    fn index(&self, id: NodeId) -> &Node {
+        self.node(id).expect("no such node in the scene")
+    }
+}
+
+impl IndexMut<NodeId> for Scene {
+    fn index_mut(&mut self, id: NodeId) -> &mut Node {
+        self.node_mut(id).expect("no such node in the scene")
+    }
+}
+
+impl Drawable for Scene {
+    fn draw(&self, backend: &mut dyn Backend, position_in_screen: IVec2) {
+        for (id, position) in self.layout(position_in_screen) {
+            if let Some(drawable) = &self[id].drawable {
+                drawable.draw(backend, position);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::backend::Backend;
+    use crate::graphics::quad::{Drawable, Placed};
+    use crate::graphics::scene::Scene;
+    use macroquad::math::IVec2;
+    use std::time::Duration;
+
+    /// Draws nothing : scenes are tested without a window.
+    #[derive(Debug, PartialEq)]
+    struct Dot(u32);
+
+    impl Drawable for Dot {
+        fn draw(&self, _backend: &mut dyn Backend, _position_in_screen: IVec2) {}
+    }
+
+    #[test]
+    fn check_relative_positions() {
+        let mut scene = Scene::default();
+        let group = scene.group(scene.root());
+        scene[group].set_position(IVec2::new(10, 20));
+        let a = scene.add(group, Dot(1));
+        scene[a].set_position(IVec2::new(1, 2));
+        let b = scene.add(a, Dot(2));
+        scene[b].translate(IVec2::new(-5, 0));
+
+        assert_eq!(scene.absolute_position(b), IVec2::new(6, 22));
+        assert_eq!(
+            scene.layout(IVec2::new(100, 100)),
+            vec![(a, IVec2::new(111, 122)), (b, IVec2::new(106, 122))]
+        );
+
+        // children follow their parent
+        scene[group].translate(IVec2::new(1, 1));
+        assert_eq!(scene.absolute_position(b), IVec2::new(7, 23));
+    }
+
+    #[test]
+    fn check_z_order() {
+        let mut scene = Scene::default();
+        let background = scene.add(scene.root(), Dot(0));
+        let board = scene.add(scene.root(), Dot(1));
+        let cursor = scene.add(board, Dot(2));
+        let hud = scene.add(scene.root(), Dot(3));
+
+        let order = |scene: &Scene| -> Vec<_> {
+            scene
+                .layout(IVec2::ZERO)
+                .into_iter()
+                .map(|(id, _)| id)
+                .collect()
+        };
+        // tree order
+        assert_eq!(order(&scene), vec![background, board, cursor, hud]);
+
+        scene[cursor].set_z(2);
+        scene[hud].set_z(1);
+        scene[background].set_z(-1);
+        assert_eq!(order(&scene), vec![background, board, hud, cursor]);
+    }
+
+    #[test]
+    fn check_visibility_and_removal() {
+        let mut scene = Scene::default();
+        let a = scene.add(scene.root(), Dot(1));
+        let b = scene.add(a, Dot(2));
+        let c = scene.add(scene.root(), Dot(3));
+
+        scene[a].set_visible(false);
+        assert_eq!(scene.layout(IVec2::ZERO), vec![(c, IVec2::ZERO)]);
+
+        scene[a].set_visible(true);
+        scene.remove(a);
+        assert!(scene.node(a).is_none());
+        assert!(scene.node(b).is_none());
+        assert_eq!(scene[scene.root()].children(), &[c]);
+        assert_eq!(scene.layout(IVec2::ZERO), vec![(c, IVec2::ZERO)]);
+    }
+
+    #[test]
+    fn check_update_hooks() {
+        let mut scene = Scene::default();
+        let dot = scene.add(scene.root(), Dot(0));
+        scene[dot].on_update(|node, elapsed| {
+            node.translate(IVec2::new(elapsed.as_millis() as i32, 0));
+            node.drawable_mut::<Dot>().unwrap().0 += 1;
+        });
+
+        scene.update(Duration::from_millis(10));
+        scene.update(Duration::from_millis(5));
+
+        assert_eq!(scene[dot].get_position(), IVec2::new(15, 0));
+        assert_eq!(scene[dot].drawable::<Dot>(), Some(&Dot(2)));
+        assert!(scene[dot].drawable::<Scene>().is_none());
+    }
+}
@@ -1,15 +1,19 @@
+use crate::graphics::backend::Backend;
+use crate::graphics::dirty::DirtyRect;
+use crate::graphics::image::RGBAImage;
 use crate::graphics::quad::{Drawable, Placed, Quad, Updatable};
+use crate::graphics::texture::Texture;
 use delegate::delegate;
 use macroquad::color::YELLOW;
 use macroquad::math::IVec2;
-use macroquad::prelude::{draw_rectangle, draw_texture, Color, Image, Texture2D, UVec2};
+use macroquad::prelude::{Color, UVec2};
 use std::ops::AddAssign;
 
 #[derive(Default)]
 pub struct Sprite {
     color: Color,
     dimensions: UVec2,
-    texture: Option<Texture2D>,
+    texture: Option<Texture>,
 }
 //TODO : a Vec of drawables instead ??
 
@@ -25,53 +29,42 @@ impl Sprite {
         }
     }
 
-    fn new(texture2d: Texture2D) -> Self {
+    fn new(texture: Texture) -> Self {
         Self {
-            dimensions: UVec2::new(texture2d.width() as u32, texture2d.height() as u32),
-            texture: Some(texture2d),
+            dimensions: texture.get_dimensions(),
+            texture: Some(texture),
             ..Self::default()
         }
     }
 
-    pub fn from_image(image: &Image) -> Self {
-        let texture = Texture2D::from_image(image);
-
-        Self {
-            dimensions: UVec2::new(image.width() as u32, image.height() as u32),
-            texture: Some(texture),
-            ..Self::default()
-        }
+    /// No GPU is needed until it is drawn on screen. Pixels stay sharp when zoomed in.
+    pub fn from_image(image: &RGBAImage) -> Self {
+        Self::new(Texture::new(image, DEFAULT_COLOR))
     }
 }
 
 impl Drawable for Sprite {
-    fn draw(&self, position: IVec2) {
-        if self.texture.is_some() {
-            draw_texture(
-                &self.texture.as_ref().unwrap(),
-                position[0] as f32,
-                position[1] as f32,
-                self.color,
-            );
-        } else {
-            draw_rectangle(
-                position[0] as f32,
-                position[1] as f32,
-                self.dimensions[0] as f32,
-                self.dimensions[1] as f32,
-                self.color,
-            );
+    fn draw(&self, backend: &mut dyn Backend, position: IVec2) {
+        match &self.texture {
+            Some(texture) => backend.draw_texture(texture, position, self.color),
+            None => backend.draw_rect(position, self.dimensions, self.color),
         }
     }
 }
 
 impl Updatable for Sprite {
-    fn update(&mut self, image: &Image) {
-        if self.texture.is_none() {
+    fn update(&mut self, image: &RGBAImage) {
+        match &mut self.texture {
             //we intentionally do not modify dimensions
-            self.texture = Some(Texture2D::from_image(&image));
-        } else {
-            self.texture.as_mut().unwrap().update(image);
+            None => self.texture = Some(Texture::new(image, self.color)),
+            Some(texture) => texture.update(image),
+        }
+    }
+
+    fn update_rects(&mut self, image: &RGBAImage, changed: &[DirtyRect]) {
+        match &mut self.texture {
+            None => self.update(image),
+            Some(texture) => texture.update_rects(image, changed),
         }
     }
 }
@@ -145,7 +138,7 @@ impl Drawable for PlacedSprite {
     delegate! {
         to self.sprite {
 
-    fn draw(&self, position_in_screen: IVec2);
+    fn draw(&self, backend: &mut dyn Backend, position_in_screen: IVec2);
         }
     }
 }
@@ -154,7 +147,8 @@ impl Updatable for PlacedSprite {
     delegate! {
         to self.sprite {
 
-    fn update(&mut self, image: &Image);
+    fn update(&mut self, image: &RGBAImage);
+    fn update_rects(&mut self, image: &RGBAImage, changed: &[DirtyRect]);
         }
     }
 }
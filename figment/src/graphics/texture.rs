@@ -1,39 +1,91 @@
-use crate::graphics::quad::{Drawable, Quad};
+use crate::graphics::backend::Backend;
+use crate::graphics::dirty::{DirtyRect, DirtyRects};
+use crate::graphics::image::RGBAImage;
+use crate::graphics::quad::{Drawable, Quad, Updatable};
 use macroquad::math::{IVec2, UVec2};
-use macroquad::prelude::{draw_texture, Color, FilterMode, Image, Texture2D};
+use macroquad::prelude::{Color, Texture2D};
+use std::cell::RefCell;
 
-struct Texture {
+/// Pixels kept on the CPU, and uploaded to the GPU by the backends that need it, when first drawn.
+pub struct Texture {
     color: Color,
-    texture: Texture2D,
+    pub(crate) image: RGBAImage,
+    /// Uploaded by `backend::Macroquad`.
+    pub(crate) gpu: RefCell<Option<Texture2D>>,
+    /// What changed since it was uploaded.
+    pub(crate) dirty: RefCell<DirtyRects>,
 }
 
 impl Texture {
     /// We always require an explicit background color, to help with debugging transparency issues.
-    pub fn new(image: &Image, background: Color) -> Self {
-        let t = Texture2D::from_image(image);
-        t.set_filter(FilterMode::Nearest);
-
+    pub fn new(image: &RGBAImage, background: Color) -> Self {
         Self {
             color: background,
-            texture: t,
+            image: image.clone(),
+            gpu: RefCell::new(None),
+            dirty: RefCell::new(DirtyRects::default()),
         }
     }
+
+    pub fn image(&self) -> &RGBAImage {
+        &self.image
+    }
+
+    /// For changes in place, inside `changed` : only that part is uploaded again.
+    pub(crate) fn pixels_mut(&mut self, changed: DirtyRect) -> &mut RGBAImage {
+        self.dirty.get_mut().add(changed);
+        &mut self.image
+    }
+
+    /// The parts changed since the last call, ie. to upload them.
+    pub(crate) fn take_dirty(&self) -> Vec<DirtyRect> {
+        self.dirty.borrow_mut().take()
+    }
+
+    fn is_sized_as(&self, image: &RGBAImage) -> bool {
+        (image.width(), image.height()) == (self.image.width(), self.image.height())
+    }
 }
 
 impl Drawable for Texture {
-    fn draw(&self, position: IVec2) {
-        draw_texture(
-            &self.texture,
-            position[0] as f32,
-            position[1] as f32,
-            self.color,
-        );
+    fn draw(&self, backend: &mut dyn Backend, position: IVec2) {
+        backend.draw_texture(self, position, self.color);
+    }
+}
+
+impl Updatable for Texture {
+    /// An image of another size replaces the texture.
+    fn update(&mut self, image: &RGBAImage) {
+        if self.is_sized_as(image) {
+            self.image.update(image.get_image_data());
+            let (width, height) = (image.width() as u32, image.height() as u32);
+            self.dirty
+                .get_mut()
+                .add(DirtyRect::new(0, 0, width, height));
+        } else {
+            *self = Self::new(image, self.color);
+        }
+    }
+
+    fn update_rects(&mut self, image: &RGBAImage, changed: &[DirtyRect]) {
+        if !self.is_sized_as(image) {
+            return self.update(image);
+        }
+        let (width, height) = (image.width() as u32, image.height() as u32);
+        for rect in changed.iter().filter_map(|r| r.clip(width, height)) {
+            self.image.copy_rect(
+                image,
+                UVec2::new(rect.x, rect.y),
+                UVec2::new(rect.width, rect.height),
+            );
+            self.dirty.get_mut().add(rect);
+        }
     }
 }
 
 impl Quad for Texture {
     fn get_dimensions(&self) -> UVec2 {
-        UVec2::new(self.texture.width() as u32, self.texture.height() as u32)
+        UVec2::new(self.image.width() as u32, self.image.height() as u32)
     }
 
     fn get_background(&self) -> Color {
@@ -54,3 +106,39 @@ impl Quad for Texture {
         //todo()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::color::RGBA8;
+    use crate::graphics::dirty::DirtyRect;
+    use crate::graphics::image::RGBAImage;
+    use crate::graphics::quad::Updatable;
+    use crate::graphics::texture::Texture;
+    use macroquad::color::{BLACK, WHITE};
+
+    #[test]
+    fn check_updates_are_tracked() {
+        let mut texture = Texture::new(&RGBAImage::generate(4, 3, WHITE.into()), WHITE);
+        // uploaded whole when first drawn
+        assert!(texture.take_dirty().is_empty());
+
+        let mut image = RGBAImage::generate(4, 3, WHITE.into());
+        image.fill_rect((1, 1).into(), (2, 1).into(), BLACK.into());
+        texture.update_rects(
+            &image,
+            &[DirtyRect::new(1, 1, 2, 1), DirtyRect::new(3, 2, 5, 5)],
+        );
+
+        assert_eq!(texture.image(), &image);
+        // clipped, and merged as they touch
+        assert_eq!(texture.take_dirty(), vec![DirtyRect::new(1, 1, 3, 2)]);
+
+        texture.update(&image);
+        assert_eq!(texture.take_dirty(), vec![DirtyRect::new(0, 0, 4, 3)]);
+
+        // resized : uploaded anew
+        texture.update_rects(&RGBAImage::generate(2, 2, RGBA8::default()), &[]);
+        assert_eq!(texture.image().width(), 2);
+        assert!(texture.take_dirty().is_empty());
+    }
+}
@@ -0,0 +1,351 @@
+use crate::graphics::backend::Backend;
+use crate::graphics::color::RGBA8;
+use crate::graphics::dirty::DirtyRect;
+use crate::graphics::image::RGBAImage;
+use crate::graphics::quad::{Drawable, Updatable};
+use crate::graphics::texture::Texture;
+use macroquad::color::{Color, WHITE};
+use macroquad::math::{IVec2, UVec2};
+
+/// Small enough for any GPU, big enough to keep draw calls few.
+pub const DEFAULT_TILE_SIZE: u16 = 512;
+
+/// An image of any size, split in square tiles, each a texture of its own, and drawn as one.
+/// Only the tiles an update touches are uploaded again.
+pub struct TiledTexture {
+    width: u32,
+    height: u32,
+    tile_size: u16,
+    /// Pixels are multiplied by it when drawn.
+    tint: Color,
+    /// Tiles row by row. Those on the right and bottom edges may be smaller.
+    tiles: Vec<Texture>,
+}
+
+impl TiledTexture {
+    pub fn new(width: u32, height: u32, pixel: RGBA8) -> Self {
+        Self::tiled(width, height, DEFAULT_TILE_SIZE, pixel)
+    }
+
+    pub fn from_image(image: &RGBAImage) -> Self {
+        let mut tiled = Self::new(
+            image.width() as u32,
+            image.height() as u32,
+            RGBA8::default(),
+        );
+        tiled.update_rect(UVec2::ZERO, image);
+        tiled.not_uploaded()
+    }
+
+    fn tiled(width: u32, height: u32, tile_size: u16, pixel: RGBA8) -> Self {
+        assert!(tile_size > 0, "tiles of size 0");
+        let size = tile_size as u32;
+        let tiles = (0..height.div_ceil(size))
+            .flat_map(|row| (0..width.div_ceil(size)).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let tile = RGBAImage::generate(
+                    (width - column * size).min(size),
+                    (height - row * size).min(size),
+                    pixel,
+                );
+                Texture::new(&tile, WHITE)
+            })
+            .collect();
+        Self {
+            width,
+            height,
+            tile_size,
+            tint: WHITE,
+            tiles,
+        }
+    }
+
+    /// Re-tiles the image, its pixels kept.
+    /// # Panics
+    /// If the tile size is 0.
+    pub fn with_tile_size(self, tile_size: u16) -> Self {
+        let mut tiled =
+            Self::tiled(self.width, self.height, tile_size, RGBA8::default()).with_tint(self.tint);
+        for (i, tile) in self.tiles.iter().enumerate() {
+            tiled.update_rect(self.tile_origin(i), tile.image());
+        }
+        tiled.not_uploaded()
+    }
+
+    /// New tiles are uploaded when first drawn, not as changed.
+    fn not_uploaded(self) -> Self {
+        for tile in &self.tiles {
+            tile.take_dirty();
+        }
+        self
+    }
+
+    pub fn with_tint(self, tint: Color) -> Self {
+        Self { tint, ..self }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn tile_size(&self) -> u16 {
+        self.tile_size
+    }
+
+    pub fn tiles(&self) -> &[Texture] {
+        &self.tiles
+    }
+
+    fn columns(&self) -> u32 {
+        self.width.div_ceil(self.tile_size as u32)
+    }
+
+    /// Top left corner of a tile, in the whole image.
+    fn tile_origin(&self, index: usize) -> UVec2 {
+        let columns = self.columns() as usize;
+        UVec2::new((index % columns) as u32, (index / columns) as u32) * self.tile_size as u32
+    }
+
+    /// The tile a pixel is in, and its position in that tile.
+    fn locate(&self, x: u32, y: u32) -> (usize, u32, u32) {
+        assert!(
+            x < self.width && y < self.height,
+            "pixel ({}, {}) outside a {}x{} image",
+            x,
+            y,
+            self.width,
+            self.height
+        );
+        let size = self.tile_size as u32;
+        let index = (y / size * self.columns() + x / size) as usize;
+        (index, x % size, y % size)
+    }
+
+    pub fn get_pixel(&self, x: u32, y: u32) -> RGBA8 {
+        let (index, u, v) = self.locate(x, y);
+        self.tiles[index].image().get_pixel(u, v)
+    }
+
+    pub fn set_pixel(&mut self, x: u32, y: u32, pixel: RGBA8) {
+        let (index, u, v) = self.locate(x, y);
+        self.tiles[index]
+            .pixels_mut(DirtyRect::pixel(u, v))
+            .set_pixel(u, v, pixel);
+    }
+
+    /// Copies an image in, its top left corner at `position`, clipped to this image.
+    /// Returns how many tiles were touched.
+    pub fn update_rect(&mut self, position: UVec2, image: &RGBAImage) -> usize {
+        let end = (position + UVec2::new(image.width() as u32, image.height() as u32))
+            .min(UVec2::new(self.width, self.height));
+        if position.x >= end.x || position.y >= end.y {
+            return 0;
+        }
+
+        let size = self.tile_size as u32;
+        let columns = self.columns();
+        let mut touched = 0;
+        for row in position.y / size..end.y.div_ceil(size) {
+            for column in position.x / size..end.x.div_ceil(size) {
+                let origin = UVec2::new(column, row) * size;
+                // the overlap, in the whole image
+                let from = position.max(origin);
+                let to = end.min(origin + size);
+                let changed = DirtyRect::new(
+                    from.x - origin.x,
+                    from.y - origin.y,
+                    to.x - from.x,
+                    to.y - from.y,
+                );
+                let tile = self.tiles[(row * columns + column) as usize].pixels_mut(changed);
+                for y in from.y..to.y {
+                    let source = (y - position.y) as usize * image.width();
+                    let target = (y - origin.y) as usize * tile.width();
+                    let (source_x, target_x) =
+                        ((from.x - position.x) as usize, (from.x - origin.x) as usize);
+                    let length = (to.x - from.x) as usize;
+                    tile.get_image_data_mut()[target + target_x..target + target_x + length]
+                        .copy_from_slice(
+                            &image.get_image_data()[source + source_x..source + source_x + length],
+                        );
+                }
+                touched += 1;
+            }
+        }
+        touched
+    }
+}
+
+impl Drawable for TiledTexture {
+    fn draw(&self, backend: &mut dyn Backend, position: IVec2) {
+        for (i, tile) in self.tiles.iter().enumerate() {
+            backend.draw_texture(tile, position + self.tile_origin(i).as_ivec2(), self.tint);
+        }
+    }
+}
+
+impl Updatable for TiledTexture {
+    /// An image of another size replaces the texture.
+    fn update(&mut self, image: &RGBAImage) {
+        if (image.width() as u32, image.height() as u32) == (self.width, self.height) {
+            self.update_rect(UVec2::ZERO, image);
+        } else {
+            *self = Self::from_image(image)
+                .with_tile_size(self.tile_size)
+                .with_tint(self.tint);
+        }
+    }
+
+    fn update_rects(&mut self, image: &RGBAImage, changed: &[DirtyRect]) {
+        if (image.width() as u32, image.height() as u32) != (self.width, self.height) {
+            return self.update(image);
+        }
+        let (width, height) = (self.width, self.height);
+        for rect in changed.iter().filter_map(|r| r.clip(width, height)) {
+            let position = UVec2::new(rect.x, rect.y);
+            if let Some(part) = image.crop(position, UVec2::new(rect.width, rect.height)) {
+                self.update_rect(position, &part);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::graphics::backend::software::Software;
+    use crate::graphics::color::RGBA8;
+    use crate::graphics::dirty::DirtyRect;
+    use crate::graphics::image::RGBAImage;
+    use crate::graphics::quad::{Drawable, Updatable};
+    use crate::graphics::texture::Texture;
+    use crate::graphics::tiled::TiledTexture;
+    use macroquad::color::WHITE;
+    use macroquad::math::{IVec2, UVec2};
+
+    fn gray(level: u8) -> RGBA8 {
+        RGBA8::from([level, level, level, 255])
+    }
+
+    /// Each pixel different.
+    fn gradient(width: u32, height: u32) -> RGBAImage {
+        RGBAImage::from_pixels(
+            width,
+            height,
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| RGBA8::from([x as u8, y as u8, 0, 255])))
+                .collect(),
+        )
+    }
+
+    fn dirty(tiled: &TiledTexture) -> Vec<Vec<DirtyRect>> {
+        tiled.tiles().iter().map(|t| t.take_dirty()).collect()
+    }
+
+    #[test]
+    fn check_tiles_cover_the_image() {
+        let tiled = TiledTexture::new(10, 5, gray(0)).with_tile_size(4);
+        let sizes: Vec<_> = tiled
+            .tiles()
+            .iter()
+            .map(|t| (t.image().width(), t.image().height()))
+            .collect();
+        assert_eq!(sizes, vec![(4, 4), (4, 4), (2, 4), (4, 1), (4, 1), (2, 1)]);
+
+        assert!(TiledTexture::new(0, 5, gray(0)).tiles().is_empty());
+    }
+
+    #[test]
+    fn check_larger_than_u16() {
+        let mut tiled = TiledTexture::new(70_000, 2, gray(0));
+        assert_eq!(tiled.tiles().len(), 137);
+
+        tiled.set_pixel(69_999, 1, gray(9));
+        assert_eq!(tiled.get_pixel(69_999, 1), gray(9));
+        assert_eq!(tiled.get_pixel(69_998, 1), gray(0));
+
+        // from an image as large
+        let mut image = RGBAImage::generate(70_000, 2, gray(0));
+        image.set_pixel(69_999, 0, gray(5));
+        let tiled = TiledTexture::from_image(&image);
+        assert_eq!(tiled.width(), 70_000);
+        assert_eq!(tiled.get_pixel(69_999, 0), gray(5));
+        assert!(tiled
+            .tiles()
+            .iter()
+            .all(|t| t.image().u16_dimensions().is_ok()));
+    }
+
+    #[test]
+    fn check_update_touches_only_overlapped_tiles() {
+        let mut tiled = TiledTexture::new(12, 8, gray(0)).with_tile_size(4);
+        assert_eq!(dirty(&tiled), vec![vec![]; 6]);
+
+        // over the 4 tiles around (4, 4)
+        let touched = tiled.update_rect(UVec2::new(3, 3), &gradient(2, 2));
+        assert_eq!(touched, 4);
+        // only the overlaps are uploaded again
+        assert_eq!(
+            dirty(&tiled),
+            vec![
+                vec![DirtyRect::pixel(3, 3)],
+                vec![DirtyRect::pixel(0, 3)],
+                vec![],
+                vec![DirtyRect::pixel(3, 0)],
+                vec![DirtyRect::pixel(0, 0)],
+                vec![],
+            ]
+        );
+        assert_eq!(tiled.get_pixel(3, 3), RGBA8::from([0, 0, 0, 255]));
+        assert_eq!(tiled.get_pixel(4, 4), RGBA8::from([1, 1, 0, 255]));
+        assert_eq!(tiled.get_pixel(5, 5), gray(0));
+
+        let touched = tiled.update_rect(UVec2::new(10, 6), &gradient(5, 5));
+        assert_eq!(touched, 1);
+        assert_eq!(tiled.get_pixel(11, 7), RGBA8::from([1, 1, 0, 255]));
+
+        assert_eq!(tiled.update_rect(UVec2::new(12, 0), &gradient(5, 5)), 0);
+    }
+
+    #[test]
+    fn check_drawn_as_one() {
+        let image = gradient(11, 7);
+        let draw = |d: &dyn Drawable| {
+            let mut backend = Software::new(16, 12);
+            d.draw(&mut backend, IVec2::new(2, 3));
+            backend.into_image()
+        };
+
+        let tiled = TiledTexture::from_image(&image).with_tile_size(3);
+        assert_eq!(tiled.tiles().len(), 12);
+        assert_eq!(draw(&tiled), draw(&Texture::new(&image, WHITE)));
+    }
+
+    #[test]
+    fn check_resized_by_update() {
+        let mut tiled = TiledTexture::new(4, 4, gray(0)).with_tile_size(2);
+        tiled.update(&gradient(5, 3));
+
+        assert_eq!((tiled.width(), tiled.height()), (5, 3));
+        assert_eq!(tiled.tile_size(), 2);
+        assert_eq!(tiled.get_pixel(4, 2), RGBA8::from([4, 2, 0, 255]));
+    }
+
+    #[test]
+    fn check_update_rects_touches_only_changes() {
+        let mut tiled = TiledTexture::new(8, 4, gray(0)).with_tile_size(4);
+        let mut image = RGBAImage::generate(8, 4, gray(0));
+        image.set_pixel(5, 1, gray(7));
+        image.set_pixel(0, 0, gray(7));
+
+        // the change at (0, 0) is not reported : left as is
+        tiled.update_rects(&image, &[DirtyRect::pixel(5, 1)]);
+
+        assert_eq!(tiled.get_pixel(5, 1), gray(7));
+        assert_eq!(tiled.get_pixel(0, 0), gray(0));
+        assert_eq!(dirty(&tiled), vec![vec![], vec![DirtyRect::pixel(1, 1)]]);
+    }
+}
@@ -6,7 +6,9 @@ use macroquad::ui;
 use std::ops::Deref;
 use std::time::Duration;
 
-use crate::graphics::quad::{Drawable, Updatable};
+use crate::graphics;
+use crate::graphics::backend;
+use crate::graphics::quad::Drawable;
 use crate::graphics::Viewable;
 
 pub struct View {
@@ -32,11 +34,11 @@ impl View {
     pub(crate) async fn update(&mut self, viewable: &impl Viewable) {
         clear_background(RED);
 
-        self.sprite.update(viewable.render().borrow().deref());
+        graphics::update(&mut self.sprite, viewable);
 
         let pos = IVec2::new(0, 0);
 
-        Drawable::draw(&self.sprite, pos);
+        Drawable::draw(&self.sprite, &mut backend::Macroquad::default(), pos);
 
         //TODO : on screen / window instead of log...
         // println!("FPS: {}", self.current_fps());
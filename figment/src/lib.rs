@@ -5,3 +5,4 @@ extern crate test;
 
 pub mod compute;
 pub mod graphics;
+pub mod recorder;
@@ -0,0 +1,440 @@
+use crate::compute;
+use crate::compute::Computable;
+use crate::graphics::image::RGBAImage;
+use crate::graphics::Viewable;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::time::Duration;
+
+/// Animated output formats supported by the recorder.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    Gif,
+    Apng,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Palette {
+    /// GIF frames are quantized one by one, APNG frames are stored in true colour.
+    Auto,
+    /// Every pixel is mapped to the closest colour of this palette (at most 256 colours).
+    Fixed(Vec<[u8; 3]>),
+}
+
+#[derive(Debug)]
+pub enum RecordError {
+    Gif(gif::EncodingError),
+    Apng(png::EncodingError),
+    Io(std::io::Error),
+    /// Fixed palettes must hold between 1 and 256 colours.
+    InvalidPalette(usize),
+    /// Upscaled frames (width, height) larger than the format allows : 65535 pixels for GIF.
+    TooLarge(u64, u64),
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordError::Gif(e) => write!(f, "GIF encoding failed: {}", e),
+            RecordError::Apng(e) => write!(f, "APNG encoding failed: {}", e),
+            RecordError::Io(e) => write!(f, "recording output failed: {}", e),
+            RecordError::InvalidPalette(len) => {
+                write!(f, "a palette of {} colours cannot be indexed", len)
+            }
+            RecordError::TooLarge(width, height) => {
+                write!(f, "{}x{} frames do not fit in this format", width, height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordError {}
+
+impl From<gif::EncodingError> for RecordError {
+    fn from(value: gif::EncodingError) -> Self {
+        RecordError::Gif(value)
+    }
+}
+
+impl From<png::EncodingError> for RecordError {
+    fn from(value: png::EncodingError) -> Self {
+        RecordError::Apng(value)
+    }
+}
+
+impl From<std::io::Error> for RecordError {
+    fn from(value: std::io::Error) -> Self {
+        RecordError::Io(value)
+    }
+}
+
+/// Runs a simulation headless (no window needed) and records its renderings as an animation.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    format: Format,
+    generations: u32,
+    frame_skip: u32,
+    scale: u32,
+    palette: Palette,
+    frame_delay: Duration,
+}
+
+impl Recorder {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            generations: 100,
+            frame_skip: 0,
+            scale: 1,
+            palette: Palette::Auto,
+            frame_delay: Duration::from_millis(100),
+        }
+    }
+
+    pub fn with_generations(self, generations: u32) -> Self {
+        Self {
+            generations,
+            ..self
+        }
+    }
+
+    /// Number of generations computed, but not recorded, between two frames.
+    pub fn with_frame_skip(self, frame_skip: u32) -> Self {
+        Self { frame_skip, ..self }
+    }
+
+    /// Integer upscaling factor, each cell becomes a `scale` x `scale` square.
+    pub fn with_scale(self, scale: u32) -> Self {
+        Self {
+            scale: scale.max(1),
+            ..self
+        }
+    }
+
+    pub fn with_palette(self, palette: Palette) -> Self {
+        Self { palette, ..self }
+    }
+
+    pub fn with_frame_delay(self, frame_delay: Duration) -> Self {
+        Self {
+            frame_delay,
+            ..self
+        }
+    }
+
+    /// Number of frames in the animation : the initial state, then one every `frame_skip + 1` generations.
+    /// Trailing generations that would not be recorded are not computed.
+    pub fn frame_count(&self) -> u32 {
+        1 + self.generations / (self.frame_skip + 1)
+    }
+
+    pub fn record_to_file<C>(
+        &self,
+        simulation: &mut C,
+        path: impl AsRef<Path>,
+    ) -> Result<u32, RecordError>
+    where
+        C: Computable + Viewable,
+    {
+        let file = File::create(path)?;
+        self.record(simulation, BufWriter::new(file))
+    }
+
+    /// Computes `generations` updates of the simulation, writing frames to `writer`.
+    /// Returns the number of frames written.
+    pub fn record<C>(&self, simulation: &mut C, writer: impl Write) -> Result<u32, RecordError>
+    where
+        C: Computable + Viewable,
+    {
+        if let Palette::Fixed(colors) = &self.palette {
+            if colors.is_empty() || colors.len() > 256 {
+                return Err(RecordError::InvalidPalette(colors.len()));
+            }
+        }
+        self.check_frame_size(simulation)?;
+
+        let first = self.capture(simulation);
+        match self.format {
+            Format::Gif => self.record_gif(simulation, first, writer),
+            Format::Apng => self.record_apng(simulation, first, writer),
+        }
+    }
+
+    /// Before upscaling anything.
+    fn check_frame_size(&self, simulation: &impl Viewable) -> Result<(), RecordError> {
+        let (width, height) = {
+            let image = simulation.render().borrow();
+            (
+                image.width() as u64 * self.scale as u64,
+                image.height() as u64 * self.scale as u64,
+            )
+        };
+        let max = match self.format {
+            Format::Gif => u16::MAX as u64,
+            Format::Apng => u32::MAX as u64,
+        };
+        if width > max || height > max {
+            return Err(RecordError::TooLarge(width, height));
+        }
+        Ok(())
+    }
+
+    fn capture(&self, simulation: &impl Viewable) -> RGBAImage {
+        simulation.render().borrow().upscale(self.scale)
+    }
+
+    /// Advances the simulation up to the next recorded frame.
+    fn advance<C>(&self, simulation: &mut C) -> RGBAImage
+    where
+        C: Computable + Viewable,
+    {
+        for _ in 0..=self.frame_skip {
+            compute::compute(simulation);
+        }
+        self.capture(simulation)
+    }
+
+    fn record_gif<C>(
+        &self,
+        simulation: &mut C,
+        first: RGBAImage,
+        writer: impl Write,
+    ) -> Result<u32, RecordError>
+    where
+        C: Computable + Viewable,
+    {
+        let global_palette: Vec<u8> = match &self.palette {
+            Palette::Auto => vec![],
+            Palette::Fixed(colors) => colors.iter().flatten().copied().collect(),
+        };
+        let too_large = || RecordError::TooLarge(first.width() as u64, first.height() as u64);
+        let width = u16::try_from(first.width()).map_err(|_| too_large())?;
+        let height = u16::try_from(first.height()).map_err(|_| too_large())?;
+        let mut encoder = gif::Encoder::new(writer, width, height, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+
+        let delay = (self.frame_delay.as_millis() / 10).min(u16::MAX as u128) as u16;
+        let mut image = first;
+        for f in 1..=self.frame_count() {
+            let mut frame = match &self.palette {
+                Palette::Auto => {
+                    let mut bytes = image.as_bytes().to_vec();
+                    gif::Frame::from_rgba_speed(width, height, &mut bytes, 10)
+                }
+                Palette::Fixed(colors) => {
+                    gif::Frame::from_indexed_pixels(width, height, indexed(&image, colors), None)
+                }
+            };
+            frame.delay = delay;
+            encoder.write_frame(&frame)?;
+
+            if f < self.frame_count() {
+                image = self.advance(simulation);
+            }
+        }
+        Ok(self.frame_count())
+    }
+
+    fn record_apng<C>(
+        &self,
+        simulation: &mut C,
+        first: RGBAImage,
+        writer: impl Write,
+    ) -> Result<u32, RecordError>
+    where
+        C: Computable + Viewable,
+    {
+        let mut encoder = png::Encoder::new(writer, first.width() as u32, first.height() as u32);
+        encoder.set_depth(png::BitDepth::Eight);
+        match &self.palette {
+            Palette::Auto => encoder.set_color(png::ColorType::Rgba),
+            Palette::Fixed(colors) => {
+                encoder.set_color(png::ColorType::Indexed);
+                encoder.set_palette(colors.iter().flatten().copied().collect::<Vec<u8>>());
+            }
+        }
+        encoder.set_animated(self.frame_count(), 0)?;
+        let delay = self.frame_delay.as_millis().min(u16::MAX as u128) as u16;
+        encoder.set_frame_delay(delay, 1000)?;
+
+        let mut png_writer = encoder.write_header()?;
+        let mut image = first;
+        for f in 1..=self.frame_count() {
+            match &self.palette {
+                Palette::Auto => png_writer.write_image_data(image.as_bytes())?,
+                Palette::Fixed(colors) => {
+                    png_writer.write_image_data(indexed(&image, colors).as_slice())?
+                }
+            }
+
+            if f < self.frame_count() {
+                image = self.advance(simulation);
+            }
+        }
+        png_writer.finish()?;
+        Ok(self.frame_count())
+    }
+}
+
+/// Maps each pixel to the index of the closest palette colour (alpha is ignored).
+fn indexed(image: &RGBAImage, colors: &[[u8; 3]]) -> Vec<u8> {
+    image
+        .get_image_data()
+        .iter()
+        .map(|px| <[u8; 4]>::from(*px))
+        .map(|px| {
+            colors
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, c)| {
+                    (0..3)
+                        .map(|i| (px[i] as i32 - c[i] as i32).pow(2))
+                        .sum::<i32>()
+                })
+                .map(|(i, _)| i as u8)
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compute::Computable;
+    use crate::graphics::image::RGBAImage;
+    use crate::graphics::Viewable;
+    use crate::recorder::{Format, Palette, RecordError, Recorder};
+    use macroquad::color::{BLACK, WHITE};
+    use std::cell::RefCell;
+    use std::iter::Peekable;
+    use std::ops::Range;
+    use std::time::Duration;
+
+    /// Blinks a single pixel, once per generation.
+    struct Blinker {
+        generation: u32,
+        image: RefCell<RGBAImage>,
+    }
+
+    impl Blinker {
+        fn new() -> Self {
+            Self {
+                generation: 0,
+                image: RefCell::new(RGBAImage::generate(2, 1, WHITE.into())),
+            }
+        }
+    }
+
+    impl Computable for Blinker {
+        type Stepper = Range<u8>;
+
+        fn compute_reset(&self) -> Peekable<Self::Stepper> {
+            (0..1).peekable()
+        }
+
+        fn compute(&mut self, _elapsed: Duration, remainder: &mut Peekable<Self::Stepper>) {
+            for _ in remainder {
+                self.generation += 1;
+            }
+        }
+
+        fn compute_until(
+            &mut self,
+            elapsed: Duration,
+            remainder: &mut Peekable<Self::Stepper>,
+            _until: impl Fn() -> bool,
+        ) {
+            self.compute(elapsed, remainder)
+        }
+    }
+
+    impl Viewable for Blinker {
+        fn render(&self) -> &RefCell<RGBAImage> {
+            let color = if self.generation.is_multiple_of(2) {
+                WHITE
+            } else {
+                BLACK
+            };
+            self.image.borrow_mut().set_pixel(0, 0, color.into());
+            &self.image
+        }
+    }
+
+    #[test]
+    fn check_frame_count_with_skip() {
+        let r = Recorder::new(Format::Gif)
+            .with_generations(10)
+            .with_frame_skip(1);
+        assert_eq!(r.frame_count(), 6);
+    }
+
+    #[test]
+    fn check_gif_recording() {
+        let mut b = Blinker::new();
+        let mut out: Vec<u8> = vec![];
+
+        let frames = Recorder::new(Format::Gif)
+            .with_generations(4)
+            .with_scale(3)
+            .with_palette(Palette::Fixed(vec![[0, 0, 0], [255, 255, 255]]))
+            .record(&mut b, &mut out)
+            .unwrap();
+        assert_eq!(frames, 5);
+        assert_eq!(b.generation, 4);
+
+        let mut decoder = gif::DecodeOptions::new().read_info(out.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (6, 3));
+        let mut decoded = 0;
+        let mut first_pixels: Vec<u8> = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            first_pixels.push(frame.buffer[0]);
+            decoded += 1;
+        }
+        assert_eq!(decoded, 5);
+        // white, black, white, ... as indexes in the palette
+        assert_eq!(first_pixels, vec![1, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn check_apng_recording() {
+        let mut b = Blinker::new();
+        let mut out: Vec<u8> = vec![];
+
+        let frames = Recorder::new(Format::Apng)
+            .with_generations(6)
+            .with_frame_skip(2)
+            .record(&mut b, &mut out)
+            .unwrap();
+        assert_eq!(frames, 3);
+        assert_eq!(b.generation, 6);
+
+        let reader = png::Decoder::new(out.as_slice()).read_info().unwrap();
+        assert_eq!(
+            reader.info().animation_control().map(|a| a.num_frames),
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn check_empty_palette_refused() {
+        let mut b = Blinker::new();
+        let res = Recorder::new(Format::Gif)
+            .with_palette(Palette::Fixed(vec![]))
+            .record(&mut b, Vec::new());
+        assert!(matches!(res, Err(RecordError::InvalidPalette(0))));
+    }
+
+    #[test]
+    fn check_too_large_for_gif_refused() {
+        let mut b = Blinker::new();
+        let mut out = Vec::new();
+        // 2 x 40000 pixels wide
+        let res = Recorder::new(Format::Gif)
+            .with_generations(1)
+            .with_scale(40_000)
+            .record(&mut b, &mut out);
+        assert!(matches!(res, Err(RecordError::TooLarge(80_000, 40_000))));
+        assert!(out.is_empty());
+    }
+}
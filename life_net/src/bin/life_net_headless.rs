@@ -0,0 +1,113 @@
+//! Runs a life_net simulation without any window, for batch experiments.
+use clap::Parser;
+use figment::graphics;
+use life_net::runner::Runner;
+use life_net::setup::Board;
+use quadlife::rule::{Rule, Topology};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+#[command(about = "Run a life_net simulation headless, and report its final state")]
+struct Args {
+    /// Board width, ignored when starting from a pattern
+    #[arg(long, default_value_t = 256)]
+    width: u32,
+    /// Board height, ignored when starting from a pattern
+    #[arg(long, default_value_t = 256)]
+    height: u32,
+    /// Life-like rule, in B/S notation
+    #[arg(long, default_value_t = Rule::default())]
+    rule: Rule,
+    /// bounded or torus
+    #[arg(long, default_value_t = Topology::default())]
+    topology: Topology,
+    /// Seed of the random soup
+    #[arg(long)]
+    seed: Option<u64>,
+    /// PNG pattern to start from, instead of a random soup
+    #[arg(long)]
+    pattern: Option<PathBuf>,
+    /// Maximum number of generations to compute
+    #[arg(long, default_value_t = 1000)]
+    generations: u64,
+    /// Stop as soon as the board is stable (still or oscillating)
+    #[arg(long)]
+    until_stable: bool,
+    /// How many past generations are remembered to detect oscillators
+    #[arg(long, default_value_t = 64)]
+    stable_window: usize,
+    /// Write the final state as a PNG image
+    #[arg(long)]
+    output: Option<PathBuf>,
+    /// Write the statistics to this file instead of stdout
+    #[arg(long)]
+    stats: Option<PathBuf>,
+    /// Write the population of each generation as CSV
+    #[arg(long)]
+    history: Option<PathBuf>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    let board = Board {
+        width: args.width,
+        height: args.height,
+        rule: args.rule,
+        topology: args.topology,
+        seed: args.seed,
+        pattern: args.pattern,
+    };
+
+    let mut lifequad = match board.build() {
+        Ok(q) => q,
+        Err(e) => {
+            eprintln!("cannot build the board: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut runner = Runner::new(args.generations);
+    if args.until_stable {
+        runner = runner.until_stable(args.stable_window);
+    }
+    let stats = runner.run(&mut lifequad);
+
+    if let Some(path) = args.output {
+        if let Err(e) = graphics::export(&lifequad, &path) {
+            eprintln!("cannot write {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    if let Some(path) = args.history {
+        let written = File::create(&path).and_then(|f| stats.write_history_csv(BufWriter::new(f)));
+        if let Err(e) = written {
+            eprintln!("cannot write {}: {}", path.display(), e);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let report = format!(
+        "rule: {}\ntopology: {}\nsize: {}x{}\n{}\n",
+        lifequad.rule(),
+        lifequad.topology(),
+        lifequad.width(),
+        lifequad.height(),
+        stats
+    );
+    match args.stats {
+        None => print!("{}", report),
+        Some(path) => {
+            if let Err(e) = std::fs::write(&path, report) {
+                eprintln!("cannot write {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
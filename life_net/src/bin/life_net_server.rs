@@ -0,0 +1,148 @@
+//! Runs an authoritative life_net game without any window : clients connect with
+//! `life_net --net-mode broadcast`, their actions are validated here, and they are sent the resulting state.
+use clap::Parser;
+use life_net::net::tcp::TcpTransport;
+use life_net::net::PeerId;
+use life_net::server::{Server, SERVER_ID};
+use life_net::setup::Board;
+use quadlife::event::Game;
+use quadlife::placement::Policy;
+use quadlife::rule::{Rule, Topology};
+use quadlife::score::{Referee, Scoreboard, Victory};
+use quadlife::tribe::MAX_TRIBES;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+/// How long the clients are still served once the game is over, for the last generation to reach them.
+const LINGER: Duration = Duration::from_millis(500);
+
+#[derive(Parser, Debug)]
+#[command(about = "Run an authoritative life_net game server, headless")]
+struct Args {
+    /// Address to listen on, for the clients
+    #[arg(long, default_value = "0.0.0.0:4000")]
+    listen: SocketAddr,
+    /// Clients have the peer ids 1 to max-clients, which are also their tribes : 5 at most
+    #[arg(long, default_value_t = (MAX_TRIBES - 1) as PeerId)]
+    max_clients: PeerId,
+    /// Board width, ignored when starting from a pattern
+    #[arg(long, default_value_t = 256)]
+    width: u32,
+    /// Board height, ignored when starting from a pattern
+    #[arg(long, default_value_t = 256)]
+    height: u32,
+    /// Life-like rule, in B/S notation
+    #[arg(long, default_value_t = Rule::default())]
+    rule: Rule,
+    /// bounded or torus
+    #[arg(long, default_value_t = Topology::default())]
+    topology: Topology,
+    /// Seed of the random soup
+    #[arg(long)]
+    seed: Option<u64>,
+    /// PNG pattern to start from, instead of a random soup
+    #[arg(long)]
+    pattern: Option<PathBuf>,
+    /// Generations per second
+    #[arg(long, default_value_t = 10.)]
+    generation_rate: f32,
+    /// Snapshots per second sent to the clients
+    #[arg(long, default_value_t = 10)]
+    broadcast_rate: u32,
+    /// domination:<percent>, last-alive or time:<generations>
+    #[arg(long)]
+    victory: Option<Victory>,
+    /// Stop the game at this generation, and exit once it is over
+    #[arg(long)]
+    generations: Option<u64>,
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    if args.max_clients as usize >= MAX_TRIBES {
+        eprintln!(
+            "{} clients, but only {} tribes to play",
+            args.max_clients,
+            MAX_TRIBES - 1
+        );
+        return ExitCode::FAILURE;
+    }
+
+    let board = Board {
+        width: args.width,
+        height: args.height,
+        rule: args.rule,
+        topology: args.topology,
+        seed: args.seed,
+        pattern: args.pattern,
+    };
+    let quad = match board.build() {
+        Ok(q) => q,
+        Err(e) => {
+            eprintln!("cannot build the board: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let clients = 1..=args.max_clients;
+    let mut transport = match TcpTransport::serve(SERVER_ID, args.listen, clients.clone()) {
+        Ok(t) => t,
+        Err(e) => {
+            eprintln!("cannot listen on {}: {}", args.listen, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut server = Server::new(Game::new(quad, Policy::default()), clients)
+        .with_generation_rate(args.generation_rate)
+        .with_broadcast_rate(args.broadcast_rate);
+    if let Some(last) = args.generations {
+        server = server.with_generation_limit(last);
+    }
+    println!(
+        "serving a {}x{} board on {}",
+        server.game().quad().width(),
+        server.game().quad().height(),
+        args.listen
+    );
+
+    let mut referee = Referee::new(args.victory.into_iter().collect());
+    let mut judged = 0;
+    let mut joined = 0;
+    loop {
+        if let Err(e) = server.update(&mut transport) {
+            eprintln!("network error: {}", e);
+        }
+
+        let active = server.broadcaster().active().count();
+        if active != joined {
+            joined = active;
+            println!("{} clients joined", joined);
+        }
+        let generation = server.game().quad().generation();
+        if generation != judged && !server.game().quad().is_computing() {
+            judged = generation;
+            if let Some(over) = referee.judge(&Scoreboard::of(server.game().quad())) {
+                println!("{}", over);
+            }
+        }
+        if server.is_over() {
+            println!("stopped at generation {}", generation);
+            // the last generation, whatever the broadcast rate, then time to write it out
+            server.flush(&mut transport);
+            let linger = Instant::now() + LINGER;
+            while Instant::now() < linger {
+                if let Err(e) = server.update(&mut transport) {
+                    eprintln!("network error: {}", e);
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+            return ExitCode::SUCCESS;
+        }
+        if !server.game().quad().is_computing() {
+            // waiting for the next generation
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use macroquad::input::{is_key_pressed, KeyCode};
+use std::fmt::{Display, Formatter};
+
+/// Slowest and fastest simulation rates, in generations per frame.
+const MIN_RATE: f32 = 1. / 64.;
+const MAX_RATE: f32 = 64.;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    TogglePause,
+    /// Advance exactly one generation (finishing the one in progress, if any).
+    StepGeneration,
+    /// Advance one partial update slice.
+    StepSlice,
+    Faster,
+    Slower,
+}
+
+impl Command {
+    /// Keyboard bindings : Space pauses, N steps a generation, M steps a slice, Up / Down change speed.
+    pub fn from_keyboard() -> Vec<Command> {
+        [
+            (KeyCode::Space, Command::TogglePause),
+            (KeyCode::N, Command::StepGeneration),
+            (KeyCode::M, Command::StepSlice),
+            (KeyCode::Up, Command::Faster),
+            (KeyCode::KpAdd, Command::Faster),
+            (KeyCode::Down, Command::Slower),
+            (KeyCode::KpSubtract, Command::Slower),
+        ]
+        .iter()
+        .filter(|(key, _)| is_key_pressed(*key))
+        .map(|(_, command)| *command)
+        .collect()
+    }
+}
+
+/// What the simulation should compute during the current frame.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Plan {
+    /// Complete generations (the first one finishing any generation in progress).
+    pub generations: u32,
+    /// Partial update slices, each one constrained by the available frame time.
+    pub slices: u32,
+}
+
+/// Pause, single step and speed state of the simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Playback {
+    paused: bool,
+    /// generations (or slices, in partial update mode) per frame
+    rate: f32,
+    /// fractional progress accumulated over frames, for rates below 1.
+    credit: f32,
+    partial: bool,
+    requested: Plan,
+}
+
+impl Playback {
+    pub fn new(partial: bool) -> Self {
+        Self {
+            paused: false,
+            rate: 1.,
+            credit: 0.,
+            partial,
+            requested: Plan::default(),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn rate(&self) -> f32 {
+        self.rate
+    }
+
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::TogglePause => {
+                self.paused = !self.paused;
+                self.credit = 0.;
+            }
+            Command::StepGeneration => self.requested.generations += 1,
+            Command::StepSlice => self.requested.slices += 1,
+            Command::Faster => self.rate = (self.rate * 2.).min(MAX_RATE),
+            Command::Slower => self.rate = (self.rate / 2.).max(MIN_RATE),
+        }
+    }
+
+    /// Decides what to compute this frame. Steps requested while running are ignored.
+    pub fn plan(&mut self) -> Plan {
+        let requested = std::mem::take(&mut self.requested);
+        if self.paused {
+            return requested;
+        }
+
+        self.credit += self.rate;
+        let whole = self.credit.floor();
+        self.credit -= whole;
+        if self.partial {
+            Plan {
+                generations: 0,
+                slices: whole as u32,
+            }
+        } else {
+            Plan {
+                generations: whole as u32,
+                slices: 0,
+            }
+        }
+    }
+}
+
+impl Display for Playback {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let unit = if self.partial { "slice" } else { "gen" };
+        let state = if self.paused { "PAUSED" } else { "RUNNING" };
+        if self.rate >= 1. {
+            write!(f, "{} x{} {}/frame", state, self.rate, unit)
+        } else {
+            write!(f, "{} 1 {} / {} frames", state, unit, (1. / self.rate))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::controls::{Command, Plan, Playback};
+
+    #[test]
+    fn check_running_full_rate() {
+        let mut p = Playback::new(false);
+        assert_eq!(
+            p.plan(),
+            Plan {
+                generations: 1,
+                slices: 0
+            }
+        );
+        p.apply(Command::Faster);
+        assert_eq!(p.plan().generations, 2);
+    }
+
+    #[test]
+    fn check_fractional_rate() {
+        let mut p = Playback::new(false);
+        p.apply(Command::Slower);
+        p.apply(Command::Slower);
+        assert_eq!(p.rate(), 0.25);
+
+        let total: u32 = (0..8).map(|_| p.plan().generations).sum();
+        assert_eq!(total, 2);
+        assert_eq!(p.to_string(), "RUNNING 1 gen / 4 frames");
+    }
+
+    #[test]
+    fn check_paused_only_steps() {
+        let mut p = Playback::new(true);
+        p.apply(Command::TogglePause);
+        assert_eq!(p.plan(), Plan::default());
+
+        p.apply(Command::StepSlice);
+        p.apply(Command::StepGeneration);
+        assert_eq!(
+            p.plan(),
+            Plan {
+                generations: 1,
+                slices: 1
+            }
+        );
+        assert_eq!(p.plan(), Plan::default());
+        assert_eq!(p.to_string(), "PAUSED x1 slice/frame");
+    }
+
+    #[test]
+    fn check_rate_is_bounded() {
+        let mut p = Playback::new(false);
+        for _ in 0..20 {
+            p.apply(Command::Faster);
+        }
+        assert_eq!(p.rate(), 64.);
+        for _ in 0..40 {
+            p.apply(Command::Slower);
+        }
+        assert_eq!(p.rate(), 1. / 64.);
+    }
+}
@@ -0,0 +1,7 @@
+pub mod controls;
+pub mod net;
+pub mod paint;
+pub mod runner;
+pub mod server;
+pub mod settings;
+pub mod setup;
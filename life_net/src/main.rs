@@ -5,75 +5,364 @@ extern crate test;
 
 use figment::compute;
 use figment::graphics;
+use figment::graphics::camera::Camera;
+use figment::graphics::scene::Scene;
+use figment::graphics::tiled::TiledTexture;
 use figment::graphics::Viewable; // needed for render method...
+use life_net::controls::{Command, Playback};
+use life_net::net::broadcast::{Broadcaster, Viewer};
+use life_net::net::lockstep::Lockstep;
+use life_net::net::rollback::Rollback;
+use life_net::net::{NetMode, Transport};
+use life_net::paint::Painter;
+use life_net::server;
+use life_net::settings::{Settings, UpdateMode};
 use macroquad::prelude::*;
+use macroquad::ui;
+use once_cell::sync::Lazy;
 use quadlife::cell;
+use quadlife::desync::{DesyncEvent, Region};
+use quadlife::event::{Action, Game};
+use quadlife::placement::Policy;
+use quadlife::quad::Quad;
+use quadlife::score::{Referee, Scoreboard};
+use quadlife::tribe::Tribe;
+use std::collections::BTreeSet;
 use std::ops::Deref;
 use std::time::Duration;
 
+// Parsed once, before the window is created.
+static SETTINGS: Lazy<Settings> = Lazy::new(|| match Settings::from_args() {
+    Ok(settings) => settings,
+    Err(e) => {
+        eprintln!("{}", e);
+        std::process::exit(2)
+    }
+});
+
 fn window_conf() -> Conf {
     Conf {
         window_title: "Life Net".to_owned(),
-        window_width: 256,
-        window_height: 256,
+        window_width: SETTINGS.window_width as i32,
+        window_height: SETTINGS.window_height as i32,
         fullscreen: false,
         window_resizable: false,
         ..Default::default()
     }
 }
 
-const PARTIAL_UPDATE: bool = false;
+/// How this peer takes part in a network game.
+enum Network {
+    Lockstep(Box<dyn Transport>, Box<Lockstep>),
+    Rollback(Box<dyn Transport>, Box<Rollback>),
+    /// Simulates, and broadcasts its state.
+    Host(Box<dyn Transport>, Box<Broadcaster>),
+    /// Shows the host's state, and sends it our actions.
+    Viewer(Box<dyn Transport>, Viewer),
+}
+
+/// Cells of the board on screen : a cell is a world unit, the board being at the world origin.
+fn on_screen(camera: &Camera) -> Region {
+    let area = camera.visible_world(Vec2::new(screen_width(), screen_height()));
+    let (x, y) = (area.x.max(0.).floor(), area.y.max(0.).floor());
+    Region {
+        x: x as u32,
+        y: y as u32,
+        width: (area.right().ceil() - x).max(0.) as u32,
+        height: (area.bottom().ceil() - y).max(0.) as u32,
+    }
+}
+
+/// In tiles, so that boards larger than what the GPU takes in one texture are drawn too.
+/// Tinted yellow : dead cells show yellow, live ones black.
+fn board_texture(quad: &Quad) -> TiledTexture {
+    let texture = TiledTexture::from_image(quad.render().borrow().deref()).with_tint(YELLOW);
+    // all in already
+    quad.take_changes();
+    texture
+}
 
 #[macroquad::main(window_conf)]
 async fn main() {
-    let width = screen_width().floor() as u32;
-    let height = screen_height().floor() as u32;
-
-    //convert f32 screen size to something safe for render on image (u16 size)
-    //TMP : convert to u16 (until world implement multiquads... TODO)
-    let w: u16 = u16::try_from(width).unwrap_or_else(|_v| u16::MAX);
-    let h: u16 = u16::try_from(height).unwrap_or_else(|_v| u16::MAX);
+    let settings = SETTINGS.deref();
+    // in lockstep, generations are only computed once every peer's inputs are in.
+    let mut multiplayer = settings.network.as_ref().map(|network| {
+        let transport = network.connect().unwrap_or_else(|e| {
+            eprintln!("cannot listen on the network: {}", e);
+            std::process::exit(1)
+        });
+        let peers = network.peers.0.keys().copied();
+        match network.mode {
+            NetMode::Lockstep => Network::Lockstep(
+                transport,
+                Box::new(
+                    Lockstep::new(network.me, peers, network.input_delay)
+                        .with_desync_detection(network.checksum_interval),
+                ),
+            ),
+            NetMode::Rollback => Network::Rollback(
+                transport,
+                Box::new(Rollback::new(network.me, peers, network.input_delay)),
+            ),
+            NetMode::Broadcast if network.me == network.host() => Network::Host(
+                transport,
+                Box::new(
+                    Broadcaster::new(peers.filter(|p| *p != network.me))
+                        .with_rate(network.broadcast_rate),
+                ),
+            ),
+            NetMode::Broadcast => Network::Viewer(transport, Viewer::new(network.host())),
+        }
+    });
+    let partial_update = settings.update_mode == UpdateMode::Partial && multiplayer.is_none();
+    // The local player.
+    let player = settings
+        .network
+        .as_ref()
+        .map_or(Tribe(0), |network| Tribe(network.me));
 
-    println!("{} {}", w, h);
+    println!("{} {}", settings.board.width, settings.board.height);
 
     //We want a functional architecture
     // => the inner structure of the nested loops' states should probably be reflected here somehow ?
 
-    // TODO : View ==> Scene
-
-    // TODO : scene, for all relative positioning...
-
-    let mut lifequad = quadlife::quad::Quad::gen(cell::State::Dead, w, h).with_random_cells();
-    let mut sprite = graphics::sprite::Sprite::from_image(lifequad.render().borrow().deref());
+    let mut game = match settings.board.build() {
+        Ok(quad) => Game::new(quad, Policy::default()),
+        Err(e) => {
+            eprintln!("cannot build the board: {}", e);
+            std::process::exit(1)
+        }
+    };
+    let mut scene = Scene::default();
+    let board = scene.add(scene.root(), board_texture(game.quad()));
 
-    let mut compute_context =
-        compute::ComputeCtx::default().with_constraint(Duration::from_secs_f32(1. / 60.));
+    let mut compute_context = compute::ComputeCtx::default()
+        .with_constraint(Duration::from_secs_f32(1. / settings.target_fps));
 
-    let mut quad_upd_opt = if PARTIAL_UPDATE {
-        Some(compute::compute_reset(&lifequad))
+    let mut quad_upd_opt = if partial_update {
+        Some(compute::compute_reset(game.quad()))
     } else {
         None
     };
 
+    let mut playback = Playback::new(partial_update);
+    // unconstrained, to finish a generation in progress (or compute a full one)
+    let mut step_context = compute::ComputeCtx::default();
+    let mut painter = Painter::default();
+    let mut last_rejection = None;
+    let mut referee = Referee::new(settings.victory.into_iter().collect());
+    let mut scores = Scoreboard::of(game.quad());
+    let mut desynced = BTreeSet::new();
+    let mut camera = Camera::default();
+
     loop {
-        let available_sim_duration =
-            graphics::target_frame_time(60.0).saturating_sub(graphics::last_frame_time());
+        let available_sim_duration = graphics::target_frame_time(settings.target_fps)
+            .saturating_sub(graphics::last_frame_time());
+
+        for command in Command::from_keyboard() {
+            playback.apply(command);
+        }
+        let plan = playback.plan();
+        camera.control(graphics::last_frame_time());
+
+        painter.update_brush();
+        let action = match painter.update(&camera) {
+            None => None,
+            Some((cells, cell::State::Alive)) => Some(Action::Place {
+                tribe: player,
+                cells: cells.into_iter().collect(),
+            }),
+            // erasing is not limited (yet ?)
+            Some((cells, _)) => Some(Action::Erase {
+                cells: cells.into_iter().collect(),
+            }),
+        };
+        // applied at the next generation boundary, or after the input delay in lockstep
+        match (action, &mut multiplayer) {
+            (None, _) => {}
+            (Some(action), None | Some(Network::Host(..))) => {
+                last_rejection = game.submit(action).pop()
+            }
+            (Some(action), Some(Network::Lockstep(_, lockstep))) => lockstep.submit(action),
+            (Some(action), Some(Network::Rollback(_, rollback))) => rollback.submit(action),
+            // validated by the host
+            (Some(action), Some(Network::Viewer(_, viewer))) => viewer.submit(action),
+        }
 
         //Note : Discrete simulation can be called multiple time without rendering (speed purposes)
         // However a Continuous simulation (working on floats) leverage the elapsed time to algebraically compute next Update.
         // CAREFUL : Simulation could also be called multiple times, just to finish one full Update...
 
-        // attempt (TODO) multiple total Update on (possibly linear) simulation
-        // lifeworld.compute_partial(last_update.elapsed(), Some(available_sim_duration));
+        //LOCKSTEP UPDATE(S)
+        if let Some(Network::Lockstep(transport, lockstep)) = &mut multiplayer {
+            if let Err(e) = lockstep.poll(transport.as_mut()) {
+                eprintln!("network error: {}", e);
+            }
+            for _ in 0..plan.generations {
+                let Some(events) = lockstep.advance() else {
+                    break;
+                };
+                for event in events {
+                    game.schedule(event);
+                }
+                last_rejection = game.sync().pop().or(last_rejection);
+                if let Err(e) = game.step() {
+                    eprintln!("{}", e);
+                    break;
+                }
+                lockstep.check(game.quad());
+            }
+            for event in lockstep.take_desync_events() {
+                eprintln!("{}", event);
+                if let DesyncEvent::Detected { peer, .. } = event {
+                    desynced.insert(peer);
+                }
+            }
+        }
+        //ROLLBACK UPDATE(S) : late inputs rewind the game, even when paused
+        else if let Some(Network::Rollback(transport, rollback)) = &mut multiplayer {
+            if let Err(e) = rollback.poll(transport.as_mut()) {
+                eprintln!("network error: {}", e);
+            }
+            last_rejection = rollback.correct(&mut game).pop().or(last_rejection);
+            for _ in 0..plan.generations {
+                let Some(mut rejections) = rollback.advance(&mut game) else {
+                    break;
+                };
+                last_rejection = rejections.pop().or(last_rejection);
+            }
+        }
+        //BROADCAST UPDATE
+        else if let Some(Network::Viewer(transport, viewer)) = &mut multiplayer {
+            let size = (game.quad().width(), game.quad().height());
+            // only the cells on screen are synced
+            viewer.set_viewport(on_screen(&camera));
+            if let Err(e) = viewer.poll(game.quad_mut(), transport.as_mut()) {
+                eprintln!("network error: {}", e);
+            }
+            last_rejection = viewer.last_rejection();
+            if size != (game.quad().width(), game.quad().height()) {
+                // the host's board is not ours
+                scene[board].set_drawable(board_texture(game.quad()));
+            }
+        }
+        //FULL UPDATE(S) : alone, or hosting, this quad is the only one
+        else {
+            for _ in 0..plan.generations {
+                compute::compute_until(game.quad_mut(), &mut quad_upd_opt, &mut step_context);
+                last_rejection = game.sync().pop().or(last_rejection);
+            }
 
-        if PARTIAL_UPDATE {
-            //PARTIAL UPDATE
-            compute_context.set_constraint(available_sim_duration);
+            //PARTIAL UPDATE(S)
+            if plan.slices > 0 {
+                compute_context.set_constraint(available_sim_duration / plan.slices);
+                for _ in 0..plan.slices {
+                    compute::compute_until(
+                        game.quad_mut(),
+                        &mut quad_upd_opt,
+                        &mut compute_context,
+                    );
+                    last_rejection = game.sync().pop().or(last_rejection);
+                }
+            }
+        }
 
-            compute::compute_until(&mut lifequad, &mut quad_upd_opt, &mut compute_context);
-        } else {
-            //FULL UPDATE
-            compute::compute(&mut lifequad);
+        //BROADCAST : a rate limited subsystem of its own, next to simulation and rendering
+        if let Some(Network::Host(transport, broadcaster)) = &mut multiplayer {
+            match broadcaster.poll(game.quad(), transport.as_mut()) {
+                // one viewer failing does not discard the others' actions
+                Ok(inputs) => {
+                    for (peer, action) in inputs {
+                        if let Err(e) =
+                            server::apply_input(&mut game, peer, action, transport.as_mut())
+                        {
+                            eprintln!("network error: {}", e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("network error: {}", e),
+            }
+        }
+
+        if scores.generation != game.quad().generation() {
+            scores = Scoreboard::of(game.quad());
+            if let Some(over) = referee.judge(&scores) {
+                println!("{}", over);
+            }
+        }
+
+        //CAREFUL with z order : labels are drawn over the board, in call order.
+        ui::root_ui().label(None, playback.to_string().as_str());
+        ui::root_ui().label(
+            None,
+            format!("generation: {}", game.quad().generation()).as_str(),
+        );
+        ui::root_ui().label(None, format!("brush: {}", painter.brush.radius).as_str());
+        ui::root_ui().label(None, format!("zoom: {:.2}", camera.zoom()).as_str());
+        match &multiplayer {
+            Some(Network::Lockstep(_, lockstep)) => {
+                let waiting = lockstep.waiting_for();
+                if !waiting.is_empty() {
+                    ui::root_ui().label(None, format!("waiting for peers {:?}", waiting).as_str());
+                }
+                if !desynced.is_empty() {
+                    ui::root_ui().label(None, format!("DESYNC with peers {:?}", desynced).as_str());
+                }
+            }
+            Some(Network::Rollback(_, rollback)) => {
+                let waiting = rollback.waiting_for();
+                if !waiting.is_empty() {
+                    ui::root_ui().label(None, format!("waiting for peers {:?}", waiting).as_str());
+                }
+                ui::root_ui().label(
+                    None,
+                    format!(
+                        "rollbacks: {} ({} generations resimulated)",
+                        rollback.rollbacks(),
+                        rollback.resimulated()
+                    )
+                    .as_str(),
+                );
+            }
+            Some(Network::Host(_, broadcaster)) => ui::root_ui().label(
+                None,
+                format!("broadcast: {} KB sent", broadcaster.sent_bytes() / 1000).as_str(),
+            ),
+            Some(Network::Viewer(_, viewer)) => ui::root_ui().label(
+                None,
+                format!(
+                    "watching peer {}: {} KB received",
+                    viewer.host(),
+                    viewer.received_bytes() / 1000
+                )
+                .as_str(),
+            ),
+            None => {}
+        }
+        ui::root_ui().label(
+            None,
+            format!(
+                "energy: {}",
+                game.placements().energy(player, game.quad().generation())
+            )
+            .as_str(),
+        );
+        if let Some(rejection) = last_rejection {
+            ui::root_ui().label(None, rejection.to_string().as_str());
+        }
+        if let Some(score) = scores.get(player) {
+            ui::root_ui().label(
+                None,
+                format!(
+                    "territory: {:.1}% live: {:.1}%",
+                    score.terrain_share * 100.,
+                    score.live_share * 100.
+                )
+                .as_str(),
+            );
+        }
+        if let Some(over) = referee.game_over() {
+            ui::root_ui().label(None, over.to_string().as_str());
         }
 
         //TODO : put this in UI (useful only if different from FPS...)
@@ -84,8 +373,10 @@ async fn main() {
 
         // screen.update(&mut simulation).await;
 
-        graphics::update(&mut sprite, &lifequad);
+        if let Some(texture) = scene[board].drawable_mut::<TiledTexture>() {
+            graphics::update(texture, game.quad());
+        }
 
-        graphics::render(&sprite, IVec2::new(0, 0)).await;
+        graphics::render_with_camera(&scene, &camera).await;
     }
 }
@@ -0,0 +1,239 @@
+//! Multiplayer networking : peers exchange messages over a `Transport`, and play in `lockstep`,
+//! or follow the `broadcast` of a host.
+use crate::net::message::Message;
+use crate::net::tcp::TcpTransport;
+use crate::net::udp::UdpTransport;
+use quadlife::tribe::MAX_TRIBES;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+pub mod broadcast;
+pub mod local;
+pub mod lockstep;
+pub mod message;
+pub mod rollback;
+pub mod sim;
+pub mod tcp;
+pub mod udp;
+
+/// Identifies a peer in a game. It is also the tribe this peer plays : below `tribe::MAX_TRIBES`.
+pub type PeerId = u8;
+
+/// Moves messages between peers, without ever blocking.
+/// Delivery is best effort : messages may be lost (ie. to a disconnected peer), the lockstep layer resends them.
+pub trait Transport {
+    fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()>;
+
+    /// Next message received, if any.
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>>;
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum TransportKind {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+impl Display for TransportKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportKind::Tcp => write!(f, "tcp"),
+            TransportKind::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "tcp" => Ok(TransportKind::Tcp),
+            "udp" => Ok(TransportKind::Udp),
+            _ => Err(format!("{:?} is not a transport (tcp or udp)", s)),
+        }
+    }
+}
+
+/// How the peers of a game share it.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum NetMode {
+    /// Every peer plays and simulates, in lockstep : authoritative, but as slow as the slowest peer.
+    #[default]
+    Lockstep,
+    /// Every peer plays right away, predicting the inputs of the others, and rewinds when they arrive late :
+    /// nobody waits for the slowest peer, up to a limit.
+    Rollback,
+    /// Transient state broadcast : the host (lowest id) simulates and validates the actions of the others,
+    /// who watch its snapshots. The host may also be a headless `life_net_server`.
+    Broadcast,
+}
+
+impl Display for NetMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetMode::Lockstep => write!(f, "lockstep"),
+            NetMode::Rollback => write!(f, "rollback"),
+            NetMode::Broadcast => write!(f, "broadcast"),
+        }
+    }
+}
+
+impl FromStr for NetMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "lockstep" => Ok(NetMode::Lockstep),
+            "rollback" => Ok(NetMode::Rollback),
+            "broadcast" => Ok(NetMode::Broadcast),
+            _ => Err(format!(
+                "{:?} is not a network mode (lockstep, rollback or broadcast)",
+                s
+            )),
+        }
+    }
+}
+
+/// Addresses of all the peers of a game, ie. "0=127.0.0.1:4000,1=127.0.0.1:4001".
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PeerList(pub BTreeMap<PeerId, SocketAddr>);
+
+impl Display for PeerList {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let peers: Vec<String> = self
+            .0
+            .iter()
+            .map(|(id, addr)| format!("{}={}", id, addr))
+            .collect();
+        write!(f, "{}", peers.join(","))
+    }
+}
+
+impl FromStr for PeerList {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|peer| {
+                let (id, addr) = peer
+                    .split_once('=')
+                    .ok_or_else(|| format!("{:?} is not a peer (<id>=<address>)", peer))?;
+                let id = id
+                    .trim()
+                    .parse::<PeerId>()
+                    .map_err(|e| format!("{:?} is not a peer id: {}", id, e))?;
+                let addr = addr
+                    .trim()
+                    .parse::<SocketAddr>()
+                    .map_err(|e| format!("{:?} is not an address: {}", addr, e))?;
+                Ok((id, addr))
+            })
+            .collect::<Result<BTreeMap<_, _>, String>>()
+            .map(PeerList)
+    }
+}
+
+/// How to join a multiplayer game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetConfig {
+    pub me: PeerId,
+    /// All peers, this one included : it listens on its own address.
+    pub peers: PeerList,
+    pub transport: TransportKind,
+    /// Generations between an action and its application, to hide the network latency.
+    pub input_delay: u64,
+    pub mode: NetMode,
+    /// Generations between two checkpoints exchanged to detect desyncs, in lockstep.
+    pub checksum_interval: u64,
+    /// Snapshots per second sent by the host, in broadcast mode.
+    pub broadcast_rate: u32,
+}
+
+impl NetConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.peers.0.contains_key(&self.me) {
+            return Err(format!(
+                "peer {} is not in the peer list {}",
+                self.me, self.peers
+            ));
+        }
+        if self.peers.0.len() < 2 {
+            return Err("a multiplayer game needs at least 2 peers".to_string());
+        }
+        // peer ids are tribes
+        if let Some(id) = self.peers.0.keys().find(|id| **id as usize >= MAX_TRIBES) {
+            return Err(format!(
+                "peer {} has no tribe : ids go from 0 to {}",
+                id,
+                MAX_TRIBES - 1
+            ));
+        }
+        Ok(())
+    }
+
+    /// The peer simulating the game, in broadcast mode.
+    pub fn host(&self) -> PeerId {
+        self.peers.0.keys().next().copied().unwrap_or(self.me)
+    }
+
+    pub fn connect(&self) -> io::Result<Box<dyn Transport>> {
+        let listen =
+            self.peers.0.get(&self.me).copied().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidInput, "own address is unknown")
+            })?;
+        let others: BTreeMap<PeerId, SocketAddr> = self
+            .peers
+            .0
+            .iter()
+            .filter(|(id, _)| **id != self.me)
+            .map(|(id, addr)| (*id, *addr))
+            .collect();
+        Ok(match self.transport {
+            TransportKind::Tcp => Box::new(TcpTransport::bind(self.me, listen, others)?),
+            TransportKind::Udp => Box::new(UdpTransport::bind(self.me, listen, others)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{NetConfig, NetMode, PeerList, TransportKind};
+
+    #[test]
+    fn check_peer_list() {
+        let peers: PeerList = "0=127.0.0.1:4000, 1=[::1]:4001".parse().unwrap();
+        assert_eq!(peers.0.len(), 2);
+        assert_eq!(peers.0[&1].port(), 4001);
+        assert_eq!(peers.to_string(), "0=127.0.0.1:4000,1=[::1]:4001");
+
+        assert!("0=localhost".parse::<PeerList>().is_err());
+        assert!("127.0.0.1:4000".parse::<PeerList>().is_err());
+        assert!("300=127.0.0.1:4000".parse::<PeerList>().is_err());
+    }
+
+    #[test]
+    fn check_validate() {
+        let config = NetConfig {
+            me: 2,
+            peers: "0=127.0.0.1:4000,1=127.0.0.1:4001".parse().unwrap(),
+            transport: TransportKind::Udp,
+            input_delay: 2,
+            mode: NetMode::Broadcast,
+            checksum_interval: 32,
+            broadcast_rate: 10,
+        };
+        assert!(config.validate().is_err());
+        assert!(NetConfig {
+            me: 1,
+            ..config.clone()
+        }
+        .validate()
+        .is_ok());
+        assert_eq!(config.host(), 0);
+    }
+}
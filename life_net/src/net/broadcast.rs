@@ -0,0 +1,757 @@
+use crate::net::message::{Message, PROTOCOL_VERSION};
+use crate::net::{PeerId, Transport};
+use figment::compute::rate_limiter::RateLimiter;
+use grid::Grid;
+use quadlife::cell;
+use quadlife::chunk::{self, ChunkId};
+use quadlife::codec::Encode;
+use quadlife::delta::{Decoder, DeltaError, Encoder, Frame};
+use quadlife::desync::Region;
+use quadlife::event::Action;
+use quadlife::placement::Rejection;
+use quadlife::quad::Quad;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Delay between two announcements of a viewer, until it gets its first snapshot.
+const HELLO_DELAY: Duration = Duration::from_millis(200);
+
+/// Delay between two declarations of the same viewport, in case one was lost.
+const VIEWPORT_DELAY: Duration = Duration::from_millis(500);
+
+/// Transient state broadcast, host side : the host simulates alone, and sends snapshots of its quad to viewers,
+/// as deltas from the last snapshot each viewer acknowledged.
+/// Fast and cosmetic : a lost snapshot is simply superseded by the next one.
+/// Viewers only send their actions, for the host to validate and apply.
+/// Over udp, a snapshot must fit in a datagram : large boards need tcp.
+///
+/// Interest management : a viewer declaring a viewport only gets the chunks covering it, each as a delta
+/// from the last state of this chunk it acknowledged. Chunks coming into view are sent whole.
+pub struct Broadcaster {
+    viewers: BTreeSet<PeerId>,
+    /// Viewers heard from : snapshots are only sent to them.
+    active: BTreeSet<PeerId>,
+    /// Last frame applied by each viewer.
+    acked: BTreeMap<PeerId, u64>,
+    encoder: Encoder,
+    /// Cells each viewer looks at, if it told us.
+    viewports: BTreeMap<PeerId, Region>,
+    /// Last frame of each chunk in view applied by each viewer.
+    chunks_acked: BTreeMap<PeerId, BTreeMap<ChunkId, u64>>,
+    chunk_encoders: BTreeMap<ChunkId, Encoder>,
+    limiter: RateLimiter,
+    sent_bytes: u64,
+}
+
+impl Broadcaster {
+    pub fn new(viewers: impl IntoIterator<Item = PeerId>) -> Self {
+        Self {
+            viewers: viewers.into_iter().collect(),
+            active: BTreeSet::new(),
+            acked: BTreeMap::new(),
+            encoder: Encoder::default(),
+            viewports: BTreeMap::new(),
+            chunks_acked: BTreeMap::new(),
+            chunk_encoders: BTreeMap::new(),
+            limiter: RateLimiter::default(),
+            sent_bytes: 0,
+        }
+    }
+
+    /// Snapshots per second, at most. Unlimited by default : one per poll.
+    pub fn with_rate(self, per_second: u32) -> Self {
+        Self {
+            limiter: RateLimiter::default().with_maximum_rate(per_second.max(1) as f32),
+            ..self
+        }
+    }
+
+    /// Bytes of snapshots sent so far.
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes
+    }
+
+    /// Viewers heard from, ie. connected.
+    pub fn active(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.active.iter().copied()
+    }
+
+    /// The viewport of a viewer, if it declared one.
+    pub fn viewport(&self, viewer: PeerId) -> Option<Region> {
+        self.viewports.get(&viewer).copied()
+    }
+
+    /// Receives acknowledgements, and when the rate allows, sends the current state to the viewers not up to date.
+    /// Nothing is sent while the quad is computing : a partial generation is no state to show.
+    /// A viewer that cannot be sent to is dropped, and logged : the others are still served.
+    /// Returns the actions received from the viewers, to validate.
+    pub fn poll(
+        &mut self,
+        quad: &Quad,
+        transport: &mut (impl Transport + ?Sized),
+    ) -> io::Result<Vec<(PeerId, Action)>> {
+        let mut actions = vec![];
+        while let Some((from, message)) = transport.receive()? {
+            if !self.viewers.contains(&from) {
+                continue;
+            }
+            self.active.insert(from);
+            match message {
+                Message::SnapshotAck { frame } => {
+                    let acked = self.acked.entry(from).or_insert(frame);
+                    *acked = (*acked).max(frame);
+                }
+                // (re)connected, or lost track : its next snapshot is a keyframe
+                Message::Hello { .. } => {
+                    self.acked.remove(&from);
+                    self.chunks_acked.remove(&from);
+                }
+                Message::Viewport(viewport) => {
+                    // chunks out of view are forgotten : sent whole when they come back
+                    let visible = chunk::covering(viewport, quad.width(), quad.height());
+                    if let Some(acked) = self.chunks_acked.get_mut(&from) {
+                        acked.retain(|chunk, _| visible.contains(chunk));
+                    }
+                    self.viewports.insert(from, viewport);
+                }
+                Message::ChunkAck { chunk, frame } => {
+                    let acked = self.chunks_acked.entry(from).or_default();
+                    let frame = acked.get(&chunk).map_or(frame, |f| frame.max(*f));
+                    acked.insert(chunk, frame);
+                }
+                Message::Inputs { actions: a, .. } => {
+                    actions.extend(a.into_iter().map(|action| (from, action)));
+                }
+                _ => {}
+            }
+        }
+
+        if self.limiter.ready() {
+            self.flush(quad, transport);
+        }
+        Ok(actions)
+    }
+
+    /// Sends the current state to the viewers not up to date, whatever the rate : ie. the last state of a game.
+    /// Nothing is sent while the quad is computing.
+    pub fn flush(&mut self, quad: &Quad, transport: &mut (impl Transport + ?Sized)) {
+        if quad.is_computing() {
+            return;
+        }
+        let active: Vec<PeerId> = self.active.iter().copied().collect();
+        for viewer in active {
+            // ie. a snapshot too large for udp
+            if let Err(e) = self.send_state(viewer, quad, transport) {
+                eprintln!("viewer {} dropped: {}", viewer, e);
+                self.drop_viewer(viewer);
+            }
+        }
+    }
+
+    /// Sends the current state to a viewer, if it is not up to date.
+    fn send_state(
+        &mut self,
+        viewer: PeerId,
+        quad: &Quad,
+        transport: &mut (impl Transport + ?Sized),
+    ) -> io::Result<()> {
+        let Some(viewport) = self.viewports.get(&viewer).copied() else {
+            let base = self.acked.get(&viewer).copied();
+            let frame = self.encoder.encode(quad, base);
+            if base == Some(frame.id) {
+                return Ok(());
+            }
+            let message = Message::Snapshot(frame);
+            self.sent_bytes += message.to_bytes().len() as u64;
+            return transport.send(viewer, &message);
+        };
+        let (width, height) = (quad.width(), quad.height());
+        for chunk in chunk::covering(viewport, width, height) {
+            let base = self
+                .chunks_acked
+                .get(&viewer)
+                .and_then(|acked| acked.get(&chunk))
+                .copied();
+            let frame = self.chunk_encoders.entry(chunk).or_default().encode_region(
+                quad,
+                chunk::region(chunk, width, height),
+                base,
+            );
+            if base == Some(frame.id) {
+                continue;
+            }
+            let message = Message::ChunkSnapshot {
+                width: width as u32,
+                height: height as u32,
+                chunk,
+                frame,
+            };
+            self.sent_bytes += message.to_bytes().len() as u64;
+            transport.send(viewer, &message)?;
+        }
+        Ok(())
+    }
+
+    /// Stops sending to a viewer, until it is heard from again : then it starts over from a keyframe.
+    fn drop_viewer(&mut self, viewer: PeerId) {
+        self.active.remove(&viewer);
+        self.acked.remove(&viewer);
+        self.chunks_acked.remove(&viewer);
+    }
+}
+
+/// Transient state broadcast, viewer side : patches a local quad with the snapshots of the host,
+/// and sends it the local actions.
+/// With a viewport, only the chunks covering it are kept up to date : the rest of the quad is stale.
+pub struct Viewer {
+    host: PeerId,
+    decoder: Decoder,
+    /// Last frame applied, older ones arriving late are dropped.
+    frame: Option<u64>,
+    viewport: Option<Region>,
+    last_viewport: Option<Instant>,
+    /// Decoder and last frame applied of each chunk in view.
+    chunks: BTreeMap<ChunkId, (Decoder, Option<u64>)>,
+    received_bytes: u64,
+    /// Actions submitted since the last poll.
+    local: Vec<Action>,
+    last_rejection: Option<Rejection>,
+    last_hello: Option<Instant>,
+}
+
+impl Viewer {
+    pub fn new(host: PeerId) -> Self {
+        Self {
+            host,
+            decoder: Decoder::default(),
+            frame: None,
+            viewport: None,
+            last_viewport: None,
+            chunks: BTreeMap::new(),
+            received_bytes: 0,
+            local: vec![],
+            last_rejection: None,
+            last_hello: None,
+        }
+    }
+
+    /// Largest board of the host accepted, `delta::MAX_SIZE` on each side by default.
+    pub fn with_max_board(self, width: u32, height: u32) -> Self {
+        Self {
+            decoder: self.decoder.with_max_size(width, height),
+            ..self
+        }
+    }
+
+    /// Queues a local action, for the host to validate. It shows in a later snapshot, if accepted.
+    pub fn submit(&mut self, action: Action) {
+        self.local.push(action);
+    }
+
+    /// Last action refused by the host.
+    pub fn last_rejection(&self) -> Option<Rejection> {
+        self.last_rejection
+    }
+
+    pub fn host(&self) -> PeerId {
+        self.host
+    }
+
+    /// Asks the host for the chunks covering these cells only, ie. the ones on screen.
+    pub fn set_viewport(&mut self, viewport: Region) {
+        if self.viewport == Some(viewport) {
+            return;
+        }
+        self.viewport = Some(viewport);
+        self.last_viewport = None;
+        // the host forgets them too
+        let visible = chunk::covering(viewport, u32::MAX as usize, u32::MAX as usize);
+        self.chunks.retain(|chunk, _| visible.contains(chunk));
+    }
+
+    pub fn viewport(&self) -> Option<Region> {
+        self.viewport
+    }
+
+    /// Bytes of snapshots received so far.
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes
+    }
+
+    /// Applies the snapshots received to the quad, resizing it to the host's if needed.
+    /// Returns whether the quad was updated.
+    pub fn poll(
+        &mut self,
+        quad: &mut Quad,
+        transport: &mut (impl Transport + ?Sized),
+    ) -> io::Result<bool> {
+        // announced until the first snapshot : over udp, the host would not know about us
+        if self.frame.is_none()
+            && self.chunks.is_empty()
+            && self.last_hello.is_none_or(|t| t.elapsed() >= HELLO_DELAY)
+        {
+            self.last_hello = Some(Instant::now());
+            transport.send(
+                self.host,
+                &Message::Hello {
+                    version: PROTOCOL_VERSION,
+                },
+            )?;
+        }
+        if let Some(viewport) = self.viewport {
+            if self
+                .last_viewport
+                .is_none_or(|t| t.elapsed() >= VIEWPORT_DELAY)
+            {
+                self.last_viewport = Some(Instant::now());
+                transport.send(self.host, &Message::Viewport(viewport))?;
+            }
+        }
+        if !self.local.is_empty() {
+            let inputs = Message::Inputs {
+                generation: quad.generation(),
+                actions: std::mem::take(&mut self.local),
+            };
+            transport.send(self.host, &inputs)?;
+        }
+
+        let mut updated = false;
+        while let Some((from, message)) = transport.receive()? {
+            if from != self.host {
+                continue;
+            }
+            if matches!(
+                message,
+                Message::Snapshot(_) | Message::ChunkSnapshot { .. }
+            ) {
+                self.received_bytes += message.to_bytes().len() as u64;
+            }
+            let frame = match message {
+                Message::Snapshot(frame) => frame,
+                Message::ChunkSnapshot {
+                    width,
+                    height,
+                    chunk,
+                    frame,
+                } => {
+                    match self.apply_chunk(quad, (width, height), chunk, &frame) {
+                        Ok(false) => {}
+                        Ok(true) => {
+                            updated = true;
+                            transport.send(
+                                self.host,
+                                &Message::ChunkAck {
+                                    chunk,
+                                    frame: frame.id,
+                                },
+                            )?;
+                        }
+                        // ie. the chunk left the view and came back before the host knew :
+                        // every chunk is sent whole again
+                        Err(_) => {
+                            self.chunks.clear();
+                            transport.send(
+                                self.host,
+                                &Message::Hello {
+                                    version: PROTOCOL_VERSION,
+                                },
+                            )?;
+                        }
+                    }
+                    continue;
+                }
+                Message::Rejected(rejection) => {
+                    self.last_rejection = Some(rejection);
+                    continue;
+                }
+                _ => continue,
+            };
+            if self.frame.is_some_and(|f| f >= frame.id) {
+                continue;
+            }
+            match self.decoder.apply(&frame, quad) {
+                Ok(id) => {
+                    self.frame = Some(id);
+                    updated = true;
+                    transport.send(self.host, &Message::SnapshotAck { frame: id })?;
+                }
+                // ie. we restarted, and the host sends deltas from frames we never saw
+                Err(_) => transport.send(
+                    self.host,
+                    &Message::Hello {
+                        version: PROTOCOL_VERSION,
+                    },
+                )?,
+            }
+        }
+        Ok(updated)
+    }
+
+    /// Applies a chunk in view, resizing the quad to the host's board if needed.
+    /// Returns whether it was applied, to acknowledge it : chunks out of view and late ones are dropped.
+    /// The board and the chunk are checked before the quad is resized : they come from the network.
+    fn apply_chunk(
+        &mut self,
+        quad: &mut Quad,
+        (width, height): (u32, u32),
+        chunk: ChunkId,
+        frame: &Frame,
+    ) -> Result<bool, DeltaError> {
+        let max = self.decoder.max_size();
+        if width > max.0 || height > max.1 {
+            return Err(DeltaError::TooLarge(width, height));
+        }
+        let (width, height) = (width as usize, height as usize);
+        let visible = self
+            .viewport
+            .is_some_and(|v| chunk::covering(v, width, height).contains(&chunk));
+        if !visible {
+            return Ok(false);
+        }
+        let region = chunk::region(chunk, width, height);
+        if (frame.width, frame.height) != (region.width, region.height) {
+            return Err(DeltaError::Corrupted(format!(
+                "{}x{} frame for chunk {:?} of a {}x{} board",
+                frame.width, frame.height, chunk, width, height
+            )));
+        }
+        if (quad.width(), quad.height()) != (width, height) {
+            // the host's board is not ours
+            *quad = Quad::new(Grid::init(height, width, cell::State::Dead))
+                .with_rule(quad.rule())
+                .with_topology(quad.topology());
+            self.chunks.clear();
+        }
+        let (decoder, last) = self
+            .chunks
+            .entry(chunk)
+            .or_insert_with(|| (Decoder::default().with_max_size(max.0, max.1), None));
+        if last.is_some_and(|l| l >= frame.id) {
+            return Ok(false);
+        }
+        *last = Some(decoder.apply_region(frame, quad, region.x, region.y)?);
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::broadcast::{Broadcaster, Viewer};
+    use crate::net::local::LocalNetwork;
+    use crate::net::message::Message;
+    use crate::net::sim::{Conditions, SimulatedTransport};
+    use crate::net::udp::UdpTransport;
+    use crate::net::{PeerId, Transport};
+    use quadlife::cell::State;
+    use quadlife::chunk;
+    use quadlife::delta::{DeltaError, Encoder};
+    use quadlife::desync::Region;
+    use quadlife::event::{Action, Game};
+    use quadlife::placement::Policy;
+    use quadlife::quad::Quad;
+    use quadlife::tribe::Tribe;
+    use std::collections::BTreeMap;
+    use std::io;
+    use std::net::UdpSocket;
+    use std::time::{Duration, Instant};
+
+    fn udp_pair() -> (UdpTransport, UdpTransport) {
+        let (host, client) = (
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+            UdpSocket::bind("127.0.0.1:0").unwrap(),
+        );
+        let addrs = [host.local_addr().unwrap(), client.local_addr().unwrap()];
+        (
+            UdpTransport::with_socket(0, host, BTreeMap::from([(1, addrs[1])])).unwrap(),
+            UdpTransport::with_socket(1, client, BTreeMap::from([(0, addrs[0])])).unwrap(),
+        )
+    }
+
+    /// Polls both sides until the viewer shows the host's state.
+    fn sync(
+        broadcaster: &mut Broadcaster,
+        game: &Game,
+        host: &mut impl Transport,
+        viewer: &mut Viewer,
+        remote: &mut Quad,
+        client: &mut impl Transport,
+    ) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while remote.checksum() != game.quad().checksum()
+            || remote.generation() != game.quad().generation()
+        {
+            assert!(Instant::now() < deadline, "viewer never caught up");
+            broadcaster.poll(game.quad(), host).unwrap();
+            viewer.poll(remote, client).unwrap();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[test]
+    fn check_viewer_follows_host() {
+        let (mut host, mut client) = udp_pair();
+        let mut game = Game::new(
+            Quad::gen(State::Dead, 64, 48).with_seed(5),
+            Policy::UNLIMITED,
+        );
+        let mut broadcaster = Broadcaster::new([1]);
+        let mut viewer = Viewer::new(0);
+        let mut remote = Quad::gen(State::Dead, 1, 1);
+
+        for g in 0..30 {
+            if g == 10 {
+                game.quad_mut().place([(5, 5), (6, 5), (7, 5)], Tribe(2));
+            }
+            sync(
+                &mut broadcaster,
+                &game,
+                &mut host,
+                &mut viewer,
+                &mut remote,
+                &mut client,
+            );
+            game.step().unwrap();
+        }
+        assert_eq!((remote.width(), remote.height()), (64, 48));
+        assert!(broadcaster.sent_bytes() > 0);
+        assert!(viewer.received_bytes() <= broadcaster.sent_bytes());
+
+        // a restarted viewer asks for a keyframe
+        let mut viewer = Viewer::new(0);
+        let mut remote = Quad::gen(State::Dead, 1, 1);
+        game.step().unwrap();
+        sync(
+            &mut broadcaster,
+            &game,
+            &mut host,
+            &mut viewer,
+            &mut remote,
+            &mut client,
+        );
+    }
+
+    #[test]
+    fn check_viewer_follows_host_over_a_bad_network() {
+        let network = LocalNetwork::default();
+        let conditions = Conditions {
+            latency: Duration::from_millis(10),
+            jitter: Duration::from_millis(20),
+            loss: 0.2,
+            duplication: 0.1,
+            reordering: 0.2,
+        };
+        let mut host = SimulatedTransport::new(network.join(0), conditions, 1);
+        let mut client = SimulatedTransport::new(network.join(1), conditions, 2);
+        let mut game = Game::new(
+            Quad::gen(State::Dead, 32, 32).with_seed(5),
+            Policy::UNLIMITED,
+        );
+        let mut broadcaster = Broadcaster::new([1]);
+        let mut viewer = Viewer::new(0);
+        let mut remote = Quad::gen(State::Dead, 1, 1);
+
+        for _ in 0..20 {
+            sync(
+                &mut broadcaster,
+                &game,
+                &mut host,
+                &mut viewer,
+                &mut remote,
+                &mut client,
+            );
+            game.step().unwrap();
+        }
+    }
+
+    /// Whether the viewer shows the host's cells in this area.
+    fn same_area(host: &Quad, remote: &Quad, area: Region) -> bool {
+        (remote.width(), remote.height()) == (host.width(), host.height())
+            && (area.y..area.y + area.height).all(|row| {
+                (area.x..area.x + area.width).all(|col| {
+                    let (row, col) = (row as usize, col as usize);
+                    remote.cells()[(row, col)] == host.cells()[(row, col)]
+                        && remote.owners()[(row, col)] == host.owners()[(row, col)]
+                })
+            })
+    }
+
+    #[test]
+    fn check_viewport_subscriptions() {
+        let network = LocalNetwork::default();
+        let mut host = network.join(0);
+        let mut client = network.join(1);
+        let mut other = network.join(2);
+        let mut game = Game::new(
+            Quad::gen(State::Dead, 256, 256).with_seed(9),
+            Policy::UNLIMITED,
+        );
+        let mut broadcaster = Broadcaster::new([1, 2]);
+        // one viewer looks at a corner, the other at everything
+        let mut viewer = Viewer::new(0);
+        let mut remote = Quad::gen(State::Dead, 1, 1);
+        let mut whole = Viewer::new(0);
+        let mut whole_remote = Quad::gen(State::Dead, 1, 1);
+        let corner = Region {
+            x: 10,
+            y: 20,
+            width: 50,
+            height: 40,
+        };
+        let far = Region {
+            x: 200,
+            y: 200,
+            width: 56,
+            height: 56,
+        };
+
+        // then panning : the chunks coming into view are sent whole
+        for viewport in [corner, far] {
+            viewer.set_viewport(viewport);
+            for _ in 0..10 {
+                let deadline = Instant::now() + Duration::from_secs(5);
+                while !same_area(game.quad(), &remote, viewport)
+                    || whole_remote.checksum() != game.quad().checksum()
+                {
+                    assert!(Instant::now() < deadline, "viewers never caught up");
+                    broadcaster.poll(game.quad(), &mut host).unwrap();
+                    viewer.poll(&mut remote, &mut client).unwrap();
+                    whole.poll(&mut whole_remote, &mut other).unwrap();
+                }
+                game.step().unwrap();
+            }
+            assert_eq!(broadcaster.viewport(1), Some(viewport));
+            if viewport == corner {
+                assert_eq!((remote.width(), remote.height()), (256, 256));
+                // the 4 chunks in view only
+                assert!(viewer.received_bytes() * 8 < whole.received_bytes());
+                assert!(!same_area(game.quad(), &remote, far));
+            }
+        }
+        // the chunks out of view are not updated anymore
+        assert!(!same_area(game.quad(), &remote, corner));
+    }
+
+    #[test]
+    fn check_chunks_must_fit_the_board() {
+        let host = Quad::gen(State::Dead, 40, 40).with_seed(3);
+        let region = chunk::region((1, 0), 40, 40);
+        let frame = Encoder::default().encode_region(&host, region, None);
+        let mut viewer = Viewer::new(0);
+        viewer.set_viewport(Region {
+            x: 0,
+            y: 0,
+            width: 64,
+            height: 64,
+        });
+        let mut remote = Quad::gen(State::Dead, 8, 8);
+
+        assert_eq!(
+            viewer.apply_chunk(&mut remote, (u32::MAX, u32::MAX), (1, 0), &frame),
+            Err(DeltaError::TooLarge(u32::MAX, u32::MAX))
+        );
+        let mut small = Viewer::new(0).with_max_board(32, 32);
+        small.set_viewport(viewer.viewport().unwrap());
+        assert_eq!(
+            small.apply_chunk(&mut remote, (40, 40), (1, 0), &frame),
+            Err(DeltaError::TooLarge(40, 40))
+        );
+        // the chunk (0, 0) is 32x32
+        assert!(matches!(
+            viewer.apply_chunk(&mut remote, (40, 40), (0, 0), &frame),
+            Err(DeltaError::Corrupted(_))
+        ));
+        assert_eq!((remote.width(), remote.height()), (8, 8));
+
+        assert_eq!(
+            viewer.apply_chunk(&mut remote, (40, 40), (1, 0), &frame),
+            Ok(true)
+        );
+        assert_eq!((remote.width(), remote.height()), (40, 40));
+        assert!(same_area(&host, &remote, region));
+    }
+
+    /// Refuses to send anything to one peer.
+    struct Broken<T> {
+        inner: T,
+        broken: PeerId,
+    }
+
+    impl<T: Transport> Transport for Broken<T> {
+        fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()> {
+            if to == self.broken {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "too large"));
+            }
+            self.inner.send(to, message)
+        }
+
+        fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>> {
+            self.inner.receive()
+        }
+    }
+
+    #[test]
+    fn check_a_failing_viewer_does_not_stop_the_others() {
+        let network = LocalNetwork::default();
+        let mut host = Broken {
+            inner: network.join(0),
+            broken: 1,
+        };
+        let mut clients = [network.join(1), network.join(2)];
+        let game = Game::new(
+            Quad::gen(State::Dead, 16, 16).with_seed(5),
+            Policy::UNLIMITED,
+        );
+        let mut broadcaster = Broadcaster::new([1, 2]);
+        let mut viewers = [Viewer::new(0), Viewer::new(0)];
+        let mut remotes = [Quad::gen(State::Dead, 1, 1), Quad::gen(State::Dead, 1, 1)];
+
+        for (i, viewer) in viewers.iter_mut().enumerate() {
+            viewer.submit(Action::Place {
+                tribe: Tribe(i as u8),
+                cells: vec![(1, 1)],
+            });
+            viewer.poll(&mut remotes[i], &mut clients[i]).unwrap();
+        }
+        let actions = broadcaster.poll(game.quad(), &mut host).unwrap();
+
+        assert_eq!(
+            actions.iter().map(|(from, _)| *from).collect::<Vec<_>>(),
+            vec![1, 2]
+        );
+        assert_eq!(broadcaster.active().collect::<Vec<_>>(), vec![2]);
+        assert!(viewers[1].poll(&mut remotes[1], &mut clients[1]).unwrap());
+        assert_eq!(remotes[1].checksum(), game.quad().checksum());
+    }
+
+    #[test]
+    fn check_rate_limit() {
+        let (mut host, mut client) = udp_pair();
+        let mut game = Game::new(
+            Quad::gen(State::Dead, 16, 16).with_seed(5),
+            Policy::UNLIMITED,
+        );
+        let mut broadcaster = Broadcaster::new([1]).with_rate(1);
+        let mut viewer = Viewer::new(0);
+        let mut remote = Quad::gen(State::Dead, 16, 16);
+
+        // nothing sent before the viewer shows up
+        broadcaster.poll(game.quad(), &mut host).unwrap();
+        assert_eq!(broadcaster.sent_bytes(), 0);
+        viewer.poll(&mut remote, &mut client).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while broadcaster.sent_bytes() == 0 {
+            assert!(Instant::now() < deadline, "viewer never showed up");
+            broadcaster.poll(game.quad(), &mut host).unwrap();
+        }
+        let sent = broadcaster.sent_bytes();
+        for _ in 0..10 {
+            game.step().unwrap();
+            broadcaster.poll(game.quad(), &mut host).unwrap();
+            viewer.poll(&mut remote, &mut client).unwrap();
+        }
+        // a second has not passed yet
+        assert_eq!(broadcaster.sent_bytes(), sent);
+        assert_eq!(remote.generation(), 0);
+    }
+}
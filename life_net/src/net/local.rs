@@ -0,0 +1,53 @@
+use crate::net::message::Message;
+use crate::net::{PeerId, Transport};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+type Inboxes = Arc<Mutex<BTreeMap<PeerId, VecDeque<(PeerId, Message)>>>>;
+
+/// Peers in the same process, ie. for tests : every message is delivered, in order, with no delay.
+#[derive(Clone, Default)]
+pub struct LocalNetwork {
+    inboxes: Inboxes,
+}
+
+impl LocalNetwork {
+    /// The transport of a new peer on this network.
+    pub fn join(&self, me: PeerId) -> LocalTransport {
+        self.inboxes.lock().unwrap().entry(me).or_default();
+        LocalTransport {
+            me,
+            inboxes: self.inboxes.clone(),
+        }
+    }
+}
+
+pub struct LocalTransport {
+    me: PeerId,
+    inboxes: Inboxes,
+}
+
+impl Transport for LocalTransport {
+    fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()> {
+        match self.inboxes.lock().unwrap().get_mut(&to) {
+            Some(inbox) => {
+                inbox.push_back((self.me, message.clone()));
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown peer {}", to),
+            )),
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>> {
+        Ok(self
+            .inboxes
+            .lock()
+            .unwrap()
+            .get_mut(&self.me)
+            .and_then(|inbox| inbox.pop_front()))
+    }
+}
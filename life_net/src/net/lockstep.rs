@@ -0,0 +1,674 @@
+use crate::net::message::Message;
+use crate::net::{PeerId, Transport};
+use quadlife::desync::{DesyncDetector, DesyncEvent};
+use quadlife::event::{Action, Event};
+use quadlife::quad::Quad;
+use quadlife::tribe::{Tribe, MAX_TRIBES};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Delay before unacknowledged inputs are sent again.
+const RESEND_DELAY: Duration = Duration::from_millis(100);
+
+/// Lockstep synchronisation : every peer sends a bundle of inputs (maybe empty) for every generation,
+/// and a generation is only played once the bundles of all peers for it are in.
+/// All peers then apply the exact same events, at the same generation.
+///
+/// Local actions are sent for the generation `input_delay` generations ahead of the one being played,
+/// so that they have time to reach the other peers before they are needed.
+pub struct Lockstep {
+    me: PeerId,
+    others: BTreeSet<PeerId>,
+    input_delay: u64,
+    /// Next generation to play.
+    generation: u64,
+    /// Actions submitted since the last bundle.
+    local: Vec<Action>,
+    /// Bundles not played yet, by generation then peer (ourself included).
+    bundles: BTreeMap<u64, BTreeMap<PeerId, Vec<Action>>>,
+    /// Our bundles, until every peer acknowledged them.
+    outbox: BTreeMap<u64, Vec<Action>>,
+    /// For each other peer, we have all its bundles before this generation.
+    received_until: BTreeMap<PeerId, u64>,
+    /// For each other peer, it has all our bundles before this generation.
+    acked_until: BTreeMap<PeerId, u64>,
+    /// Peers to send all our unacknowledged bundles to, right away.
+    resend_to: BTreeSet<PeerId>,
+    last_resend: Instant,
+    outgoing: Vec<(PeerId, Message)>,
+    /// Exchanges checkpoints with the other peers, if enabled.
+    desync: Option<DesyncDetector>,
+    desync_events: Vec<DesyncEvent>,
+}
+
+impl Lockstep {
+    /// An input delay of 0 is the same as 1 : actions cannot be applied to the generation being played.
+    pub fn new(me: PeerId, others: impl IntoIterator<Item = PeerId>, input_delay: u64) -> Self {
+        let others: BTreeSet<PeerId> = others.into_iter().filter(|p| *p != me).collect();
+        let input_delay = input_delay.max(1);
+        Self {
+            me,
+            received_until: others.iter().map(|p| (*p, input_delay)).collect(),
+            acked_until: others.iter().map(|p| (*p, input_delay)).collect(),
+            others,
+            input_delay,
+            generation: 0,
+            local: vec![],
+            bundles: BTreeMap::new(),
+            outbox: BTreeMap::new(),
+            resend_to: BTreeSet::new(),
+            last_resend: Instant::now(),
+            outgoing: vec![],
+            desync: None,
+            desync_events: vec![],
+        }
+    }
+
+    /// Exchanges checkpoints every `interval` generations, see `check`.
+    /// Checkpoints are not resent : a lost one only delays the detection to the next.
+    pub fn with_desync_detection(self, interval: u64) -> Self {
+        Self {
+            desync: Some(DesyncDetector::new(interval)),
+            ..self
+        }
+    }
+
+    pub fn me(&self) -> PeerId {
+        self.me
+    }
+
+    /// Next generation to be played.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn input_delay(&self) -> u64 {
+        self.input_delay
+    }
+
+    /// Queues a local action, for the next bundle.
+    pub fn submit(&mut self, action: Action) {
+        self.local.push(action);
+    }
+
+    /// Peers whose inputs for the next generation are still missing.
+    pub fn waiting_for(&self) -> Vec<PeerId> {
+        if self.generation < self.input_delay {
+            return vec![];
+        }
+        let bundle = self.bundles.get(&self.generation);
+        self.others
+            .iter()
+            .filter(|p| bundle.is_none_or(|b| !b.contains_key(p)))
+            .copied()
+            .collect()
+    }
+
+    /// To be called with each generation played : sends our checkpoints, when desync detection is enabled.
+    pub fn check(&mut self, quad: &Quad) {
+        let Some(detector) = &mut self.desync else {
+            return;
+        };
+        if let Some(checkpoint) = detector.observe(quad) {
+            for peer in self.others.iter() {
+                self.outgoing
+                    .push((*peer, Message::Checkpoint(checkpoint.clone())));
+            }
+        }
+        self.collect_desync_events();
+    }
+
+    /// Desyncs detected since the last call, and later the cells that differ.
+    pub fn take_desync_events(&mut self) -> Vec<DesyncEvent> {
+        std::mem::take(&mut self.desync_events)
+    }
+
+    /// Asks the desynced peers for the first region differing.
+    fn collect_desync_events(&mut self) {
+        let Some(detector) = &mut self.desync else {
+            return;
+        };
+        for event in detector.take_events() {
+            if let DesyncEvent::Detected {
+                peer,
+                generation,
+                region: Some(region),
+                ..
+            } = event
+            {
+                self.outgoing
+                    .push((peer, Message::RegionRequest { generation, region }));
+            }
+            self.desync_events.push(event);
+        }
+    }
+
+    /// Receives and sends whatever is possible. To be called often, even when waiting for nothing.
+    pub fn poll(&mut self, transport: &mut (impl Transport + ?Sized)) -> io::Result<()> {
+        while let Some((from, message)) = transport.receive()? {
+            self.handle(from, message);
+        }
+        self.collect_desync_events();
+
+        if self.last_resend.elapsed() >= RESEND_DELAY {
+            self.last_resend = Instant::now();
+            self.resend_to.extend(self.others.iter().copied());
+        }
+        for peer in std::mem::take(&mut self.resend_to) {
+            let from = self.acked_until[&peer];
+            for (generation, actions) in self.outbox.range(from..) {
+                self.outgoing.push((
+                    peer,
+                    Message::Inputs {
+                        generation: *generation,
+                        actions: actions.clone(),
+                    },
+                ));
+            }
+        }
+
+        for (to, message) in self.outgoing.drain(..) {
+            transport.send(to, &message)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, from: PeerId, message: Message) {
+        if !self.others.contains(&from) {
+            return;
+        }
+        match message {
+            Message::Hello { .. } => {
+                // (re)connected : whatever was sent before may be lost
+                self.resend_to.insert(from);
+            }
+            Message::Inputs {
+                generation,
+                actions,
+            } => {
+                let until = self.received_until.get_mut(&from).unwrap();
+                if generation >= *until {
+                    self.bundles
+                        .entry(generation)
+                        .or_default()
+                        .insert(from, actions);
+                    while self
+                        .bundles
+                        .get(until)
+                        .is_some_and(|b| b.contains_key(&from))
+                    {
+                        *until += 1;
+                    }
+                }
+                // acknowledged every time, the previous ack may have been lost
+                self.outgoing.push((from, Message::Ack { until: *until }));
+            }
+            Message::Ack { until } => {
+                let acked = self.acked_until.get_mut(&from).unwrap();
+                *acked = (*acked).max(until);
+                let all_acked = self.acked_until.values().min().copied().unwrap_or(u64::MAX);
+                self.outbox.retain(|generation, _| *generation >= all_acked);
+            }
+            Message::Checkpoint(checkpoint) => {
+                if let Some(detector) = &mut self.desync {
+                    detector.receive(from, checkpoint);
+                }
+            }
+            Message::RegionRequest { generation, region } => {
+                if let Some(state) = self
+                    .desync
+                    .as_ref()
+                    .and_then(|d| d.region_state(generation, region))
+                {
+                    self.outgoing.push((from, Message::RegionState(state)));
+                }
+            }
+            Message::RegionState(state) => {
+                if let Some(detector) = &mut self.desync {
+                    detector.diff(from, &state);
+                }
+            }
+            // transient state broadcasts and servers are no part of a lockstep game
+            Message::Snapshot(_)
+            | Message::SnapshotAck { .. }
+            | Message::Rejected(_)
+            | Message::Viewport(_)
+            | Message::ChunkSnapshot { .. }
+            | Message::ChunkAck { .. } => {}
+        }
+    }
+
+    /// Plays the next generation, if the inputs of all peers are in :
+    /// returns its events, in the same order on all peers, and sends our inputs for a later generation.
+    pub fn advance(&mut self) -> Option<Vec<Event>> {
+        if !self.waiting_for().is_empty() {
+            return None;
+        }
+        let generation = self.generation;
+        let events: Vec<Event> = self
+            .bundles
+            .remove(&generation)
+            .unwrap_or_default()
+            .into_iter()
+            .flat_map(|(peer, actions)| actions.into_iter().filter(move |a| Self::allowed(peer, a)))
+            .map(|action| Event { generation, action })
+            .collect();
+        self.generation += 1;
+
+        let target = generation + self.input_delay;
+        let bundle = std::mem::take(&mut self.local);
+        self.bundles
+            .entry(target)
+            .or_default()
+            .insert(self.me, bundle.clone());
+        for peer in self.others.iter() {
+            self.outgoing.push((
+                *peer,
+                Message::Inputs {
+                    generation: target,
+                    actions: bundle.clone(),
+                },
+            ));
+        }
+        if !self.others.is_empty() {
+            self.outbox.insert(target, bundle);
+        }
+
+        Some(events)
+    }
+
+    /// A peer may only place cells of its own tribe, if it has one.
+    pub(crate) fn allowed(peer: PeerId, action: &Action) -> bool {
+        match action {
+            Action::Place { tribe, .. } => *tribe == Tribe(peer) && (peer as usize) < MAX_TRIBES,
+            _ => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::local::{LocalNetwork, LocalTransport};
+    use crate::net::lockstep::Lockstep;
+    use crate::net::message::Message;
+    use crate::net::sim::{Conditions, SimulatedTransport};
+    use crate::net::tcp::TcpTransport;
+    use crate::net::udp::UdpTransport;
+    use crate::net::{PeerId, Transport};
+    use quadlife::cell::State;
+    use quadlife::desync::DesyncEvent;
+    use quadlife::event::{Action, Game};
+    use quadlife::placement::Policy;
+    use quadlife::quad::Quad;
+    use quadlife::tribe::Tribe;
+    use std::collections::{BTreeMap, VecDeque};
+    use std::io;
+    use std::net::{SocketAddr, TcpListener, UdpSocket};
+    use std::time::{Duration, Instant};
+
+    /// In memory transport, delivering everything, in order.
+    #[derive(Default)]
+    struct Mailbox {
+        inbox: VecDeque<(PeerId, Message)>,
+        outbox: Vec<(PeerId, Message)>,
+    }
+
+    impl Transport for Mailbox {
+        fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()> {
+            self.outbox.push((to, message.clone()));
+            Ok(())
+        }
+
+        fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>> {
+            Ok(self.inbox.pop_front())
+        }
+    }
+
+    fn deliver(from: PeerId, boxes: &mut BTreeMap<PeerId, Mailbox>) {
+        let sent = std::mem::take(&mut boxes.get_mut(&from).unwrap().outbox);
+        for (to, message) in sent {
+            boxes.get_mut(&to).unwrap().inbox.push_back((from, message));
+        }
+    }
+
+    #[test]
+    fn check_waits_for_all_inputs() {
+        let mut boxes: BTreeMap<PeerId, Mailbox> =
+            [(0, Mailbox::default()), (1, Mailbox::default())].into();
+        let mut a = Lockstep::new(0, [0, 1], 2);
+        let mut b = Lockstep::new(1, [0, 1], 2);
+
+        // the first generations have no inputs by definition
+        assert!(a.advance().is_some());
+        assert!(a.advance().is_some());
+        a.submit(Action::Place {
+            tribe: Tribe(0),
+            cells: vec![(1, 1)],
+        });
+        assert_eq!(a.generation(), 2);
+        assert_eq!(a.advance(), None);
+        assert_eq!(a.waiting_for(), vec![1]);
+
+        b.advance().unwrap();
+        b.poll(boxes.get_mut(&1).unwrap()).unwrap();
+        deliver(1, &mut boxes);
+        a.poll(boxes.get_mut(&0).unwrap()).unwrap();
+        deliver(0, &mut boxes);
+        b.poll(boxes.get_mut(&1).unwrap()).unwrap();
+
+        // generation 2 got the bundles sent when playing generation 0 : empty
+        assert_eq!(a.advance(), Some(vec![]));
+        b.advance().unwrap();
+        b.advance().unwrap();
+        b.poll(boxes.get_mut(&1).unwrap()).unwrap();
+        deliver(1, &mut boxes);
+        a.poll(boxes.get_mut(&0).unwrap()).unwrap();
+        deliver(0, &mut boxes);
+        b.poll(boxes.get_mut(&1).unwrap()).unwrap();
+        assert_eq!(a.advance(), Some(vec![]));
+
+        // our action, submitted before playing generation 2, is played at generation 4 everywhere
+        let events = a.advance().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].generation, 4);
+        b.advance().unwrap();
+        assert_eq!(b.advance().unwrap(), events);
+    }
+
+    #[test]
+    fn check_cannot_place_for_another_tribe() {
+        let mut solo = Lockstep::new(3, [3], 1);
+        solo.submit(Action::Place {
+            tribe: Tribe(0),
+            cells: vec![(1, 1)],
+        });
+        solo.submit(Action::Place {
+            tribe: Tribe(3),
+            cells: vec![(1, 1)],
+        });
+        solo.advance().unwrap();
+        assert_eq!(solo.advance().unwrap().len(), 1);
+    }
+
+    /// A peer playing a small game, acting now and then.
+    struct Player {
+        lockstep: Lockstep,
+        game: Game,
+        desyncs: Vec<DesyncEvent>,
+    }
+
+    impl Player {
+        fn new(me: PeerId, peers: &[PeerId], input_delay: u64) -> Self {
+            Self {
+                lockstep: Lockstep::new(me, peers.iter().copied(), input_delay)
+                    .with_desync_detection(8),
+                game: Game::new(
+                    Quad::gen(State::Dead, 32, 32).with_seed(7),
+                    Policy::default(),
+                ),
+                desyncs: vec![],
+            }
+        }
+
+        fn update(&mut self, transport: &mut dyn Transport) {
+            self.lockstep.poll(transport).unwrap();
+            while let Some(events) = self.lockstep.advance() {
+                let generation = self.lockstep.generation();
+                let me = self.lockstep.me();
+                if generation % 5 == me as u64 {
+                    let x = (generation as i64 * 7 + me as i64 * 11) % 32;
+                    self.lockstep.submit(Action::Place {
+                        tribe: Tribe(me),
+                        cells: vec![(x, 10), (x, 11), (x, 12)],
+                    });
+                }
+                for e in events {
+                    self.game.schedule(e);
+                }
+                self.game.sync();
+                self.game.step().unwrap();
+                self.lockstep.check(self.game.quad());
+            }
+            self.desyncs.extend(self.lockstep.take_desync_events());
+        }
+    }
+
+    /// Runs peers until they all reach `generations`, calling `disturb` on the way.
+    fn play<T: Transport>(
+        transports: &mut [T],
+        input_delay: u64,
+        generations: u64,
+        mut disturb: impl FnMut(&mut [T], &mut [Player], u64),
+    ) -> Vec<Player> {
+        let ids: Vec<PeerId> = (0..transports.len() as PeerId).collect();
+        let mut players: Vec<Player> = ids
+            .iter()
+            .map(|id| Player::new(*id, &ids, input_delay))
+            .collect();
+
+        let deadline = Instant::now() + Duration::from_secs(20);
+        while players
+            .iter()
+            .any(|p| p.game.quad().generation() < generations)
+        {
+            assert!(Instant::now() < deadline, "peers are stuck");
+            for (p, t) in players.iter_mut().zip(transports.iter_mut()) {
+                if p.game.quad().generation() < generations {
+                    p.update(t);
+                } else {
+                    // done, but still answering the others
+                    p.lockstep.poll(t).unwrap();
+                    p.desyncs.extend(p.lockstep.take_desync_events());
+                }
+            }
+            let slowest = players
+                .iter()
+                .map(|p| p.game.quad().generation())
+                .min()
+                .unwrap();
+            disturb(transports, &mut players, slowest);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        players
+    }
+
+    fn assert_in_sync(players: &[Player], generations: u64) {
+        let reference = &players[0].game;
+        // not trivial : every peer placed something
+        assert_eq!(tribes(reference), players.len());
+        for p in players {
+            assert_eq!(p.desyncs, vec![]);
+            let checksums: Vec<(u64, u64)> = p
+                .game
+                .log()
+                .checksums
+                .iter()
+                .filter(|(g, _)| *g <= generations)
+                .copied()
+                .collect();
+            let expected: Vec<(u64, u64)> = reference
+                .log()
+                .checksums
+                .iter()
+                .filter(|(g, _)| *g <= generations)
+                .copied()
+                .collect();
+            assert_eq!(checksums, expected);
+        }
+    }
+
+    fn tribes(game: &Game) -> usize {
+        let mut owners: Vec<Tribe> = game.quad().owners().iter().flatten().copied().collect();
+        owners.sort();
+        owners.dedup();
+        owners.len()
+    }
+
+    /// Listeners bound by the system, kept until the transports take them.
+    fn localhost(n: usize) -> Vec<TcpListener> {
+        (0..n)
+            .map(|_| TcpListener::bind("127.0.0.1:0").unwrap())
+            .collect()
+    }
+
+    fn tcp_peers(n: usize) -> Vec<TcpTransport> {
+        let listeners = localhost(n);
+        let addrs: Vec<SocketAddr> = listeners.iter().map(|l| l.local_addr().unwrap()).collect();
+        listeners
+            .into_iter()
+            .enumerate()
+            .map(|(i, l)| TcpTransport::with_listener(i as PeerId, l, others(&addrs, i)).unwrap())
+            .collect()
+    }
+
+    fn others(addrs: &[SocketAddr], me: usize) -> BTreeMap<PeerId, SocketAddr> {
+        addrs
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != me)
+            .map(|(i, a)| (i as PeerId, *a))
+            .collect()
+    }
+
+    #[test]
+    fn check_three_peers_over_udp() {
+        let sockets: Vec<UdpSocket> = (0..3)
+            .map(|_| UdpSocket::bind("127.0.0.1:0").unwrap())
+            .collect();
+        let addrs: Vec<SocketAddr> = sockets.iter().map(|s| s.local_addr().unwrap()).collect();
+        let mut transports: Vec<UdpTransport> = sockets
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| UdpTransport::with_socket(i as PeerId, s, others(&addrs, i)).unwrap())
+            .collect();
+
+        let players = play(&mut transports, 2, 40, |_, _, _| {});
+
+        assert_in_sync(&players, 40);
+    }
+
+    #[test]
+    fn check_three_peers_over_tcp_with_reconnection() {
+        let mut transports = tcp_peers(3);
+
+        let mut dropped = false;
+        let players = play(&mut transports, 3, 40, |t, _, generation| {
+            if generation == 15 && !dropped {
+                dropped = true;
+                t[2].disconnect(0);
+                t[1].disconnect(2);
+            }
+        });
+
+        assert!(dropped);
+        assert!(transports[2].is_connected(0));
+        assert_in_sync(&players, 40);
+    }
+
+    #[test]
+    fn check_desync_is_detected() {
+        let mut transports = tcp_peers(3);
+
+        let mut corrupted = false;
+        let players = play(&mut transports, 2, 40, |_, players, generation| {
+            if generation == 12 && !corrupted {
+                corrupted = true;
+                // a block of tribe 2, out of the rules
+                let quad = players[2].game.quad_mut();
+                quad.place([(20, 20), (21, 20), (20, 21), (21, 21)], Tribe(2));
+            }
+        });
+
+        assert!(corrupted);
+        for (me, p) in players.iter().enumerate() {
+            let detected: Vec<(u8, u64)> = p
+                .desyncs
+                .iter()
+                .filter_map(|e| match e {
+                    DesyncEvent::Detected {
+                        peer, generation, ..
+                    } => Some((*peer, *generation)),
+                    _ => None,
+                })
+                .collect();
+            // the corrupted peer disagrees with everyone, the others only with it
+            let expected: Vec<u8> = if me == 2 { vec![0, 1] } else { vec![2] };
+            let mut peers: Vec<u8> = detected.iter().map(|(peer, _)| *peer).collect();
+            peers.sort();
+            assert_eq!(peers, expected, "peer {}", me);
+            assert!(detected.iter().all(|(_, g)| *g == 16 || *g == 24));
+        }
+
+        // the diff shows the block
+        let cells: Vec<(u32, u32)> = players[0]
+            .desyncs
+            .iter()
+            .find_map(|e| match e {
+                DesyncEvent::Diff { cells, .. } => Some(cells.iter().map(|c| (c.x, c.y)).collect()),
+                _ => None,
+            })
+            .expect("no diff received");
+        assert!(cells.contains(&(20, 20)), "{:?}", cells);
+    }
+
+    /// Peers on an in-process network, as bad as `conditions`.
+    fn simulated(n: usize, conditions: Conditions) -> Vec<SimulatedTransport<LocalTransport>> {
+        let network = LocalNetwork::default();
+        (0..n)
+            .map(|i| SimulatedTransport::new(network.join(i as PeerId), conditions, i as u64))
+            .collect()
+    }
+
+    const BAD_NETWORK: Conditions = Conditions {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(20),
+        loss: 0.1,
+        duplication: 0.1,
+        reordering: 0.1,
+    };
+
+    #[test]
+    fn check_three_peers_over_a_bad_network() {
+        let mut transports = simulated(3, BAD_NETWORK);
+
+        let players = play(&mut transports, 3, 40, |_, _, _| {});
+
+        assert_in_sync(&players, 40);
+    }
+
+    #[test]
+    fn check_desync_is_detected_over_a_bad_network() {
+        let mut transports = simulated(3, BAD_NETWORK);
+
+        let mut corrupted = false;
+        let players = play(&mut transports, 3, 80, |_, players, generation| {
+            if generation >= 12 && !corrupted {
+                corrupted = true;
+                let quad = players[2].game.quad_mut();
+                quad.place([(20, 20), (21, 20), (20, 21), (21, 21)], Tribe(2));
+            }
+        });
+
+        assert!(corrupted);
+        // checkpoints may be lost : detected later, but detected
+        for (me, p) in players.iter().enumerate() {
+            let mut peers: Vec<u8> = p
+                .desyncs
+                .iter()
+                .filter_map(|e| match e {
+                    DesyncEvent::Detected {
+                        peer, generation, ..
+                    } => {
+                        assert!(*generation >= 16);
+                        Some(*peer)
+                    }
+                    _ => None,
+                })
+                .collect();
+            peers.sort();
+            let expected: Vec<u8> = if me == 2 { vec![0, 1] } else { vec![2] };
+            assert_eq!(peers, expected, "peer {}", me);
+        }
+    }
+}
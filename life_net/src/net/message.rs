@@ -0,0 +1,306 @@
+use crate::net::PeerId;
+use quadlife::chunk::ChunkId;
+use quadlife::codec::{Decode, DecodeError, Encode};
+use quadlife::delta::Frame;
+use quadlife::desync::{Checkpoint, Region, RegionState};
+use quadlife::event::Action;
+use quadlife::placement::Rejection;
+
+/// Bumped on any change of the encoding.
+pub const PROTOCOL_VERSION: u8 = 5;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Message {
+    /// A connection to the sender was (re)established.
+    Hello {
+        version: u8,
+    },
+    /// Actions of the sender, to apply at `generation`.
+    Inputs {
+        generation: u64,
+        actions: Vec<Action>,
+    },
+    /// The sender has received all inputs of the receiver for the generations before `until`.
+    Ack {
+        until: u64,
+    },
+    /// State of the sender's quad, in a transient state broadcast.
+    Snapshot(Frame),
+    /// The sender has applied the snapshot `frame` : it can be the base of the next deltas.
+    SnapshotAck {
+        frame: u64,
+    },
+    /// Checksums of the sender's state, to detect desyncs.
+    Checkpoint(Checkpoint),
+    /// Asks for the receiver's cells of a region at a checkpoint, to see how they differ.
+    RegionRequest {
+        generation: u64,
+        region: Region,
+    },
+    RegionState(RegionState),
+    /// An action of the receiver was refused by the server.
+    Rejected(Rejection),
+    /// The cells the sender looks at : from now on, it only wants the chunks covering them.
+    Viewport(Region),
+    /// State of a chunk of the sender's quad, of `width` x `height` cells.
+    ChunkSnapshot {
+        width: u32,
+        height: u32,
+        chunk: ChunkId,
+        frame: Frame,
+    },
+    /// The sender has applied the snapshot `frame` of a chunk.
+    ChunkAck {
+        chunk: ChunkId,
+        frame: u64,
+    },
+}
+
+impl Encode for Message {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Message::Hello { version } => {
+                0u8.encode(out);
+                version.encode(out);
+            }
+            Message::Inputs {
+                generation,
+                actions,
+            } => {
+                1u8.encode(out);
+                generation.encode(out);
+                actions.encode(out);
+            }
+            Message::Ack { until } => {
+                2u8.encode(out);
+                until.encode(out);
+            }
+            Message::Snapshot(frame) => {
+                3u8.encode(out);
+                frame.encode(out);
+            }
+            Message::SnapshotAck { frame } => {
+                4u8.encode(out);
+                frame.encode(out);
+            }
+            Message::Checkpoint(checkpoint) => {
+                5u8.encode(out);
+                checkpoint.encode(out);
+            }
+            Message::RegionRequest { generation, region } => {
+                6u8.encode(out);
+                generation.encode(out);
+                region.encode(out);
+            }
+            Message::RegionState(state) => {
+                7u8.encode(out);
+                state.encode(out);
+            }
+            Message::Rejected(rejection) => {
+                8u8.encode(out);
+                rejection.encode(out);
+            }
+            Message::Viewport(region) => {
+                9u8.encode(out);
+                region.encode(out);
+            }
+            Message::ChunkSnapshot {
+                width,
+                height,
+                chunk,
+                frame,
+            } => {
+                10u8.encode(out);
+                width.encode(out);
+                height.encode(out);
+                chunk.encode(out);
+                frame.encode(out);
+            }
+            Message::ChunkAck { chunk, frame } => {
+                11u8.encode(out);
+                chunk.encode(out);
+                frame.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Message {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Message::Hello {
+                version: u8::decode(input)?,
+            }),
+            1 => Ok(Message::Inputs {
+                generation: u64::decode(input)?,
+                actions: Vec::decode(input)?,
+            }),
+            2 => Ok(Message::Ack {
+                until: u64::decode(input)?,
+            }),
+            3 => Ok(Message::Snapshot(Frame::decode(input)?)),
+            4 => Ok(Message::SnapshotAck {
+                frame: u64::decode(input)?,
+            }),
+            5 => Ok(Message::Checkpoint(Checkpoint::decode(input)?)),
+            6 => Ok(Message::RegionRequest {
+                generation: u64::decode(input)?,
+                region: Region::decode(input)?,
+            }),
+            7 => Ok(Message::RegionState(RegionState::decode(input)?)),
+            8 => Ok(Message::Rejected(Rejection::decode(input)?)),
+            9 => Ok(Message::Viewport(Region::decode(input)?)),
+            10 => Ok(Message::ChunkSnapshot {
+                width: u32::decode(input)?,
+                height: u32::decode(input)?,
+                chunk: ChunkId::decode(input)?,
+                frame: Frame::decode(input)?,
+            }),
+            11 => Ok(Message::ChunkAck {
+                chunk: ChunkId::decode(input)?,
+                frame: u64::decode(input)?,
+            }),
+            tag => Err(DecodeError::InvalidTag("Message", tag)),
+        }
+    }
+}
+
+/// A message, as sent on the wire : prefixed by its sender.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub from: PeerId,
+    pub message: Message,
+}
+
+impl Encode for Packet {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.from.encode(out);
+        self.message.encode(out);
+    }
+}
+
+impl Decode for Packet {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Packet {
+            from: u8::decode(input)?,
+            message: Message::decode(input)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::message::{Message, Packet, PROTOCOL_VERSION};
+    use quadlife::cell::State;
+    use quadlife::codec::{Decode, Encode};
+    use quadlife::delta::Frame;
+    use quadlife::desync::{Checkpoint, Region, RegionState};
+    use quadlife::event::Action;
+    use quadlife::placement::Rejection;
+    use quadlife::tribe::Tribe;
+
+    #[test]
+    fn check_packet_roundtrip() {
+        let region = Region {
+            x: 32,
+            y: 0,
+            width: 2,
+            height: 1,
+        };
+        let packets = [
+            Packet {
+                from: 1,
+                message: Message::Hello {
+                    version: PROTOCOL_VERSION,
+                },
+            },
+            Packet {
+                from: 2,
+                message: Message::Inputs {
+                    generation: 42,
+                    actions: vec![Action::Place {
+                        tribe: Tribe(2),
+                        cells: vec![(1, 2), (3, 4)],
+                    }],
+                },
+            },
+            Packet {
+                from: 0,
+                message: Message::Ack { until: 7 },
+            },
+            Packet {
+                from: 0,
+                message: Message::Snapshot(Frame {
+                    id: 3,
+                    generation: 9,
+                    base: Some(8),
+                    width: 2,
+                    height: 3,
+                    data: vec![1, 2, 3],
+                }),
+            },
+            Packet {
+                from: 1,
+                message: Message::SnapshotAck { frame: 3 },
+            },
+            Packet {
+                from: 2,
+                message: Message::Checkpoint(Checkpoint {
+                    generation: 32,
+                    checksum: 5,
+                    regions: vec![1, 2],
+                }),
+            },
+            Packet {
+                from: 1,
+                message: Message::RegionRequest {
+                    generation: 32,
+                    region,
+                },
+            },
+            Packet {
+                from: 0,
+                message: Message::RegionState(RegionState {
+                    generation: 32,
+                    region,
+                    cells: vec![(State::Alive, Some(Tribe(1))), (State::Dead, None)],
+                }),
+            },
+            Packet {
+                from: 0,
+                message: Message::Rejected(Rejection::Cooldown { remaining: 3 }),
+            },
+            Packet {
+                from: 1,
+                message: Message::Viewport(region),
+            },
+            Packet {
+                from: 0,
+                message: Message::ChunkSnapshot {
+                    width: 640,
+                    height: 480,
+                    chunk: (3, 2),
+                    frame: Frame {
+                        id: 1,
+                        generation: 9,
+                        base: None,
+                        width: 32,
+                        height: 32,
+                        data: vec![255, 0],
+                    },
+                },
+            },
+            Packet {
+                from: 1,
+                message: Message::ChunkAck {
+                    chunk: (3, 2),
+                    frame: 1,
+                },
+            },
+        ];
+        for p in packets {
+            assert_eq!(Packet::from_bytes(&p.to_bytes()), Ok(p));
+        }
+        assert!(Packet::from_bytes(&[0, 9]).is_err());
+    }
+}
@@ -0,0 +1,432 @@
+use crate::net::lockstep::Lockstep;
+use crate::net::message::Message;
+use crate::net::{PeerId, Transport};
+use quadlife::event::{self, Action, Event, Game};
+use quadlife::placement::Rejection;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// Delay before unacknowledged inputs are sent again.
+const RESEND_DELAY: Duration = Duration::from_millis(100);
+
+/// Generations played ahead of the last one with all inputs in, by default.
+const MAX_PREDICTION: u64 = 16;
+
+/// Rollback synchronisation : like lockstep, every peer sends a bundle of inputs (maybe empty) for every generation,
+/// but nobody waits for them. Missing remote inputs are predicted empty, and local ones are applied right away.
+/// When a late bundle shows a prediction was wrong, the game is rewound to the snapshot taken before that generation,
+/// and resimulated up to the present with the actual inputs.
+///
+/// Once all inputs of a generation are in, it is final : the same on all peers.
+/// A peer more than `max_prediction` generations ahead of that waits, as in lockstep.
+pub struct Rollback {
+    me: PeerId,
+    others: BTreeSet<PeerId>,
+    input_delay: u64,
+    max_prediction: u64,
+    /// Next generation to play.
+    generation: u64,
+    /// Actions submitted since the last bundle.
+    local: Vec<Action>,
+    /// Bundles of generations not final yet, by generation then peer (ourself included).
+    bundles: BTreeMap<u64, BTreeMap<PeerId, Vec<Action>>>,
+    /// The game before each generation not final yet, to rewind to.
+    snapshots: BTreeMap<u64, event::Snapshot>,
+    /// Earliest generation played with a wrong prediction.
+    mispredicted: Option<u64>,
+    /// Our bundles, until every peer acknowledged them.
+    outbox: BTreeMap<u64, Vec<Action>>,
+    /// For each other peer, we have all its bundles before this generation.
+    received_until: BTreeMap<PeerId, u64>,
+    /// For each other peer, it has all our bundles before this generation.
+    acked_until: BTreeMap<PeerId, u64>,
+    /// Peers to send all our unacknowledged bundles to, right away.
+    resend_to: BTreeSet<PeerId>,
+    last_resend: Instant,
+    outgoing: Vec<(PeerId, Message)>,
+    rollbacks: u64,
+    resimulated: u64,
+}
+
+impl Rollback {
+    /// With an input delay of 0, local actions are applied to the very next generation played.
+    pub fn new(me: PeerId, others: impl IntoIterator<Item = PeerId>, input_delay: u64) -> Self {
+        let others: BTreeSet<PeerId> = others.into_iter().filter(|p| *p != me).collect();
+        Self {
+            me,
+            received_until: others.iter().map(|p| (*p, input_delay)).collect(),
+            acked_until: others.iter().map(|p| (*p, input_delay)).collect(),
+            others,
+            input_delay,
+            max_prediction: MAX_PREDICTION,
+            generation: 0,
+            local: vec![],
+            bundles: BTreeMap::new(),
+            snapshots: BTreeMap::new(),
+            mispredicted: None,
+            outbox: BTreeMap::new(),
+            resend_to: BTreeSet::new(),
+            last_resend: Instant::now(),
+            outgoing: vec![],
+            rollbacks: 0,
+            resimulated: 0,
+        }
+    }
+
+    /// Generations played ahead of the last final one, at most : also the longest resimulation.
+    pub fn with_max_prediction(self, generations: u64) -> Self {
+        Self {
+            max_prediction: generations.max(1),
+            ..self
+        }
+    }
+
+    pub fn me(&self) -> PeerId {
+        self.me
+    }
+
+    /// Next generation to be played.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Generations before this one are final : all their inputs are in, and they were played with them.
+    pub fn confirmed(&self) -> u64 {
+        let received = self
+            .received_until
+            .values()
+            .min()
+            .copied()
+            .unwrap_or(u64::MAX);
+        match self.mispredicted {
+            Some(g) => received.min(g),
+            None => received,
+        }
+        .min(self.generation)
+    }
+
+    /// Times the game was rewound so far.
+    pub fn rollbacks(&self) -> u64 {
+        self.rollbacks
+    }
+
+    /// Generations computed again so far, after rewinding.
+    pub fn resimulated(&self) -> u64 {
+        self.resimulated
+    }
+
+    /// Queues a local action, for the next bundle.
+    pub fn submit(&mut self, action: Action) {
+        self.local.push(action);
+    }
+
+    /// Peers whose late inputs keep us from playing the next generation, if we are too far ahead.
+    pub fn waiting_for(&self) -> Vec<PeerId> {
+        if self.generation < self.confirmed() + self.max_prediction {
+            return vec![];
+        }
+        self.received_until
+            .iter()
+            .filter(|(_, until)| **until <= self.confirmed())
+            .map(|(p, _)| *p)
+            .collect()
+    }
+
+    /// Receives and sends whatever is possible. To be called often, even when waiting for nothing.
+    pub fn poll(&mut self, transport: &mut (impl Transport + ?Sized)) -> io::Result<()> {
+        while let Some((from, message)) = transport.receive()? {
+            self.handle(from, message);
+        }
+
+        if self.last_resend.elapsed() >= RESEND_DELAY {
+            self.last_resend = Instant::now();
+            self.resend_to.extend(self.others.iter().copied());
+        }
+        for peer in std::mem::take(&mut self.resend_to) {
+            let from = self.acked_until[&peer];
+            for (generation, actions) in self.outbox.range(from..) {
+                self.outgoing.push((
+                    peer,
+                    Message::Inputs {
+                        generation: *generation,
+                        actions: actions.clone(),
+                    },
+                ));
+            }
+        }
+
+        for (to, message) in self.outgoing.drain(..) {
+            transport.send(to, &message)?;
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, from: PeerId, message: Message) {
+        if !self.others.contains(&from) {
+            return;
+        }
+        match message {
+            Message::Hello { .. } => {
+                // (re)connected : whatever was sent before may be lost
+                self.resend_to.insert(from);
+            }
+            Message::Inputs {
+                generation,
+                actions,
+            } => {
+                let until = self.received_until.get_mut(&from).unwrap();
+                let known = self
+                    .bundles
+                    .get(&generation)
+                    .is_some_and(|b| b.contains_key(&from));
+                if generation >= *until && !known {
+                    // played already, predicting nothing : wrong, unless it is nothing indeed
+                    if generation < self.generation && !actions.is_empty() {
+                        self.mispredicted =
+                            Some(self.mispredicted.map_or(generation, |g| g.min(generation)));
+                    }
+                    self.bundles
+                        .entry(generation)
+                        .or_default()
+                        .insert(from, actions);
+                    while self
+                        .bundles
+                        .get(until)
+                        .is_some_and(|b| b.contains_key(&from))
+                    {
+                        *until += 1;
+                    }
+                }
+                // acknowledged every time, the previous ack may have been lost
+                self.outgoing.push((from, Message::Ack { until: *until }));
+            }
+            Message::Ack { until } => {
+                let acked = self.acked_until.get_mut(&from).unwrap();
+                *acked = (*acked).max(until);
+                let all_acked = self.acked_until.values().min().copied().unwrap_or(u64::MAX);
+                self.outbox.retain(|generation, _| *generation >= all_acked);
+            }
+            // no desync detection, nor broadcasts here
+            _ => {}
+        }
+    }
+
+    /// Rewinds the game and resimulates it, if late inputs showed a prediction was wrong.
+    /// Returns the placements rejected on the way, which may differ from the predicted ones.
+    pub fn correct(&mut self, game: &mut Game) -> Vec<Rejection> {
+        let Some(from) = self.mispredicted.take() else {
+            return vec![];
+        };
+        game.rollback(&self.snapshots[&from]);
+        self.rollbacks += 1;
+        self.resimulated += self.generation - from;
+
+        let mut rejections = vec![];
+        for generation in from..self.generation {
+            rejections.extend(self.play(game, generation));
+        }
+        rejections
+    }
+
+    /// Plays the next generation, with the inputs received and predicting the missing ones,
+    /// after correcting the past if needed. Returns the rejected placements,
+    /// or None if we are too far ahead of the final generations to predict more.
+    /// The game must not be computing : it is only ever computed here.
+    pub fn advance(&mut self, game: &mut Game) -> Option<Vec<Rejection>> {
+        let mut rejections = self.correct(game);
+        if !self.waiting_for().is_empty() {
+            return None;
+        }
+
+        let target = self.generation + self.input_delay;
+        let bundle = std::mem::take(&mut self.local);
+        self.bundles
+            .entry(target)
+            .or_default()
+            .insert(self.me, bundle.clone());
+        for peer in self.others.iter() {
+            self.outgoing.push((
+                *peer,
+                Message::Inputs {
+                    generation: target,
+                    actions: bundle.clone(),
+                },
+            ));
+        }
+        if !self.others.is_empty() {
+            self.outbox.insert(target, bundle);
+        }
+
+        rejections.extend(self.play(game, self.generation));
+        self.generation += 1;
+
+        // final generations are never rewound to
+        let confirmed = self.confirmed();
+        self.snapshots = self.snapshots.split_off(&confirmed);
+        self.bundles = self.bundles.split_off(&confirmed);
+        Some(rejections)
+    }
+
+    /// Plays a generation with the inputs known, saving the game before.
+    fn play(&mut self, game: &mut Game, generation: u64) -> Vec<Rejection> {
+        self.snapshots.insert(generation, game.snapshot());
+        let bundles = self.bundles.get(&generation).into_iter().flatten();
+        for (peer, actions) in bundles {
+            for action in actions.iter().filter(|a| Lockstep::allowed(*peer, a)) {
+                game.schedule(Event {
+                    generation,
+                    action: action.clone(),
+                });
+            }
+        }
+        let rejections = game.sync();
+        game.resimulate(1);
+        rejections
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::local::{LocalNetwork, LocalTransport};
+    use crate::net::rollback::Rollback;
+    use crate::net::sim::{Conditions, SimulatedTransport};
+    use crate::net::{PeerId, Transport};
+    use quadlife::cell::State;
+    use quadlife::event::{Action, Game};
+    use quadlife::placement::Policy;
+    use quadlife::quad::Quad;
+    use quadlife::tribe::Tribe;
+    use std::time::{Duration, Instant};
+
+    /// A peer playing a small game, acting now and then.
+    struct Player {
+        rollback: Rollback,
+        game: Game,
+    }
+
+    impl Player {
+        fn new(me: PeerId, peers: &[PeerId]) -> Self {
+            Self {
+                rollback: Rollback::new(me, peers.iter().copied(), 0).with_max_prediction(8),
+                game: Game::new(
+                    Quad::gen(State::Dead, 32, 32).with_seed(7),
+                    Policy::default(),
+                ),
+            }
+        }
+
+        /// Plays at most `generations`, then only corrects the past.
+        fn update(&mut self, transport: &mut dyn Transport, generations: u64) {
+            self.rollback.poll(transport).unwrap();
+            while self.rollback.generation() < generations {
+                let generation = self.rollback.generation();
+                let me = self.rollback.me();
+                if generation % 5 == me as u64 {
+                    let x = (generation as i64 * 7 + me as i64 * 11) % 32;
+                    self.rollback.submit(Action::Place {
+                        tribe: Tribe(me),
+                        cells: vec![(x, 10), (x, 11), (x, 12)],
+                    });
+                }
+                if self.rollback.advance(&mut self.game).is_none() {
+                    break;
+                }
+            }
+            self.rollback.correct(&mut self.game);
+            self.rollback.poll(transport).unwrap();
+        }
+    }
+
+    /// Runs peers until they all have `generations` final, updating peer `i` once every `slowness[i]` rounds.
+    /// Returns them, and the largest lead of a peer over another.
+    fn play(
+        transports: &mut [SimulatedTransport<LocalTransport>],
+        slowness: &[usize],
+        generations: u64,
+    ) -> (Vec<Player>, u64) {
+        let ids: Vec<PeerId> = (0..transports.len() as PeerId).collect();
+        let mut players: Vec<Player> = ids.iter().map(|id| Player::new(*id, &ids)).collect();
+
+        let deadline = Instant::now() + Duration::from_secs(20);
+        let mut round = 0;
+        let mut lead = 0;
+        while players.iter().any(|p| p.rollback.confirmed() < generations) {
+            assert!(Instant::now() < deadline, "peers are stuck");
+            round += 1;
+            for (i, (p, t)) in players.iter_mut().zip(transports.iter_mut()).enumerate() {
+                if round % slowness[i] == 0 {
+                    p.update(t, generations);
+                }
+            }
+            let played = players.iter().map(|p| p.rollback.generation());
+            lead = lead.max(played.clone().max().unwrap() - played.min().unwrap());
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        (players, lead)
+    }
+
+    fn simulated(n: usize, conditions: Conditions) -> Vec<SimulatedTransport<LocalTransport>> {
+        let network = LocalNetwork::default();
+        (0..n)
+            .map(|i| SimulatedTransport::new(network.join(i as PeerId), conditions, i as u64))
+            .collect()
+    }
+
+    fn assert_in_sync(players: &[Player], generations: u64) {
+        let reference = &players[0].game;
+        let mut owners: Vec<Tribe> = reference
+            .quad()
+            .owners()
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        owners.sort();
+        owners.dedup();
+        // not trivial : every peer placed something
+        assert_eq!(owners.len(), players.len());
+        for p in players {
+            assert_eq!(p.game.quad().generation(), generations);
+            assert_eq!(p.game.log(), reference.log());
+        }
+    }
+
+    #[test]
+    fn check_peers_converge_over_a_bad_network() {
+        let mut transports = simulated(
+            3,
+            Conditions {
+                latency: Duration::from_millis(10),
+                jitter: Duration::from_millis(20),
+                loss: 0.1,
+                duplication: 0.1,
+                reordering: 0.1,
+            },
+        );
+
+        let (players, _) = play(&mut transports, &[1, 1, 1], 40);
+
+        assert_in_sync(&players, 40);
+        // local actions applied right away : the others had predicted wrong
+        assert!(players.iter().all(|p| p.rollback.rollbacks() > 0));
+    }
+
+    #[test]
+    fn check_slow_peer_does_not_stall_the_others() {
+        let mut transports = simulated(
+            3,
+            Conditions {
+                latency: Duration::from_millis(5),
+                ..Conditions::PERFECT
+            },
+        );
+
+        // peer 2 only plays every 10 rounds
+        let (players, lead) = play(&mut transports, &[1, 1, 10], 40);
+
+        // the others predicted up to the limit, then waited
+        assert!(lead > 1 && lead <= 8, "{}", lead);
+        assert_in_sync(&players, 40);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::net::message::Message;
+use crate::net::{PeerId, Transport};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::io;
+use std::time::{Duration, Instant};
+
+/// How bad a simulated network is. Probabilities are between 0 and 1.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Conditions {
+    pub latency: Duration,
+    /// Added to the latency of each message : between 0 and this, uniformly.
+    pub jitter: Duration,
+    pub loss: f32,
+    pub duplication: f32,
+    /// Held back long enough to be overtaken by the messages sent after it.
+    pub reordering: f32,
+}
+
+impl Conditions {
+    /// A perfect network.
+    pub const PERFECT: Conditions = Conditions {
+        latency: Duration::ZERO,
+        jitter: Duration::ZERO,
+        loss: 0.,
+        duplication: 0.,
+        reordering: 0.,
+    };
+}
+
+/// Wraps a transport, to make it as bad as the given conditions, on the sending side.
+/// Random draws are seeded : with the same seed and the same sends, the same messages are lost or duplicated.
+pub struct SimulatedTransport<T: Transport> {
+    inner: T,
+    conditions: Conditions,
+    rng: StdRng,
+    /// Messages sent, waiting for their delivery time.
+    in_flight: Vec<(Instant, PeerId, Message)>,
+}
+
+impl<T: Transport> SimulatedTransport<T> {
+    pub fn new(inner: T, conditions: Conditions, seed: u64) -> Self {
+        Self {
+            inner,
+            conditions,
+            rng: StdRng::seed_from_u64(seed),
+            in_flight: vec![],
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Number of messages sent and not delivered yet.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    fn delay(&mut self) -> Duration {
+        let c = self.conditions;
+        let jitter = c.jitter.mul_f64(self.rng.gen::<f64>());
+        if self.rng.gen::<f32>() < c.reordering {
+            // late enough for the next messages to arrive first
+            c.latency + c.jitter + jitter + Duration::from_millis(5)
+        } else {
+            c.latency + jitter
+        }
+    }
+
+    /// Hands the messages due to the inner transport.
+    /// One failing send does not hold back the others : the first error is returned once all were tried.
+    fn deliver(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        let (due, later): (Vec<_>, Vec<_>) = std::mem::take(&mut self.in_flight)
+            .into_iter()
+            .partition(|(at, _, _)| *at <= now);
+        self.in_flight = later;
+        let mut due = due;
+        due.sort_by_key(|(at, _, _)| *at);
+        let mut result = Ok(());
+        for (_, to, message) in due {
+            let sent = self.inner.send(to, &message);
+            if result.is_ok() {
+                result = sent;
+            }
+        }
+        result
+    }
+}
+
+impl<T: Transport> Transport for SimulatedTransport<T> {
+    fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()> {
+        if self.rng.gen::<f32>() < self.conditions.loss {
+            return Ok(());
+        }
+        let copies = if self.rng.gen::<f32>() < self.conditions.duplication {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let at = Instant::now() + self.delay();
+            self.in_flight.push((at, to, message.clone()));
+        }
+        self.deliver()
+    }
+
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>> {
+        self.deliver()?;
+        self.inner.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::local::LocalNetwork;
+    use crate::net::message::Message;
+    use crate::net::sim::{Conditions, SimulatedTransport};
+    use crate::net::Transport;
+    use std::time::{Duration, Instant};
+
+    /// Sends 0..n as acks, and receives them all, until nothing is in flight.
+    fn exchange(conditions: Conditions, n: u64) -> Vec<u64> {
+        let network = LocalNetwork::default();
+        let mut a = SimulatedTransport::new(network.join(0), conditions, 42);
+        let mut b = network.join(1);
+        for until in 0..n {
+            a.send(1, &Message::Ack { until }).unwrap();
+        }
+        let mut received = vec![];
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while a.in_flight() > 0 {
+            assert!(Instant::now() < deadline);
+            a.receive().unwrap();
+        }
+        while let Some((0, Message::Ack { until })) = b.receive().unwrap() {
+            received.push(until);
+        }
+        received
+    }
+
+    #[test]
+    fn check_perfect_network() {
+        assert_eq!(
+            exchange(Conditions::PERFECT, 100),
+            (0..100).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn check_bad_network() {
+        let received = exchange(
+            Conditions {
+                latency: Duration::from_millis(5),
+                jitter: Duration::from_millis(5),
+                loss: 0.2,
+                duplication: 0.2,
+                reordering: 0.2,
+            },
+            1000,
+        );
+        let mut unique = received.clone();
+        unique.sort();
+        unique.dedup();
+        // lost
+        assert!(unique.len() > 700 && unique.len() < 900, "{}", unique.len());
+        // duplicated
+        assert!(received.len() > unique.len());
+        // reordered
+        assert!(received.windows(2).any(|w| w[0] > w[1]));
+        // deterministic
+        assert_eq!(
+            received.len(),
+            exchange(
+                Conditions {
+                    latency: Duration::from_millis(5),
+                    jitter: Duration::from_millis(5),
+                    loss: 0.2,
+                    duplication: 0.2,
+                    reordering: 0.2,
+                },
+                1000,
+            )
+            .len()
+        );
+    }
+
+    #[test]
+    fn check_a_failing_send_delivers_the_others() {
+        let network = LocalNetwork::default();
+        let conditions = Conditions {
+            latency: Duration::from_millis(5),
+            ..Conditions::PERFECT
+        };
+        let mut a = SimulatedTransport::new(network.join(0), conditions, 42);
+        let mut b = network.join(1);
+        // peer 2 never joined
+        a.send(2, &Message::Ack { until: 0 }).unwrap();
+        for until in 1..10 {
+            a.send(1, &Message::Ack { until }).unwrap();
+        }
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(a.receive().is_err());
+        assert_eq!(a.in_flight(), 0);
+        let mut received = vec![];
+        while let Some((0, Message::Ack { until })) = b.receive().unwrap() {
+            received.push(until);
+        }
+        assert_eq!(received, (1..10).collect::<Vec<_>>());
+    }
+}
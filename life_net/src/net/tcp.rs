@@ -0,0 +1,442 @@
+use crate::net::message::{Message, Packet, PROTOCOL_VERSION};
+use crate::net::{PeerId, Transport};
+use quadlife::codec::{Decode, Encode};
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+/// Largest frame accepted, anything bigger is a broken (or hostile) peer.
+pub const MAX_FRAME: usize = 16 << 20;
+
+/// Delay between two connection attempts to an unreachable peer.
+const RETRY_DELAY: Duration = Duration::from_millis(50);
+const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// A non-blocking stream, with its pending input and output.
+struct Connection {
+    stream: TcpStream,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Connection {
+    fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self {
+            stream,
+            input: vec![],
+            output: vec![],
+        })
+    }
+
+    fn queue(&mut self, packet: &Packet) {
+        let bytes = packet.to_bytes();
+        self.output
+            .extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        self.output.extend_from_slice(&bytes);
+    }
+
+    /// Writes as much as possible. Returns false if the connection is closed.
+    fn flush(&mut self) -> bool {
+        while !self.output.is_empty() {
+            match self.stream.write(&self.output) {
+                Ok(0) => return false,
+                Ok(n) => {
+                    self.output.drain(..n);
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return false,
+            }
+        }
+        true
+    }
+
+    /// Reads everything available, and returns the complete packets. None if the connection is closed or broken.
+    fn read(&mut self) -> Option<Vec<Packet>> {
+        let mut chunk = [0u8; 4096];
+        loop {
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return None,
+                Ok(n) => self.input.extend_from_slice(&chunk[..n]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(_) => return None,
+            }
+        }
+
+        let mut packets = vec![];
+        while self.input.len() >= 4 {
+            let len = u32::from_le_bytes(self.input[..4].try_into().unwrap()) as usize;
+            if len > MAX_FRAME {
+                return None;
+            }
+            if self.input.len() < 4 + len {
+                break;
+            }
+            let packet = Packet::from_bytes(&self.input[4..4 + len]).ok()?;
+            self.input.drain(..4 + len);
+            packets.push(packet);
+        }
+        Some(packets)
+    }
+}
+
+/// Length prefixed messages, over one connection per pair of peers.
+/// Each peer listens, and connects to the peers with a lower id than its own, reconnecting when a connection drops.
+/// Connections are opened on a background thread : polling never blocks.
+/// Both ends of a connection start with a `Hello`, and peers of another protocol version are refused.
+/// A `Hello` is received each time a connection to a peer is (re)established.
+/// A connection is bound to the peer id it is opened with : packets claiming another id close it,
+/// and an id already connected is not taken over.
+pub struct TcpTransport {
+    me: PeerId,
+    listener: TcpListener,
+    peers: BTreeMap<PeerId, SocketAddr>,
+    connections: BTreeMap<PeerId, Connection>,
+    /// Accepted, but not identified yet (by their first packet).
+    accepted: Vec<Connection>,
+    /// Next connection attempt, for the peers we connect to.
+    retry: BTreeMap<PeerId, Instant>,
+    /// Connection attempts in progress.
+    connecting: BTreeMap<PeerId, Receiver<io::Result<TcpStream>>>,
+    inbox: VecDeque<(PeerId, Message)>,
+}
+
+impl TcpTransport {
+    pub fn bind(
+        me: PeerId,
+        listen: SocketAddr,
+        peers: BTreeMap<PeerId, SocketAddr>,
+    ) -> io::Result<Self> {
+        Self::with_listener(me, TcpListener::bind(listen)?, peers)
+    }
+
+    /// On a listener already bound : its address can be given to the peers beforehand.
+    pub fn with_listener(
+        me: PeerId,
+        listener: TcpListener,
+        peers: BTreeMap<PeerId, SocketAddr>,
+    ) -> io::Result<Self> {
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            me,
+            listener,
+            peers,
+            connections: BTreeMap::new(),
+            accepted: vec![],
+            retry: BTreeMap::new(),
+            connecting: BTreeMap::new(),
+            inbox: VecDeque::new(),
+        })
+    }
+
+    /// Only accepts connections, from clients with a higher id than ours : for a server, whose clients come and go.
+    pub fn serve(
+        me: PeerId,
+        listen: SocketAddr,
+        clients: impl IntoIterator<Item = PeerId>,
+    ) -> io::Result<Self> {
+        // addresses are only used to connect to lower ids : never here
+        let peers = clients
+            .into_iter()
+            .filter(|id| *id > me)
+            .map(|id| (id, listen))
+            .collect();
+        Self::bind(me, listen, peers)
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    pub fn is_connected(&self, peer: PeerId) -> bool {
+        self.connections.contains_key(&peer)
+    }
+
+    /// Drops the connection to a peer, as if the network failed. It will be re-established.
+    pub fn disconnect(&mut self, peer: PeerId) {
+        if let Some(c) = self.connections.remove(&peer) {
+            let _ = c.stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+
+    fn hello(&self) -> Packet {
+        Packet {
+            from: self.me,
+            message: Message::Hello {
+                version: PROTOCOL_VERSION,
+            },
+        }
+    }
+
+    /// Accepts, connects, reads and writes whatever is possible now.
+    fn poll(&mut self) -> io::Result<()> {
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _)) => self.accepted.push(Connection::new(stream)?),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let now = Instant::now();
+        let missing: Vec<(PeerId, SocketAddr)> = self
+            .peers
+            .iter()
+            .filter(|(id, _)| **id < self.me && !self.connections.contains_key(id))
+            .filter(|(id, _)| !self.connecting.contains_key(id))
+            .filter(|(id, _)| self.retry.get(id).is_none_or(|t| *t <= now))
+            .map(|(id, addr)| (*id, *addr))
+            .collect();
+        for (peer, addr) in missing {
+            let (done, attempt) = mpsc::channel();
+            std::thread::spawn(move || {
+                let _ = done.send(TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT));
+            });
+            self.connecting.insert(peer, attempt);
+        }
+
+        let attempts: Vec<PeerId> = self.connecting.keys().copied().collect();
+        for peer in attempts {
+            match self.connecting[&peer].try_recv() {
+                Err(TryRecvError::Empty) => continue,
+                Ok(Ok(stream)) => {
+                    // the peer answers with its own hello
+                    let mut c = Connection::new(stream)?;
+                    c.queue(&self.hello());
+                    self.connections.insert(peer, c);
+                    self.retry.remove(&peer);
+                }
+                Ok(Err(_)) | Err(TryRecvError::Disconnected) => {
+                    self.retry.insert(peer, now + RETRY_DELAY);
+                }
+            }
+            self.connecting.remove(&peer);
+        }
+
+        // closed connections first : a peer reconnecting replaces them
+        let mut closed = vec![];
+        for (peer, c) in self.connections.iter_mut() {
+            match c.read() {
+                Some(packets) if packets.iter().all(|p| p.from == *peer && is_compatible(p)) => {
+                    self.inbox
+                        .extend(packets.into_iter().map(|p| (*peer, p.message)))
+                }
+                // broken, claiming another id, or of another version
+                _ => closed.push(*peer),
+            }
+            if !c.flush() {
+                closed.push(*peer);
+            }
+        }
+        for peer in closed {
+            self.disconnect(peer);
+        }
+
+        // identify accepted connections by their first packet
+        for mut c in std::mem::take(&mut self.accepted) {
+            match c.read() {
+                None => {}
+                Some(packets) if packets.is_empty() => self.accepted.push(c),
+                Some(packets) => {
+                    let from = packets[0].from;
+                    let hello = Message::Hello {
+                        version: PROTOCOL_VERSION,
+                    };
+                    if packets[0].message != hello
+                        || from == self.me
+                        || !self.peers.contains_key(&from)
+                        || self.connections.contains_key(&from)
+                        || packets.iter().any(|p| p.from != from || !is_compatible(p))
+                    {
+                        continue;
+                    }
+                    c.queue(&self.hello());
+                    self.connections.insert(from, c);
+                    self.inbox
+                        .extend(packets.into_iter().map(|p| (from, p.message)));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether a packet is not a `Hello` of another protocol version.
+fn is_compatible(packet: &Packet) -> bool {
+    match packet.message {
+        Message::Hello { version } => version == PROTOCOL_VERSION,
+        _ => true,
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()> {
+        if !self.peers.contains_key(&to) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown peer {}", to),
+            ));
+        }
+        let packet = Packet {
+            from: self.me,
+            message: message.clone(),
+        };
+        // not connected : lost, like it would be in a broken connection
+        if let Some(c) = self.connections.get_mut(&to) {
+            c.queue(&packet);
+            if !c.flush() {
+                self.disconnect(to);
+            }
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>> {
+        if self.inbox.is_empty() {
+            self.poll()?;
+        }
+        Ok(self.inbox.pop_front())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::message::{Message, Packet, PROTOCOL_VERSION};
+    use crate::net::tcp::TcpTransport;
+    use crate::net::Transport;
+    use quadlife::codec::Encode;
+    use std::collections::BTreeMap;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::time::{Duration, Instant};
+
+    fn framed(packet: &Packet) -> Vec<u8> {
+        let bytes = packet.to_bytes();
+        let mut frame = (bytes.len() as u32).to_le_bytes().to_vec();
+        frame.extend_from_slice(&bytes);
+        frame
+    }
+
+    #[test]
+    fn check_connected_ids_are_not_taken_over() {
+        let mut server = TcpTransport::serve(0, "127.0.0.1:0".parse().unwrap(), 1..=2).unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut client = TcpTransport::bind(
+            1,
+            "127.0.0.1:0".parse().unwrap(),
+            BTreeMap::from([(0, addr)]),
+        )
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !server.is_connected(1) {
+            assert!(Instant::now() < deadline, "client never connected");
+            client.receive().unwrap();
+            server.receive().unwrap();
+        }
+
+        // another client, claiming the same id
+        let mut impostor = TcpStream::connect(addr).unwrap();
+        let hello = Message::Hello {
+            version: PROTOCOL_VERSION,
+        };
+        let ack = |until| Packet {
+            from: 1,
+            message: Message::Ack { until },
+        };
+        impostor
+            .write_all(
+                &[
+                    framed(&Packet {
+                        from: 1,
+                        message: hello.clone(),
+                    }),
+                    framed(&ack(666)),
+                ]
+                .concat(),
+            )
+            .unwrap();
+        // and a known client, claiming another id later on
+        let mut other = TcpStream::connect(addr).unwrap();
+        other
+            .write_all(&framed(&Packet {
+                from: 2,
+                message: hello.clone(),
+            }))
+            .unwrap();
+
+        let (mut received, mut lied) = (vec![], false);
+        let deadline = Instant::now() + Duration::from_millis(300);
+        while Instant::now() < deadline {
+            if let Some(message) = server.receive().unwrap() {
+                received.push(message);
+            }
+            if !lied && received.contains(&(2, hello.clone())) {
+                lied = true;
+                other.write_all(&framed(&ack(667))).unwrap();
+            }
+            client.receive().unwrap();
+        }
+
+        assert!(lied);
+        assert!(received
+            .iter()
+            .all(|(_, m)| !matches!(m, Message::Ack { .. })));
+        assert!(server.is_connected(1));
+        assert!(!server.is_connected(2));
+
+        // the genuine client is still the one reached
+        server.send(1, &Message::Ack { until: 3 }).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            assert!(Instant::now() < deadline, "message never received");
+            server.receive().unwrap();
+            if client.receive().unwrap() == Some((0, Message::Ack { until: 3 })) {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn check_other_versions_are_refused() {
+        let mut server = TcpTransport::serve(0, "127.0.0.1:0".parse().unwrap(), 1..=2).unwrap();
+        let addr = server.local_addr().unwrap();
+        let mut old = TcpStream::connect(addr).unwrap();
+        old.write_all(&framed(&Packet {
+            from: 2,
+            message: Message::Hello {
+                version: PROTOCOL_VERSION - 1,
+            },
+        }))
+        .unwrap();
+
+        let deadline = Instant::now() + Duration::from_millis(300);
+        while Instant::now() < deadline {
+            assert_eq!(server.receive().unwrap(), None);
+        }
+        assert!(!server.is_connected(2));
+    }
+
+    #[test]
+    fn check_connecting_does_not_block() {
+        // not routable : the connection can only time out, or fail
+        let mut client = TcpTransport::bind(
+            1,
+            "127.0.0.1:0".parse().unwrap(),
+            BTreeMap::from([(0, "10.255.255.1:9".parse().unwrap())]),
+        )
+        .unwrap();
+        for _ in 0..10 {
+            let start = Instant::now();
+            assert_eq!(client.receive().unwrap(), None);
+            assert!(start.elapsed() < Duration::from_millis(50));
+        }
+        assert!(!client.is_connected(0));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::net::message::{Message, Packet};
+use crate::net::{PeerId, Transport};
+use quadlife::codec::{Decode, Encode};
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+
+/// Largest packet we send : safe for IPv4 and IPv6 without relying on fragmentation... mostly.
+pub const MAX_DATAGRAM: usize = 65_000;
+
+/// One datagram per message, nothing more : messages may be lost, duplicated or reordered.
+/// A peer id is bound to the address it is first heard from : the same id from another address,
+/// or another id from that address, is ignored.
+pub struct UdpTransport {
+    me: PeerId,
+    socket: UdpSocket,
+    peers: BTreeMap<PeerId, SocketAddr>,
+    /// Peers heard from, their address bound.
+    bound: BTreeSet<PeerId>,
+    buffer: Vec<u8>,
+}
+
+impl UdpTransport {
+    pub fn bind(
+        me: PeerId,
+        listen: SocketAddr,
+        peers: BTreeMap<PeerId, SocketAddr>,
+    ) -> io::Result<Self> {
+        Self::with_socket(me, UdpSocket::bind(listen)?, peers)
+    }
+
+    /// On a socket already bound : its address can be given to the peers beforehand.
+    pub fn with_socket(
+        me: PeerId,
+        socket: UdpSocket,
+        peers: BTreeMap<PeerId, SocketAddr>,
+    ) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            me,
+            socket,
+            peers,
+            bound: BTreeSet::new(),
+            buffer: vec![0; MAX_DATAGRAM],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Whether a packet claiming to be from `from` may come from `addr`.
+    fn is_genuine(&self, from: PeerId, addr: SocketAddr) -> bool {
+        if from == self.me {
+            return false;
+        }
+        match self.peers.get(&from) {
+            None => false,
+            Some(bound) if self.bound.contains(&from) => *bound == addr,
+            // the first packet of a peer : unless its address is another's
+            Some(_) => !self
+                .bound
+                .iter()
+                .any(|peer| self.peers.get(peer) == Some(&addr)),
+        }
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, to: PeerId, message: &Message) -> io::Result<()> {
+        let Some(addr) = self.peers.get(&to) else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("unknown peer {}", to),
+            ));
+        };
+        let bytes = Packet {
+            from: self.me,
+            message: message.clone(),
+        }
+        .to_bytes();
+        if bytes.len() > MAX_DATAGRAM {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("message of {} bytes is too large for udp", bytes.len()),
+            ));
+        }
+        match self.socket.send_to(&bytes, addr) {
+            Ok(_) => Ok(()),
+            // the peer is not there (yet ?) : as if the datagram was lost
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn receive(&mut self) -> io::Result<Option<(PeerId, Message)>> {
+        loop {
+            let (len, addr) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+                // errors reported for a previous send, on some platforms
+                Err(e)
+                    if e.kind() == io::ErrorKind::ConnectionRefused
+                        || e.kind() == io::ErrorKind::ConnectionReset =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            // garbage, or a stranger : ignored
+            let Ok(packet) = Packet::from_bytes(&self.buffer[..len]) else {
+                continue;
+            };
+            if !self.is_genuine(packet.from, addr) {
+                continue;
+            }
+            // configured addresses may differ from the one a peer sends from (ie. a wildcard)
+            self.peers.insert(packet.from, addr);
+            self.bound.insert(packet.from);
+            return Ok(Some((packet.from, packet.message)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::message::{Message, Packet};
+    use crate::net::udp::UdpTransport;
+    use crate::net::Transport;
+    use quadlife::codec::Encode;
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    fn transport(me: u8, peers: &[(u8, SocketAddr)]) -> UdpTransport {
+        UdpTransport::bind(
+            me,
+            "127.0.0.1:0".parse().unwrap(),
+            peers.iter().copied().collect::<BTreeMap<_, _>>(),
+        )
+        .unwrap()
+    }
+
+    /// Everything received within a short while.
+    fn drain(transport: &mut UdpTransport) -> Vec<(u8, Message)> {
+        let mut received = vec![];
+        let deadline = Instant::now() + Duration::from_millis(100);
+        while Instant::now() < deadline {
+            if let Some(message) = transport.receive().unwrap() {
+                received.push(message);
+            }
+        }
+        received
+    }
+
+    #[test]
+    fn check_bound_ids_are_not_taken_over() {
+        let any: SocketAddr = "127.0.0.1:9".parse().unwrap();
+        let mut host = transport(0, &[(1, any), (2, any)]);
+        let host_addr = host.local_addr().unwrap();
+        let mut genuine = transport(1, &[(0, host_addr)]);
+        let mut impostor = transport(1, &[(0, host_addr)]);
+        let mut liar = transport(2, &[(0, host_addr)]);
+
+        genuine.send(0, &Message::Ack { until: 1 }).unwrap();
+        assert_eq!(drain(&mut host), vec![(1, Message::Ack { until: 1 })]);
+
+        // the same id from another address, then another id from a bound address
+        impostor.send(0, &Message::Ack { until: 666 }).unwrap();
+        let lie = Packet {
+            from: 2,
+            message: Message::Ack { until: 667 },
+        };
+        genuine.socket.send_to(&lie.to_bytes(), host_addr).unwrap();
+        liar.send(0, &Message::Ack { until: 2 }).unwrap();
+        assert_eq!(drain(&mut host), vec![(2, Message::Ack { until: 2 })]);
+
+        // still sent to the genuine peer
+        host.send(1, &Message::Ack { until: 3 }).unwrap();
+        assert_eq!(drain(&mut genuine), vec![(0, Message::Ack { until: 3 })]);
+        assert_eq!(drain(&mut impostor), vec![]);
+    }
+}
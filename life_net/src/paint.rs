@@ -0,0 +1,138 @@
+use figment::graphics::camera::Camera;
+use macroquad::input::{
+    is_key_pressed, is_mouse_button_down, mouse_position, KeyCode, MouseButton,
+};
+use macroquad::math::Vec2;
+use quadlife::brush::{Brush, Shape};
+use quadlife::cell;
+use std::collections::BTreeSet;
+
+/// Maps a screen position to the (possibly out of bounds) cell under it, for a board drawn at the world origin,
+/// a cell being a world unit.
+pub fn screen_to_cell(screen: Vec2, camera: &Camera) -> (i64, i64) {
+    let world = camera.screen_to_world(screen);
+    (world.x.floor() as i64, world.y.floor() as i64)
+}
+
+/// Paints live cells with the left mouse button, erases them with the right one.
+/// The painter only decides which cells are touched : the game applies them, subject to its placement rules.
+pub struct Painter {
+    pub brush: Brush,
+    /// Last painted cell and state, to interpolate fast strokes.
+    last: Option<((i64, i64), cell::State)>,
+}
+
+impl Default for Painter {
+    fn default() -> Self {
+        Self {
+            brush: Brush::new(0, Shape::Disc),
+            last: None,
+        }
+    }
+}
+
+impl Painter {
+    /// Keyboard bindings : [ and ] shrink and grow the brush.
+    pub fn update_brush(&mut self) {
+        if is_key_pressed(KeyCode::LeftBracket) {
+            self.brush.shrink();
+        }
+        if is_key_pressed(KeyCode::RightBracket) {
+            self.brush.grow();
+        }
+    }
+
+    /// Cells painted by the mouse during this frame, on a board seen through `camera`.
+    pub fn update(&mut self, camera: &Camera) -> Option<(BTreeSet<(i64, i64)>, cell::State)> {
+        let state = if is_mouse_button_down(MouseButton::Left) {
+            Some(cell::State::Alive)
+        } else if is_mouse_button_down(MouseButton::Right) {
+            Some(cell::State::Dead)
+        } else {
+            None
+        };
+        let target = screen_to_cell(Vec2::from(mouse_position()), camera);
+        self.stroke(state.map(|s| (target, s)))
+    }
+
+    /// Cells painted from the last painted cell (if the same state is still painted) up to `target`.
+    pub fn stroke(
+        &mut self,
+        target: Option<((i64, i64), cell::State)>,
+    ) -> Option<(BTreeSet<(i64, i64)>, cell::State)> {
+        let painted = match (self.last, target) {
+            (_, None) => None,
+            (Some((from, previous)), Some((to, state))) if previous == state => {
+                Some((self.brush.stroke(from, to), state))
+            }
+            (_, Some((to, state))) => Some((self.brush.cells(to.0, to.1).collect(), state)),
+        };
+        self.last = target;
+        painted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::paint::{screen_to_cell, Painter};
+    use figment::graphics::camera::Camera;
+    use macroquad::math::Vec2;
+    use quadlife::cell::State;
+    use quadlife::quad::Quad;
+
+    fn apply(
+        q: &mut Quad,
+        painted: Option<(std::collections::BTreeSet<(i64, i64)>, State)>,
+    ) -> usize {
+        painted.map_or(0, |(cells, state)| q.paint(cells, state))
+    }
+
+    #[test]
+    fn check_screen_to_cell() {
+        let camera = Camera::default();
+        assert_eq!(screen_to_cell(Vec2::new(3.5, 7.9), &camera), (3, 7));
+        // board drawn at (10, 20) on screen, 2 pixels per cell
+        let camera = Camera::default()
+            .with_origin(Vec2::new(-5., -10.))
+            .with_zoom(2.);
+        assert_eq!(screen_to_cell(Vec2::new(13., 27.), &camera), (1, 3));
+        let camera = Camera::default().with_origin(Vec2::new(-10., -10.));
+        assert_eq!(screen_to_cell(Vec2::new(5., 5.), &camera), (-5, -5));
+        // zoomed out
+        let camera = Camera::default().with_zoom(0.5);
+        assert_eq!(screen_to_cell(Vec2::new(5., 5.), &camera), (10, 10));
+    }
+
+    #[test]
+    fn check_fast_stroke_is_interpolated() {
+        let mut q = Quad::gen(State::Dead, 16, 4);
+        let mut p = Painter::default();
+
+        apply(&mut q, p.stroke(Some(((0, 1), State::Alive))));
+        apply(&mut q, p.stroke(Some(((10, 1), State::Alive))));
+
+        assert_eq!(q.population(), 11);
+    }
+
+    #[test]
+    fn check_stroke_restarts_after_release() {
+        let mut q = Quad::gen(State::Dead, 16, 4);
+        let mut p = Painter::default();
+
+        apply(&mut q, p.stroke(Some(((0, 1), State::Alive))));
+        apply(&mut q, p.stroke(None));
+        apply(&mut q, p.stroke(Some(((10, 1), State::Alive))));
+
+        assert_eq!(q.population(), 2);
+    }
+
+    #[test]
+    fn check_erase() {
+        let mut q = Quad::gen(State::Alive, 4, 4);
+        let mut p = Painter::default();
+        p.brush.grow();
+
+        assert_eq!(apply(&mut q, p.stroke(Some(((0, 0), State::Dead)))), 3);
+        assert_eq!(q.population(), 13);
+    }
+}
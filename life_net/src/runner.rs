@@ -0,0 +1,211 @@
+use figment::compute;
+use quadlife::quad::Quad;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::fmt::{Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// Why a headless run stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The maximum number of generations was reached.
+    Completed,
+    /// The board repeats itself, every `period` generations (1 for still lifes).
+    Stable { period: u64 },
+    /// No live cell left.
+    Extinct,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Completed => write!(f, "completed"),
+            Outcome::Stable { period } => write!(f, "stable (period {})", period),
+            Outcome::Extinct => write!(f, "extinct"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Statistics {
+    pub generations: u64,
+    pub outcome: Outcome,
+    /// Population before the first generation, then after each generation.
+    pub history: Vec<usize>,
+    pub elapsed: Duration,
+}
+
+impl Statistics {
+    pub fn initial_population(&self) -> usize {
+        self.history.first().copied().unwrap_or_default()
+    }
+
+    pub fn final_population(&self) -> usize {
+        self.history.last().copied().unwrap_or_default()
+    }
+
+    pub fn generations_per_second(&self) -> f64 {
+        self.generations as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn write_history_csv(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writeln!(writer, "generation,population")?;
+        for (g, p) in self.history.iter().enumerate() {
+            writeln!(writer, "{},{}", g, p)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "generations: {}", self.generations)?;
+        writeln!(f, "outcome: {}", self.outcome)?;
+        writeln!(f, "initial population: {}", self.initial_population())?;
+        writeln!(f, "final population: {}", self.final_population())?;
+        writeln!(
+            f,
+            "min population: {}",
+            self.history.iter().min().copied().unwrap_or_default()
+        )?;
+        writeln!(
+            f,
+            "max population: {}",
+            self.history.iter().max().copied().unwrap_or_default()
+        )?;
+        writeln!(f, "elapsed: {:.3}s", self.elapsed.as_secs_f64())?;
+        write!(
+            f,
+            "generations per second: {:.1}",
+            self.generations_per_second()
+        )
+    }
+}
+
+/// Runs a quad without any rendering, for a number of generations or until it stabilizes.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Runner {
+    max_generations: u64,
+    /// How many past generations are remembered to detect oscillators. None to never stop early.
+    stable_window: Option<usize>,
+}
+
+impl Runner {
+    pub fn new(max_generations: u64) -> Self {
+        Self {
+            max_generations,
+            stable_window: None,
+        }
+    }
+
+    /// Stop as soon as the board repeats a state seen in the last `window` generations.
+    pub fn until_stable(self, window: usize) -> Self {
+        Self {
+            stable_window: Some(window.max(1)),
+            ..self
+        }
+    }
+
+    pub fn run(&self, quad: &mut Quad) -> Statistics {
+        let start = Instant::now();
+        let mut history = vec![quad.population()];
+        let mut seen: VecDeque<u64> = VecDeque::new();
+        seen.push_front(state_hash(quad));
+
+        let mut outcome = Outcome::Completed;
+        let mut generations = 0;
+        while generations < self.max_generations {
+            compute::compute(quad);
+            generations += 1;
+            history.push(quad.population());
+
+            if let Some(window) = self.stable_window {
+                if quad.population() == 0 {
+                    outcome = Outcome::Extinct;
+                    break;
+                }
+                let hash = state_hash(quad);
+                if let Some(p) = seen.iter().position(|h| *h == hash) {
+                    outcome = Outcome::Stable {
+                        period: p as u64 + 1,
+                    };
+                    break;
+                }
+                seen.push_front(hash);
+                seen.truncate(window);
+            }
+        }
+
+        Statistics {
+            generations,
+            outcome,
+            history,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+fn state_hash(quad: &Quad) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    quad.cells().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::runner::{Outcome, Runner};
+    use grid::grid;
+    use quadlife::cell::State;
+    use quadlife::quad::Quad;
+
+    #[test]
+    fn check_fixed_generations() {
+        let mut q = Quad::gen(State::Dead, 8, 8).with_random_cells();
+
+        let stats = Runner::new(5).run(&mut q);
+
+        assert_eq!(stats.generations, 5);
+        assert_eq!(stats.outcome, Outcome::Completed);
+        assert_eq!(stats.history.len(), 6);
+        assert_eq!(q.generation(), 5);
+    }
+
+    #[test]
+    fn check_blinker_is_stable_with_period_2() {
+        let d = State::Dead;
+        let a = State::Alive;
+        let mut q = Quad::new(grid![[d, d, d, d, d][d, a, a, a, d][d, d, d, d, d]]);
+
+        let stats = Runner::new(100).until_stable(8).run(&mut q);
+
+        assert_eq!(stats.outcome, Outcome::Stable { period: 2 });
+        assert_eq!(stats.generations, 2);
+        assert_eq!(stats.final_population(), 3);
+    }
+
+    #[test]
+    fn check_extinction() {
+        let mut q = Quad::new(grid![[State::Alive]]);
+
+        let stats = Runner::new(100).until_stable(8).run(&mut q);
+
+        assert_eq!(stats.outcome, Outcome::Extinct);
+        assert_eq!(stats.generations, 1);
+    }
+
+    #[test]
+    fn check_history_csv() {
+        let mut q = Quad::new(grid![[State::Alive]]);
+        let stats = Runner::new(1).run(&mut q);
+
+        let mut out: Vec<u8> = vec![];
+        stats.write_history_csv(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "generation,population\n0,1\n1,0\n"
+        );
+    }
+}
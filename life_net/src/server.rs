@@ -0,0 +1,336 @@
+//! Authoritative server : runs the game headless, validates the actions of its clients against the game rules,
+//! and broadcasts its state to them. A client joining mid-game gets a full snapshot first, deltas afterwards.
+use crate::net::broadcast::Broadcaster;
+use crate::net::lockstep::Lockstep;
+use crate::net::message::Message;
+use crate::net::{PeerId, Transport};
+use figment::compute;
+use figment::compute::ComputeCtx;
+use quadlife::event::{Action, Game};
+use quadlife::quad::QuadUpdate;
+use quadlife::tribe::MAX_TRIBES;
+use std::io;
+use std::iter::Peekable;
+use std::time::{Duration, Instant};
+
+/// The server's peer id : clients have higher ones, which are also their tribes.
+pub const SERVER_ID: PeerId = 0;
+
+/// Longest computation between two polls of the network.
+const SLICE: Duration = Duration::from_millis(5);
+
+/// Applies an action of a client, if the rules allow it, sending back the rejections.
+/// The game must not be computing : rejections are only known once the action is applied.
+pub fn apply_input(
+    game: &mut Game,
+    peer: PeerId,
+    action: Action,
+    transport: &mut (impl Transport + ?Sized),
+) -> io::Result<()> {
+    // a client placing for another tribe is lying : no answer
+    if !Lockstep::allowed(peer, &action) {
+        return Ok(());
+    }
+    for rejection in game.submit(action) {
+        transport.send(peer, &Message::Rejected(rejection))?;
+    }
+    Ok(())
+}
+
+pub struct Server {
+    game: Game,
+    broadcaster: Broadcaster,
+    stepper: Option<Peekable<QuadUpdate>>,
+    ctx: ComputeCtx,
+    /// Between the starts of two generations.
+    period: Duration,
+    next_generation: Instant,
+    /// No generation is computed past this one.
+    last_generation: Option<u64>,
+    /// Actions received while a generation is in flight, applied once it is complete.
+    inputs: Vec<(PeerId, Action)>,
+}
+
+impl Server {
+    /// # Panics
+    /// If a client has no tribe : its id is `MAX_TRIBES` or more.
+    pub fn new(game: Game, clients: impl IntoIterator<Item = PeerId>) -> Self {
+        let clients: Vec<PeerId> = clients.into_iter().filter(|c| *c != SERVER_ID).collect();
+        assert!(
+            clients.iter().all(|c| (*c as usize) < MAX_TRIBES),
+            "client ids are tribes, below {}",
+            MAX_TRIBES
+        );
+        Self {
+            game,
+            broadcaster: Broadcaster::new(clients),
+            stepper: None,
+            ctx: ComputeCtx::default().with_constraint(SLICE),
+            period: Duration::ZERO,
+            next_generation: Instant::now(),
+            last_generation: None,
+            inputs: vec![],
+        }
+    }
+
+    /// Generations per second, at most. Unlimited by default : as fast as the server computes.
+    pub fn with_generation_rate(self, per_second: f32) -> Self {
+        Self {
+            period: Duration::from_secs_f32(1. / per_second.max(f32::EPSILON)),
+            ..self
+        }
+    }
+
+    /// Snapshots per second sent to the clients, at most.
+    pub fn with_broadcast_rate(self, per_second: u32) -> Self {
+        Self {
+            broadcaster: self.broadcaster.with_rate(per_second),
+            ..self
+        }
+    }
+
+    /// The game stops at this generation, clients still being served.
+    pub fn with_generation_limit(self, last: u64) -> Self {
+        Self {
+            last_generation: Some(last),
+            ..self
+        }
+    }
+
+    pub fn game(&self) -> &Game {
+        &self.game
+    }
+
+    pub fn broadcaster(&self) -> &Broadcaster {
+        &self.broadcaster
+    }
+
+    /// Whether the generation limit is reached.
+    pub fn is_over(&self) -> bool {
+        self.last_generation
+            .is_some_and(|last| self.game.quad().generation() >= last)
+    }
+
+    /// Sends the current state to every client, whatever the broadcast rate : ie. the last generation, once over.
+    pub fn flush(&mut self, transport: &mut (impl Transport + ?Sized)) {
+        self.broadcaster.flush(self.game.quad(), transport);
+    }
+
+    /// One round, to be called in a loop : receives actions and applies them at the next generation boundary,
+    /// computes a slice of generation, and sends snapshots to the clients.
+    pub fn update(&mut self, transport: &mut (impl Transport + ?Sized)) -> io::Result<()> {
+        let inputs = self.broadcaster.poll(self.game.quad(), transport)?;
+        self.inputs.extend(inputs);
+
+        if !self.game.quad().is_computing() {
+            // one client failing does not discard the others' actions
+            for (peer, action) in std::mem::take(&mut self.inputs) {
+                if let Err(e) = apply_input(&mut self.game, peer, action, transport) {
+                    eprintln!("client {}: {}", peer, e);
+                }
+            }
+            let now = Instant::now();
+            if self.is_over() || now < self.next_generation {
+                return Ok(());
+            }
+            self.next_generation = now + self.period;
+        }
+
+        compute::compute_until(self.game.quad_mut(), &mut self.stepper, &mut self.ctx);
+        self.game.sync();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::broadcast::Viewer;
+    use crate::net::local::LocalNetwork;
+    use crate::net::tcp::TcpTransport;
+    use crate::net::{PeerId, Transport};
+    use crate::server::{Server, SERVER_ID};
+    use quadlife::cell::State;
+    use quadlife::event::{Action, Game};
+    use quadlife::placement::{Policy, Rejection};
+    use quadlife::quad::Quad;
+    use quadlife::tribe::Tribe;
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    struct Client {
+        transport: TcpTransport,
+        viewer: Viewer,
+        quad: Quad,
+    }
+
+    impl Client {
+        fn join(me: PeerId, server: SocketAddr) -> Self {
+            Self {
+                transport: TcpTransport::bind(
+                    me,
+                    "127.0.0.1:0".parse().unwrap(),
+                    BTreeMap::from([(SERVER_ID, server)]),
+                )
+                .unwrap(),
+                viewer: Viewer::new(SERVER_ID),
+                quad: Quad::gen(State::Dead, 1, 1),
+            }
+        }
+
+        fn update(&mut self) {
+            self.viewer
+                .poll(&mut self.quad, &mut self.transport)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn check_clients_follow_the_server() {
+        let policy = Policy {
+            max_energy: 10,
+            regeneration: 0,
+            cost_per_cell: 1,
+            cooldown: 0,
+            proximity: None,
+        };
+        let game = Game::new(Quad::gen(State::Dead, 48, 32).with_seed(11), policy);
+        let mut server = Server::new(game, 1..=4).with_generation_limit(60);
+        let mut transport =
+            TcpTransport::serve(SERVER_ID, "127.0.0.1:0".parse().unwrap(), 1..=4).unwrap();
+        let addr = transport.local_addr().unwrap();
+
+        let mut clients = vec![Client::join(1, addr)];
+        let mut placed = false;
+        let deadline = Instant::now() + Duration::from_secs(20);
+        loop {
+            assert!(Instant::now() < deadline, "clients never caught up");
+            server.update(&mut transport).unwrap();
+            for c in clients.iter_mut() {
+                c.update();
+            }
+
+            let generation = server.game().quad().generation();
+            if generation >= 10 && !placed {
+                placed = true;
+                let viewer = &mut clients[0].viewer;
+                // another tribe's cells are ignored, too many cells are rejected
+                viewer.submit(Action::Place {
+                    tribe: Tribe(2),
+                    cells: vec![(1, 1)],
+                });
+                viewer.submit(Action::Place {
+                    tribe: Tribe(1),
+                    cells: (0..20).map(|x| (x, 5)).collect(),
+                });
+                viewer.submit(Action::Place {
+                    tribe: Tribe(1),
+                    cells: vec![(30, 20), (31, 20), (30, 21), (31, 21)],
+                });
+            }
+            // joining mid-game
+            if generation >= 30 && clients.len() == 1 {
+                clients.push(Client::join(3, addr));
+            }
+
+            let checksum = server.game().quad().checksum();
+            if server.is_over()
+                && clients.len() == 2
+                && clients
+                    .iter()
+                    .all(|c| c.quad.checksum() == checksum && c.quad.generation() == 60)
+            {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(
+            clients[0].viewer.last_rejection(),
+            Some(Rejection::NotEnoughEnergy {
+                needed: 20,
+                available: 10
+            })
+        );
+        let owners: Vec<Tribe> = server
+            .game()
+            .quad()
+            .owners()
+            .iter()
+            .flatten()
+            .copied()
+            .collect();
+        assert!(owners.contains(&Tribe(1)));
+        assert!(!owners.contains(&Tribe(2)));
+        let active: Vec<PeerId> = server.broadcaster().active().collect();
+        assert_eq!(active, vec![1, 3]);
+    }
+
+    #[test]
+    fn check_a_rejection_lost_keeps_the_other_actions() {
+        let policy = Policy {
+            max_energy: 10,
+            ..Policy::UNLIMITED
+        };
+        let game = Game::new(Quad::gen(State::Dead, 16, 16), policy);
+        let mut server = Server::new(game, 1..=2);
+        // client 1 left : its rejection cannot be sent
+        let network = LocalNetwork::default();
+        let mut transport = network.join(SERVER_ID);
+        network.join(2);
+        server.inputs = vec![
+            (
+                1,
+                Action::Place {
+                    tribe: Tribe(1),
+                    cells: (0..16).map(|x| (x, 5)).collect(),
+                },
+            ),
+            (
+                2,
+                Action::Place {
+                    tribe: Tribe(2),
+                    cells: vec![(1, 1)],
+                },
+            ),
+        ];
+
+        server.update(&mut transport).unwrap();
+
+        assert_eq!(server.game().quad().owner(1, 1), Some(Tribe(2)));
+    }
+
+    #[test]
+    fn check_last_generation_is_broadcast() {
+        let game = Game::new(
+            Quad::gen(State::Dead, 16, 16).with_seed(2),
+            Policy::UNLIMITED,
+        );
+        // the client joins before the end, and gets one snapshot a second
+        let mut server = Server::new(game, 1..=1)
+            .with_generation_rate(20.)
+            .with_broadcast_rate(1)
+            .with_generation_limit(5);
+        let mut transport =
+            TcpTransport::serve(SERVER_ID, "127.0.0.1:0".parse().unwrap(), 1..=1).unwrap();
+        let mut client = Client::join(1, transport.local_addr().unwrap());
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !server.is_over() || client.quad.width() != 16 {
+            assert!(Instant::now() < deadline, "client never joined");
+            server.update(&mut transport).unwrap();
+            client.update();
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        // no more rounds : only the flush sends anything
+        server.flush(&mut transport);
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while client.quad.generation() != 5 {
+            assert!(Instant::now() < deadline, "last generation never received");
+            transport.receive().unwrap();
+            client.update();
+        }
+        assert_eq!(client.quad.checksum(), server.game().quad().checksum());
+    }
+}
@@ -0,0 +1,443 @@
+use crate::net::{NetConfig, NetMode, PeerId, PeerList, TransportKind};
+use crate::setup::Board;
+use clap::Parser;
+use quadlife::rule::{Rule, Topology};
+use quadlife::score::Victory;
+use serde::{Deserialize, Deserializer};
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Config file read when no `--config` is given, if it exists.
+pub const DEFAULT_CONFIG: &str = "life_net.toml";
+
+/// How the simulation is advanced between two frames.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum UpdateMode {
+    /// One complete generation per frame.
+    #[default]
+    Full,
+    /// As much of a generation as the frame time allows.
+    Partial,
+}
+
+impl Display for UpdateMode {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpdateMode::Full => write!(f, "full"),
+            UpdateMode::Partial => write!(f, "partial"),
+        }
+    }
+}
+
+impl FromStr for UpdateMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "full" => Ok(UpdateMode::Full),
+            "partial" => Ok(UpdateMode::Partial),
+            _ => Err(format!("{:?} is not an update mode (full or partial)", s)),
+        }
+    }
+}
+
+/// Game settings, each one optional.
+/// The same fields are read from the command line and from the TOML config file, the command line taking precedence.
+#[derive(Parser, Deserialize, Debug, Default, Clone, PartialEq)]
+#[command(about = "Life Net, a multiplayer game of life")]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Overrides {
+    /// TOML config file [default: life_net.toml, if present]
+    #[arg(long)]
+    #[serde(skip)]
+    pub config: Option<PathBuf>,
+    #[arg(long)]
+    pub window_width: Option<u32>,
+    #[arg(long)]
+    pub window_height: Option<u32>,
+    /// Board width [default: window width]
+    #[arg(long)]
+    pub board_width: Option<u32>,
+    /// Board height [default: window height]
+    #[arg(long)]
+    pub board_height: Option<u32>,
+    /// Life-like rule, in B/S notation [default: B3/S23]
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub rule: Option<Rule>,
+    /// bounded or torus [default: bounded]
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub topology: Option<Topology>,
+    /// Seed of the random soup
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// PNG pattern to start from, instead of a random soup
+    #[arg(long)]
+    pub pattern: Option<PathBuf>,
+    /// [default: 60]
+    #[arg(long)]
+    pub target_fps: Option<f32>,
+    /// full or partial [default: full]
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub update_mode: Option<UpdateMode>,
+    /// domination:<percent>, last-alive or time:<generations> [default: none, endless game]
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub victory: Option<Victory>,
+    /// Multiplayer : all peers of the game, ie. 0=192.168.1.10:4000,1=192.168.1.11:4000
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub peers: Option<PeerList>,
+    /// Multiplayer : our id in the peer list [default: 0]
+    #[arg(long)]
+    pub peer_id: Option<PeerId>,
+    /// Multiplayer : tcp or udp [default: tcp]
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub transport: Option<TransportKind>,
+    /// Multiplayer : generations between an action and its application [default: 4]
+    #[arg(long)]
+    pub input_delay: Option<u64>,
+    /// Multiplayer : lockstep, rollback, or broadcast of the host's state to viewers [default: lockstep]
+    #[arg(long)]
+    #[serde(deserialize_with = "parsed")]
+    pub net_mode: Option<NetMode>,
+    /// Multiplayer : generations between two desync checks in lockstep [default: 32]
+    #[arg(long)]
+    pub checksum_interval: Option<u64>,
+    /// Multiplayer : snapshots per second sent by the host in broadcast mode [default: 10]
+    #[arg(long)]
+    pub broadcast_rate: Option<u32>,
+}
+
+/// Deserializes any type parseable from a string, like the command line does.
+fn parsed<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr<Err = String>,
+{
+    let s = String::deserialize(deserializer)?;
+    T::from_str(&s).map(Some).map_err(serde::de::Error::custom)
+}
+
+impl Overrides {
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let content = std::fs::read_to_string(path.as_ref())
+            .map_err(|e| format!("cannot read {}: {}", path.as_ref().display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("in {}: {}", path.as_ref().display(), e))
+    }
+
+    /// Values set in `other` replace the ones in `self`.
+    pub fn overridden_by(self, other: Overrides) -> Self {
+        Self {
+            config: other.config.or(self.config),
+            window_width: other.window_width.or(self.window_width),
+            window_height: other.window_height.or(self.window_height),
+            board_width: other.board_width.or(self.board_width),
+            board_height: other.board_height.or(self.board_height),
+            rule: other.rule.or(self.rule),
+            topology: other.topology.or(self.topology),
+            seed: other.seed.or(self.seed),
+            pattern: other.pattern.or(self.pattern),
+            target_fps: other.target_fps.or(self.target_fps),
+            update_mode: other.update_mode.or(self.update_mode),
+            victory: other.victory.or(self.victory),
+            peers: other.peers.or(self.peers),
+            peer_id: other.peer_id.or(self.peer_id),
+            transport: other.transport.or(self.transport),
+            input_delay: other.input_delay.or(self.input_delay),
+            net_mode: other.net_mode.or(self.net_mode),
+            checksum_interval: other.checksum_interval.or(self.checksum_interval),
+            broadcast_rate: other.broadcast_rate.or(self.broadcast_rate),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Settings {
+    pub window_width: u32,
+    pub window_height: u32,
+    pub board: Board,
+    pub target_fps: f32,
+    pub update_mode: UpdateMode,
+    pub victory: Option<Victory>,
+    /// None for a single player game.
+    pub network: Option<NetConfig>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::from(Overrides::default())
+    }
+}
+
+impl From<Overrides> for Settings {
+    fn from(o: Overrides) -> Self {
+        let window_width = o.window_width.unwrap_or(256);
+        let window_height = o.window_height.unwrap_or(256);
+
+        let default_board = Board::default();
+        Self {
+            window_width,
+            window_height,
+            board: Board {
+                width: o.board_width.unwrap_or(window_width),
+                height: o.board_height.unwrap_or(window_height),
+                rule: o.rule.unwrap_or(default_board.rule),
+                topology: o.topology.unwrap_or(default_board.topology),
+                seed: o.seed,
+                pattern: o.pattern,
+            },
+            target_fps: o.target_fps.unwrap_or(60.),
+            update_mode: o.update_mode.unwrap_or_default(),
+            victory: o.victory,
+            network: o.peers.map(|peers| NetConfig {
+                me: o.peer_id.unwrap_or(0),
+                peers,
+                transport: o.transport.unwrap_or_default(),
+                input_delay: o.input_delay.unwrap_or(4),
+                mode: o.net_mode.unwrap_or_default(),
+                checksum_interval: o.checksum_interval.unwrap_or(32),
+                broadcast_rate: o.broadcast_rate.unwrap_or(10),
+            }),
+        }
+    }
+}
+
+impl Settings {
+    /// Settings from the command line, on top of the config file, on top of defaults.
+    pub fn from_args() -> Result<Self, String> {
+        Self::resolve(Overrides::parse())
+    }
+
+    pub fn resolve(args: Overrides) -> Result<Self, String> {
+        Self::resolve_in(args, Path::new("."))
+    }
+
+    /// Same as `resolve`, looking for the default config file in `dir` instead of the current directory.
+    pub fn resolve_in(args: Overrides, dir: &Path) -> Result<Self, String> {
+        let default_config = dir.join(DEFAULT_CONFIG);
+        let file = match &args.config {
+            Some(path) => Overrides::from_file(path)?,
+            None if default_config.exists() => Overrides::from_file(&default_config)?,
+            None => Overrides::default(),
+        };
+        let settings = Self::from(file.overridden_by(args));
+        // frame times are computed from it
+        if !(settings.target_fps.is_finite() && settings.target_fps > 0.) {
+            return Err(format!(
+                "the target fps must be a positive number, not {}",
+                settings.target_fps
+            ));
+        }
+        if let Some(network) = &settings.network {
+            network.validate()?;
+            // peers compute whole generations, in step
+            if settings.update_mode == UpdateMode::Partial {
+                return Err("a multiplayer game needs the full update mode".to_string());
+            }
+            // every peer must start from the same board, unless it is sent by the host
+            if network.mode != NetMode::Broadcast
+                && settings.board.seed.is_none()
+                && settings.board.pattern.is_none()
+            {
+                return Err("a multiplayer game needs a --seed or a --pattern".to_string());
+            }
+        }
+        Ok(settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::{NetMode, TransportKind};
+    use crate::settings::{Overrides, Settings, UpdateMode, DEFAULT_CONFIG};
+    use clap::Parser;
+    use quadlife::rule::{Rule, Topology};
+    use quadlife::score::Victory;
+    use std::path::PathBuf;
+
+    /// A new directory of its own for each test, with the given default config file : tests do not depend on
+    /// a `life_net.toml` in the current directory.
+    fn config_dir(name: &str, config: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("life_net_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        match config {
+            Some(config) => std::fs::write(dir.join(DEFAULT_CONFIG), config).unwrap(),
+            None => assert!(!dir.join(DEFAULT_CONFIG).exists()),
+        }
+        dir
+    }
+
+    fn resolve(args: Overrides) -> Result<Settings, String> {
+        Settings::resolve_in(args, &config_dir("none", None))
+    }
+
+    #[test]
+    fn check_defaults() {
+        let s = Settings::default();
+        assert_eq!(s.window_width, 256);
+        assert_eq!(s.board.width, 256);
+        assert_eq!(s.board.rule, Rule::CONWAY);
+        assert_eq!(s.target_fps, 60.);
+        assert_eq!(s.update_mode, UpdateMode::Full);
+    }
+
+    #[test]
+    fn check_board_follows_window_unless_set() {
+        let s = Settings::from(Overrides {
+            window_width: Some(640),
+            window_height: Some(100_000),
+            board_height: Some(32),
+            ..Overrides::default()
+        });
+        assert_eq!(s.board.width, 640);
+        assert_eq!(s.board.height, 32);
+
+        let s = Settings::from(Overrides {
+            window_height: Some(100_000),
+            ..Overrides::default()
+        });
+        assert_eq!(s.board.height, 100_000);
+    }
+
+    #[test]
+    fn check_toml_file() {
+        let file: Overrides = toml::from_str(
+            r#"
+            window-width = 512
+            rule = "B36/S23"
+            topology = "torus"
+            update-mode = "partial"
+            seed = 7
+            victory = "domination:75"
+            "#,
+        )
+        .unwrap();
+        let s = Settings::from(file);
+
+        assert_eq!(s.window_width, 512);
+        assert_eq!(s.board.rule, Rule::new(&[3, 6], &[2, 3]));
+        assert_eq!(s.board.topology, Topology::Torus);
+        assert_eq!(s.update_mode, UpdateMode::Partial);
+        assert_eq!(s.board.seed, Some(7));
+        assert_eq!(s.victory, Some(Victory::Domination(0.75)));
+    }
+
+    #[test]
+    fn check_example_config_is_valid() {
+        let example: Overrides =
+            toml::from_str(include_str!("../../life_net.example.toml")).unwrap();
+        assert_eq!(Settings::from(example), Settings::default());
+    }
+
+    #[test]
+    fn check_invalid_toml_values() {
+        assert!(toml::from_str::<Overrides>("rule = \"B3\"").is_err());
+        assert!(toml::from_str::<Overrides>("unknown = 3").is_err());
+    }
+
+    #[test]
+    fn check_network() {
+        assert_eq!(Settings::default().network, None);
+
+        let args = Overrides::parse_from([
+            "life_net",
+            "--peers",
+            "0=127.0.0.1:4000,1=127.0.0.1:4001",
+            "--peer-id",
+            "1",
+            "--transport",
+            "udp",
+            "--seed",
+            "3",
+        ]);
+        let network = resolve(args).unwrap().network.unwrap();
+        assert_eq!(network.me, 1);
+        assert_eq!(network.transport, TransportKind::Udp);
+        assert_eq!(network.input_delay, 4);
+        assert_eq!(network.mode, NetMode::Lockstep);
+        assert_eq!(network.checksum_interval, 32);
+
+        let args = Overrides::parse_from([
+            "life_net",
+            "--peers",
+            "0=127.0.0.1:4000,1=127.0.0.1:4001",
+            "--seed",
+            "3",
+            "--update-mode",
+            "partial",
+        ]);
+        assert!(resolve(args).is_err());
+
+        let args =
+            Overrides::parse_from(["life_net", "--peers", "0=127.0.0.1:4000,1=127.0.0.1:4001"]);
+        assert!(resolve(args).is_err());
+        let args =
+            Overrides::parse_from(["life_net", "--peers", "1=127.0.0.1:4001", "--seed", "3"]);
+        assert!(resolve(args).is_err());
+        // tribes 0 to 5 only
+        let args = Overrides::parse_from([
+            "life_net",
+            "--peers",
+            "0=127.0.0.1:4000,6=127.0.0.1:4006",
+            "--seed",
+            "3",
+        ]);
+        assert!(resolve(args).is_err());
+
+        // viewers get the board from the host
+        let args = Overrides::parse_from([
+            "life_net",
+            "--peers",
+            "0=127.0.0.1:4000,1=127.0.0.1:4001",
+            "--net-mode",
+            "broadcast",
+            "--broadcast-rate",
+            "20",
+        ]);
+        let network = resolve(args).unwrap().network.unwrap();
+        assert_eq!(network.mode, NetMode::Broadcast);
+        assert_eq!(network.broadcast_rate, 20);
+    }
+
+    #[test]
+    fn check_target_fps_must_be_positive() {
+        let args = Overrides::parse_from(["life_net", "--target-fps", "0"]);
+        assert!(resolve(args).is_err());
+        let args = Overrides::parse_from(["life_net", "--target-fps=-30"]);
+        assert!(resolve(args).is_err());
+        let file: Overrides = toml::from_str("target-fps = nan").unwrap();
+        assert!(resolve(file).is_err());
+
+        let args = Overrides::parse_from(["life_net", "--target-fps", "0.5"]);
+        assert_eq!(resolve(args).unwrap().target_fps, 0.5);
+    }
+
+    #[test]
+    fn check_args_override_file() {
+        let file: Overrides = toml::from_str("target-fps = 30.0\nseed = 7").unwrap();
+        let args = Overrides::parse_from(["life_net", "--seed", "9", "--topology", "torus"]);
+
+        let s = Settings::from(file.overridden_by(args));
+
+        assert_eq!(s.target_fps, 30.);
+        assert_eq!(s.board.seed, Some(9));
+        assert_eq!(s.board.topology, Topology::Torus);
+    }
+
+    #[test]
+    fn check_default_config_file() {
+        let dir = config_dir("default", Some("target-fps = 30.0\nseed = 7"));
+        let args = Overrides::parse_from(["life_net", "--seed", "9"]);
+
+        let s = Settings::resolve_in(args, &dir).unwrap();
+
+        assert_eq!(s.target_fps, 30.);
+        assert_eq!(s.board.seed, Some(9));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,101 @@
+use figment::graphics::bitmap::BitmapError;
+use quadlife::cell;
+use quadlife::quad::Quad;
+use quadlife::rule::{Rule, Topology};
+use std::path::PathBuf;
+
+/// Everything needed to build the initial board of a simulation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Board {
+    pub width: u32,
+    pub height: u32,
+    pub rule: Rule,
+    pub topology: Topology,
+    /// Seed of the random soup. A random seed is picked if none is given.
+    pub seed: Option<u64>,
+    /// PNG pattern to start from, instead of a random soup. Its size defines the board size.
+    pub pattern: Option<PathBuf>,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Self {
+            width: 256,
+            height: 256,
+            rule: Rule::default(),
+            topology: Topology::default(),
+            seed: None,
+            pattern: None,
+        }
+    }
+}
+
+impl Board {
+    pub fn build(&self) -> Result<Quad, BitmapError> {
+        let quad = match &self.pattern {
+            Some(path) => Quad::load_png(path)?,
+            None => Quad::gen(cell::State::Dead, self.width, self.height)
+                .with_seed(self.seed.unwrap_or_else(random_seed)),
+        };
+        Ok(quad.with_rule(self.rule).with_topology(self.topology))
+    }
+}
+
+fn random_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::setup::Board;
+    use figment::graphics::tiled::TiledTexture;
+    use figment::graphics::Viewable;
+
+    #[test]
+    fn check_same_seed_same_soup() {
+        let board = Board {
+            width: 32,
+            height: 16,
+            seed: Some(42),
+            ..Board::default()
+        };
+
+        let q1 = board.build().unwrap();
+        let q2 = board.build().unwrap();
+
+        assert_eq!(q1.width(), 32);
+        assert_eq!(q1.height(), 16);
+        assert_eq!(q1.cells(), q2.cells());
+    }
+
+    #[test]
+    fn check_board_wider_than_u16() {
+        let board = Board {
+            width: 70_000,
+            height: 2,
+            seed: Some(3),
+            ..Board::default()
+        };
+        let quad = board.build().unwrap();
+        assert_eq!(quad.width(), 70_000);
+
+        // drawn in tiles
+        let image = quad.render().borrow().clone();
+        let tiled = TiledTexture::from_image(&image);
+        assert_eq!((tiled.width(), tiled.height()), (70_000, 2));
+        assert_eq!(tiled.get_pixel(69_999, 1), image.get_pixel(69_999, 1));
+    }
+
+    #[test]
+    fn check_missing_pattern_is_an_error() {
+        let board = Board {
+            pattern: Some("does/not/exist.png".into()),
+            ..Board::default()
+        };
+
+        assert!(board.build().is_err());
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::BTreeSet;
+
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Shape {
+    #[default]
+    Square,
+    Disc,
+}
+
+/// The set of cells touched when painting at one position.
+/// Coordinates are signed : a brush may overflow the borders of a quad.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Brush {
+    /// 0 is a single cell
+    pub radius: u32,
+    pub shape: Shape,
+}
+
+impl Brush {
+    pub fn new(radius: u32, shape: Shape) -> Self {
+        Self { radius, shape }
+    }
+
+    pub fn grow(&mut self) {
+        self.radius = (self.radius + 1).min(64);
+    }
+
+    pub fn shrink(&mut self) {
+        self.radius = self.radius.saturating_sub(1);
+    }
+
+    /// Cells covered by the brush centered on (x, y).
+    pub fn cells(&self, x: i64, y: i64) -> impl Iterator<Item = (i64, i64)> + '_ {
+        let r = self.radius as i64;
+        (-r..=r)
+            .flat_map(move |j| (-r..=r).map(move |i| (i, j)))
+            .filter(move |(i, j)| match self.shape {
+                Shape::Square => true,
+                Shape::Disc => i * i + j * j <= r * r,
+            })
+            .map(move |(i, j)| (x + i, y + j))
+    }
+
+    /// Cells covered by dragging the brush from one position to another, without gaps.
+    pub fn stroke(&self, from: (i64, i64), to: (i64, i64)) -> BTreeSet<(i64, i64)> {
+        line(from, to)
+            .into_iter()
+            .flat_map(|(x, y)| self.cells(x, y).collect::<Vec<_>>())
+            .collect()
+    }
+}
+
+/// Bresenham line, both ends included.
+pub fn line(from: (i64, i64), to: (i64, i64)) -> Vec<(i64, i64)> {
+    let (mut x, mut y) = from;
+    let dx = (to.0 - x).abs();
+    let dy = -(to.1 - y).abs();
+    let sx = if x < to.0 { 1 } else { -1 };
+    let sy = if y < to.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+
+    let mut points = vec![];
+    loop {
+        points.push((x, y));
+        if (x, y) == to {
+            break;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+    points
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::brush::{line, Brush, Shape};
+
+    #[test]
+    fn check_single_cell_brush() {
+        let b = Brush::default();
+        assert_eq!(b.cells(3, 4).collect::<Vec<_>>(), vec![(3, 4)]);
+    }
+
+    #[test]
+    fn check_brush_shapes() {
+        assert_eq!(Brush::new(1, Shape::Square).cells(0, 0).count(), 9);
+        assert_eq!(Brush::new(1, Shape::Disc).cells(0, 0).count(), 5);
+        assert_eq!(Brush::new(2, Shape::Disc).cells(0, 0).count(), 13);
+    }
+
+    #[test]
+    fn check_line_has_no_gaps() {
+        let l = line((0, 0), (5, 2));
+        assert_eq!(l.first(), Some(&(0, 0)));
+        assert_eq!(l.last(), Some(&(5, 2)));
+        assert_eq!(l.len(), 6);
+        for w in l.windows(2) {
+            assert!((w[1].0 - w[0].0).abs() <= 1 && (w[1].1 - w[0].1).abs() <= 1);
+        }
+        assert_eq!(line((2, 2), (2, 2)), vec![(2, 2)]);
+        assert_eq!(line((0, 3), (0, 0)), vec![(0, 3), (0, 2), (0, 1), (0, 0)]);
+    }
+
+    #[test]
+    fn check_stroke_deduplicates() {
+        let b = Brush::new(1, Shape::Square);
+        // 3x3 brush dragged 2 cells to the right covers a 5x3 rectangle
+        assert_eq!(b.stroke((0, 0), (2, 0)).len(), 15);
+    }
+}
@@ -1,8 +1,9 @@
 use crate::cell::State::{Alive, Dead};
+use crate::rule::{Rule, Topology};
 use grid::Grid;
 use macroquad::color; // TODO : replace with our color modules...
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum State {
     Alive,
     Dead,
@@ -11,8 +12,8 @@ pub enum State {
 pub const ALIVE: color::Color = color::BLACK;
 pub const DEAD: color::Color = color::WHITE;
 
-#[allow(unused)]
-pub(crate) fn state(color: &[u8; 4]) -> State {
+/// Maps a pixel colour back to a cell state. Any colour that is not exactly ALIVE is considered dead.
+pub fn state(color: &[u8; 4]) -> State {
     if <color::Color as Into<[u8; 4]>>::into(ALIVE) == *color {
         Alive
     } else if <color::Color as Into<[u8; 4]>>::into(DEAD) == *color {
@@ -29,14 +30,22 @@ pub(crate) fn color(state: State) -> color::Color {
     }
 }
 
-fn neighbours_count(cells: &Grid<State>, x: i32, y: i32) -> i32 {
+// Note : x is the column, y is the row in the grid.
+fn neighbours_count_in(cells: &Grid<State>, x: i32, y: i32, topology: Topology) -> i32 {
     let mut neighbors_count = 0;
 
     for j in -1i32..=1 {
         for i in -1i32..=1 {
             // if not the cell itself
             if i != 0 || j != 0 {
-                match cells.get((x + i) as usize, (y + j) as usize) {
+                let neighbour = match topology {
+                    Topology::Bounded => cells.get(y + j, x + i),
+                    Topology::Torus => cells.get(
+                        (y + j).rem_euclid(cells.rows() as i32),
+                        (x + i).rem_euclid(cells.cols() as i32),
+                    ),
+                };
+                match neighbour {
                     None => {} // out of bounds
                     Some(&Alive) => {
                         neighbors_count += 1;
@@ -49,33 +58,36 @@ fn neighbours_count(cells: &Grid<State>, x: i32, y: i32) -> i32 {
     return neighbors_count;
 }
 
+/// Conway's game of life update, on a bounded grid.
 pub fn update(cells: &Grid<State>, x: i32, y: i32) -> Option<State> {
-    let neighbors_count = neighbours_count(&cells, x, y);
-
-    let current_cell = cells.get(x, y)?;
-
-    // Note : current rules : b3s23 -> devise a way to parameterize the life rules from startup ?
-    Some(match (current_cell, neighbors_count) {
-        // Rule 1: Any live cell with fewer than two live neighbours
-        // dies, as if caused by underpopulation.
-        (&State::Alive, x) if x < 2 => State::Dead,
-        // Rule 2: Any live cell with two or three live neighbours
-        // lives on to the next generation.
-        (&State::Alive, 2) | (State::Alive, 3) => State::Alive,
-        // Rule 3: Any live cell with more than three live
-        // neighbours dies, as if by overpopulation.
-        (&State::Alive, x) if x > 3 => State::Dead,
-        // Rule 4: Any dead cell with exactly three live neighbours
-        // becomes a live cell, as if by reproduction.
-        (&State::Dead, 3) => State::Alive,
-        // All other cells remain in the same state.
-        (otherwise, _) => *otherwise,
+    update_with(cells, x, y, &Rule::CONWAY, Topology::Bounded)
+}
+
+pub fn update_with(
+    cells: &Grid<State>,
+    x: i32,
+    y: i32,
+    rule: &Rule,
+    topology: Topology,
+) -> Option<State> {
+    let neighbors_count = neighbours_count_in(&cells, x, y, topology);
+
+    let current_cell = cells.get(y, x)?;
+
+    Some(match current_cell {
+        // Any live cell with a number of neighbours allowed by the rule survives, otherwise it dies.
+        &State::Alive if rule.survives(neighbors_count) => State::Alive,
+        &State::Alive => State::Dead,
+        // Any dead cell with a number of neighbours allowed by the rule becomes a live cell.
+        &State::Dead if rule.born(neighbors_count) => State::Alive,
+        &State::Dead => State::Dead,
     })
 }
 
 #[cfg(test)]
 mod tests {
     use crate::cell;
+    use crate::rule::{Rule, Topology};
     use grid::{grid, Grid};
     use test::Bencher;
 
@@ -94,31 +106,34 @@ mod tests {
         let sen = grid![[d, d, d] [d, a, d] [d, d, a]];
         let swn = grid![[d, d, d] [d, a, d][ a, d, d]];
 
-        assert_eq!(cell::neighbours_count(&alone, 1, 1), 0);
+        assert_eq!(
+            cell::neighbours_count_in(&alone, 1, 1, Topology::Bounded),
+            0
+        );
         assert_eq!(cell::update(&alone, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&nn, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&nn, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&nn, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&sn, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&sn, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&sn, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&en, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&en, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&en, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&wn, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&wn, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&wn, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&nen, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&nen, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&nen, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&nwn, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&nwn, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&nwn, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&sen, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&sen, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&sen, 1, 1), Some(cell::State::Dead));
 
-        assert_eq!(cell::neighbours_count(&swn, 1, 1), 1);
+        assert_eq!(cell::neighbours_count_in(&swn, 1, 1, Topology::Bounded), 1);
         assert_eq!(cell::update(&swn, 1, 1), Some(cell::State::Dead));
     }
 
@@ -140,10 +155,13 @@ mod tests {
         ];
         //TODO : combinations for 6, 5 and 4...
 
-        assert_eq!(cell::neighbours_count(&surrounded, 1, 1), 8);
+        assert_eq!(
+            cell::neighbours_count_in(&surrounded, 1, 1, Topology::Bounded),
+            8
+        );
         assert_eq!(cell::update(&surrounded, 1, 1), Some(cell::State::Dead));
         for s in seven {
-            assert_eq!(cell::neighbours_count(&s, 1, 1), 7);
+            assert_eq!(cell::neighbours_count_in(&s, 1, 1, Topology::Bounded), 7);
             assert_eq!(cell::update(&s, 1, 1), Some(cell::State::Dead));
         }
     }
@@ -157,10 +175,13 @@ mod tests {
         let two = grid![[d, a, a][ d, a, d][ d, d, d]];
         let three = grid![[d, d, a][ d, a, a][a, d, d]];
 
-        assert_eq!(cell::neighbours_count(&two, 1, 1), 2);
+        assert_eq!(cell::neighbours_count_in(&two, 1, 1, Topology::Bounded), 2);
         assert_eq!(cell::update(&two, 1, 1), Some(cell::State::Alive));
 
-        assert_eq!(cell::neighbours_count(&three, 1, 1), 3);
+        assert_eq!(
+            cell::neighbours_count_in(&three, 1, 1, Topology::Bounded),
+            3
+        );
         assert_eq!(cell::update(&three, 1, 1), Some(cell::State::Alive));
     }
 
@@ -171,7 +192,10 @@ mod tests {
 
         let three = grid![[d, d, a][ d, d, a][ a, d, d]];
 
-        assert_eq!(cell::neighbours_count(&three, 1, 1), 3);
+        assert_eq!(
+            cell::neighbours_count_in(&three, 1, 1, Topology::Bounded),
+            3
+        );
         assert_eq!(cell::update(&three, 1, 1), Some(cell::State::Alive));
     }
 
@@ -182,7 +206,7 @@ mod tests {
 
         let two = grid![[d, d, a][ d, d, d][a, d, d]];
 
-        assert_eq!(cell::neighbours_count(&two, 1, 1), 2);
+        assert_eq!(cell::neighbours_count_in(&two, 1, 1, Topology::Bounded), 2);
         assert_eq!(cell::update(&two, 1, 1), Some(cell::State::Dead));
     }
 
@@ -193,10 +217,41 @@ mod tests {
 
         let four = grid![[d, d, a][d, d, a][a, d, a]];
 
-        assert_eq!(cell::neighbours_count(&four, 1, 1), 4);
+        assert_eq!(cell::neighbours_count_in(&four, 1, 1, Topology::Bounded), 4);
         assert_eq!(cell::update(&four, 1, 1), Some(cell::State::Dead));
     }
 
+    #[test]
+    fn check_torus_wraps_around() {
+        let a = cell::State::Alive;
+        let d = cell::State::Dead;
+
+        // neighbours only across the borders
+        let g = grid![[d, d, d, a][d, d, d, a][d, d, d, a]];
+
+        assert_eq!(cell::neighbours_count_in(&g, 0, 1, Topology::Bounded), 0);
+        assert_eq!(cell::neighbours_count_in(&g, 0, 1, Topology::Torus), 3);
+        assert_eq!(
+            cell::update_with(&g, 0, 1, &Rule::CONWAY, Topology::Torus),
+            Some(cell::State::Alive)
+        );
+    }
+
+    #[test]
+    fn check_highlife_birth() {
+        let a = cell::State::Alive;
+        let d = cell::State::Dead;
+
+        let six = grid![[a, a, a][a, d, a][a, d, d]];
+        let highlife = Rule::new(&[3, 6], &[2, 3]);
+
+        assert_eq!(cell::update(&six, 1, 1), Some(cell::State::Dead));
+        assert_eq!(
+            cell::update_with(&six, 1, 1, &highlife, Topology::Bounded),
+            Some(cell::State::Alive)
+        );
+    }
+
     #[bench]
     fn bench_update(b: &mut Bencher) {
         let a = cell::State::Alive;
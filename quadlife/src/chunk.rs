@@ -0,0 +1,88 @@
+//! Chunks : a board cut in fixed size squares, to sync only what a viewer looks at.
+//! There is no multi quad world yet (see `world`) : chunks are views on a single quad.
+use crate::desync::Region;
+
+/// Side of the square chunks, in cells.
+pub const CHUNK_SIZE: u32 = 32;
+
+/// Coordinates of a chunk, in chunks : it covers cells from (x * CHUNK_SIZE, y * CHUNK_SIZE).
+pub type ChunkId = (u32, u32);
+
+/// Cells of a chunk, cut at the edges of the board. Empty if the chunk is out of the board.
+pub fn region(chunk: ChunkId, width: usize, height: usize) -> Region {
+    let (x, y) = (chunk.0 * CHUNK_SIZE, chunk.1 * CHUNK_SIZE);
+    let clip = |start: u32, size: usize| (size as u32).saturating_sub(start).min(CHUNK_SIZE);
+    Region {
+        x,
+        y,
+        width: clip(x, width),
+        height: clip(y, height),
+    }
+}
+
+/// Chunks of the board overlapping an area, row major.
+pub fn covering(area: Region, width: usize, height: usize) -> Vec<ChunkId> {
+    let right = (area.x.saturating_add(area.width)).min(width as u32);
+    let bottom = (area.y.saturating_add(area.height)).min(height as u32);
+    if area.x >= right || area.y >= bottom {
+        return vec![];
+    }
+    let columns = area.x / CHUNK_SIZE..(right - 1) / CHUNK_SIZE + 1;
+    (area.y / CHUNK_SIZE..(bottom - 1) / CHUNK_SIZE + 1)
+        .flat_map(|y| columns.clone().map(move |x| (x, y)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::{covering, region, CHUNK_SIZE};
+    use crate::desync::Region;
+
+    #[test]
+    fn check_covering() {
+        let area = |x, y, width, height| Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        assert_eq!(covering(area(0, 0, 1, 1), 100, 50), vec![(0, 0)]);
+        assert_eq!(
+            covering(area(31, 10, 2, 30), 100, 50),
+            vec![(0, 0), (1, 0), (0, 1), (1, 1)]
+        );
+        // clipped to the board
+        assert_eq!(
+            covering(area(90, 40, 500, 500), 100, 50),
+            vec![(2, 1), (3, 1)]
+        );
+        assert_eq!(covering(area(100, 0, 10, 10), 100, 50), vec![]);
+        assert_eq!(covering(area(10, 10, 0, 10), 100, 50), vec![]);
+        // the whole board
+        assert_eq!(covering(area(0, 0, 100, 50), 100, 50).len(), 8);
+    }
+
+    #[test]
+    fn check_region() {
+        assert_eq!(
+            region((1, 0), 100, 50),
+            Region {
+                x: CHUNK_SIZE,
+                y: 0,
+                width: CHUNK_SIZE,
+                height: CHUNK_SIZE
+            }
+        );
+        // cut at the edges
+        assert_eq!(
+            region((3, 1), 100, 50),
+            Region {
+                x: 96,
+                y: 32,
+                width: 4,
+                height: 18
+            }
+        );
+        assert_eq!(region((4, 0), 100, 50).width, 0);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::cell;
+use crate::placement::Rejection;
+use crate::rule::{Rule, Topology};
+use crate::tribe::Tribe;
+use std::fmt::{Display, Formatter};
+
+/// A compact binary encoding, little endian, with no padding nor alignment,
+/// so that every peer reads the exact same bytes, whatever its platform.
+pub trait Encode {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![];
+        self.encode(&mut out);
+        out
+    }
+}
+
+pub trait Decode: Sized {
+    /// Reads a value from the front of `input`, advancing it past the bytes read.
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+
+    /// Decodes a whole buffer, trailing bytes being an error.
+    fn from_bytes(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+        let value = Self::decode(&mut bytes)?;
+        if bytes.is_empty() {
+            Ok(value)
+        } else {
+            Err(DecodeError::Trailing(bytes.len()))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended in the middle of a value.
+    UnexpectedEnd,
+    /// Unknown variant tag for the named type.
+    InvalidTag(&'static str, u8),
+    /// Bytes left after the value.
+    Trailing(usize),
+    Invalid(String),
+}
+
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of input"),
+            DecodeError::InvalidTag(t, tag) => write!(f, "invalid tag {} for {}", tag, t),
+            DecodeError::Trailing(n) => write!(f, "{} trailing bytes", n),
+            DecodeError::Invalid(reason) => write!(f, "invalid data: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Splits the first `n` bytes off `input`.
+pub(crate) fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < n {
+        return Err(DecodeError::UnexpectedEnd);
+    }
+    let (head, tail) = input.split_at(n);
+    *input = tail;
+    Ok(head)
+}
+
+macro_rules! impl_codec_int {
+    ($($t:ty),*) => {
+        $(
+            impl Encode for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+            }
+
+            impl Decode for $t {
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let bytes = take(input, std::mem::size_of::<$t>())?;
+                    Ok(<$t>::from_le_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+impl_codec_int!(u8, u16, u32, u64, i64);
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.len() as u32).encode(out);
+        for v in self {
+            v.encode(out);
+        }
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = u32::decode(input)? as usize;
+        // do not trust the length for the allocation : each element takes at least a byte.
+        let mut v = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            v.push(T::decode(input)?);
+        }
+        Ok(v)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok((A::decode(input)?, B::decode(input)?))
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => 0u8.encode(out),
+            Some(v) => {
+                1u8.encode(out);
+                v.encode(out);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            tag => Err(DecodeError::InvalidTag("Option", tag)),
+        }
+    }
+}
+
+impl Encode for cell::State {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            cell::State::Dead => 0,
+            cell::State::Alive => 1,
+        };
+        tag.encode(out);
+    }
+}
+
+impl Decode for cell::State {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(cell::State::Dead),
+            1 => Ok(cell::State::Alive),
+            tag => Err(DecodeError::InvalidTag("State", tag)),
+        }
+    }
+}
+
+impl Encode for Tribe {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+    }
+}
+
+impl Decode for Tribe {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Tribe(u8::decode(input)?))
+    }
+}
+
+impl Encode for Rule {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.birth.encode(out);
+        self.survival.encode(out);
+    }
+}
+
+impl Decode for Rule {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let (birth, survival) = (u16::decode(input)?, u16::decode(input)?);
+        if (birth | survival) >> 9 != 0 {
+            return Err(DecodeError::Invalid(format!(
+                "neighbour count above 8 in rule {:#x}/{:#x}",
+                birth, survival
+            )));
+        }
+        Ok(Rule { birth, survival })
+    }
+}
+
+impl Encode for Topology {
+    fn encode(&self, out: &mut Vec<u8>) {
+        let tag: u8 = match self {
+            Topology::Bounded => 0,
+            Topology::Torus => 1,
+        };
+        tag.encode(out);
+    }
+}
+
+impl Decode for Topology {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Topology::Bounded),
+            1 => Ok(Topology::Torus),
+            tag => Err(DecodeError::InvalidTag("Topology", tag)),
+        }
+    }
+}
+
+impl Encode for Rejection {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Rejection::OutOfBounds => 0u8.encode(out),
+            Rejection::NotEnoughEnergy { needed, available } => {
+                1u8.encode(out);
+                needed.encode(out);
+                available.encode(out);
+            }
+            Rejection::Cooldown { remaining } => {
+                2u8.encode(out);
+                remaining.encode(out);
+            }
+            Rejection::TooFar { x, y } => {
+                3u8.encode(out);
+                x.encode(out);
+                y.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Rejection {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Rejection::OutOfBounds),
+            1 => Ok(Rejection::NotEnoughEnergy {
+                needed: u32::decode(input)?,
+                available: u32::decode(input)?,
+            }),
+            2 => Ok(Rejection::Cooldown {
+                remaining: u64::decode(input)?,
+            }),
+            3 => Ok(Rejection::TooFar {
+                x: i64::decode(input)?,
+                y: i64::decode(input)?,
+            }),
+            tag => Err(DecodeError::InvalidTag("Rejection", tag)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::codec::{Decode, DecodeError, Encode};
+    use crate::placement::Rejection;
+    use crate::rule::{Rule, Topology};
+    use crate::tribe::Tribe;
+
+    #[test]
+    fn check_little_endian() {
+        assert_eq!(0x0102u16.to_bytes(), vec![2, 1]);
+        assert_eq!(
+            (-2i64).to_bytes(),
+            vec![0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+
+    #[test]
+    fn check_roundtrips() {
+        let v: Vec<(i64, i64)> = vec![(0, -1), (i64::MAX, 3)];
+        assert_eq!(Vec::<(i64, i64)>::from_bytes(&v.to_bytes()), Ok(v));
+
+        let rule = Rule::new(&[3, 6], &[2, 3]);
+        assert_eq!(Rule::from_bytes(&rule.to_bytes()), Ok(rule));
+        assert_eq!(
+            Topology::from_bytes(&Topology::Torus.to_bytes()),
+            Ok(Topology::Torus)
+        );
+
+        let rejection = Rejection::TooFar { x: -3, y: 7 };
+        assert_eq!(Rejection::from_bytes(&rejection.to_bytes()), Ok(rejection));
+
+        let owner = Some((State::Alive, Some(Tribe(3))));
+        assert_eq!(Option::from_bytes(&owner.to_bytes()), Ok(owner));
+        assert_eq!(Option::<u64>::from_bytes(&[0]), Ok(None));
+    }
+
+    #[test]
+    fn check_invalid_input() {
+        assert_eq!(u32::from_bytes(&[1, 2]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(u8::from_bytes(&[1, 2]), Err(DecodeError::Trailing(1)));
+        assert_eq!(
+            Topology::from_bytes(&[7]),
+            Err(DecodeError::InvalidTag("Topology", 7))
+        );
+        // a huge announced length must not allocate nor panic
+        assert_eq!(
+            Vec::<u64>::from_bytes(&u32::MAX.to_bytes()),
+            Err(DecodeError::UnexpectedEnd)
+        );
+    }
+}
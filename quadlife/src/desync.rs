@@ -0,0 +1,601 @@
+//! Desync detection between peers simulating the same game : every `interval` generations,
+//! each peer sends a `Checkpoint` of its state, and compares the ones it receives with its own.
+//! A checkpoint holds a checksum per region, so a mismatch is located right away ;
+//! the states of the first differing region can then be exchanged, to see which cells differ.
+//!
+//! Only `Quad` states are checked : there is no multi quad world yet.
+use crate::cell;
+use crate::codec::{Decode, DecodeError, Encode};
+use crate::hash::StableHasher;
+use crate::quad::{hash_cell, Quad};
+use crate::tribe::Tribe;
+use grid::Grid;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+
+/// Side of the square regions checksummed separately.
+pub const REGION_SIZE: usize = 32;
+
+/// How many of our past checkpoints are kept, for the peers lagging behind.
+pub const KEPT_CHECKPOINTS: usize = 8;
+
+/// A rectangle of cells, x being the column and y the row.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    /// The regions covering a board, row major.
+    pub fn tiling(width: usize, height: usize) -> Vec<Region> {
+        let mut regions = vec![];
+        for y in (0..height).step_by(REGION_SIZE) {
+            for x in (0..width).step_by(REGION_SIZE) {
+                regions.push(Region {
+                    x: x as u32,
+                    y: y as u32,
+                    width: REGION_SIZE.min(width - x) as u32,
+                    height: REGION_SIZE.min(height - y) as u32,
+                });
+            }
+        }
+        regions
+    }
+
+    /// The part of the region inside a board : regions may come from peers.
+    pub fn clamped(&self, width: usize, height: usize) -> Region {
+        let clip = |start: u32, size: u32, side: usize| {
+            let side = side.min(u32::MAX as usize) as u32;
+            let start = start.min(side);
+            let end = start.checked_add(size).map_or(side, |end| end.min(side));
+            (start, end - start)
+        };
+        let (x, width) = clip(self.x, self.width, width);
+        let (y, height) = clip(self.y, self.height, height);
+        Region {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// Cells of the region, row major.
+    fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (self.y..self.y.saturating_add(self.height)).flat_map(move |row| {
+            (self.x..self.x.saturating_add(self.width)).map(move |col| (col as usize, row as usize))
+        })
+    }
+}
+
+impl Display for Region {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} at ({}, {})",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+impl Encode for Region {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.x.encode(out);
+        self.y.encode(out);
+        self.width.encode(out);
+        self.height.encode(out);
+    }
+}
+
+impl Decode for Region {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Region {
+            x: u32::decode(input)?,
+            y: u32::decode(input)?,
+            width: u32::decode(input)?,
+            height: u32::decode(input)?,
+        })
+    }
+}
+
+/// Checksums of a state, as a whole and by region.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub generation: u64,
+    pub checksum: u64,
+    /// One per region of `Region::tiling`.
+    pub regions: Vec<u64>,
+}
+
+impl Checkpoint {
+    pub fn of(quad: &Quad) -> Self {
+        let regions = Region::tiling(quad.width(), quad.height())
+            .iter()
+            .map(|region| {
+                let mut hasher = StableHasher::default();
+                for (col, row) in region.cells() {
+                    hash_cell(
+                        &mut hasher,
+                        quad.cells()[(row, col)],
+                        quad.owners()[(row, col)],
+                    );
+                }
+                hasher.finish()
+            })
+            .collect();
+        Checkpoint {
+            generation: quad.generation(),
+            checksum: quad.checksum(),
+            regions,
+        }
+    }
+}
+
+impl Encode for Checkpoint {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.generation.encode(out);
+        self.checksum.encode(out);
+        self.regions.encode(out);
+    }
+}
+
+impl Decode for Checkpoint {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Checkpoint {
+            generation: u64::decode(input)?,
+            checksum: u64::decode(input)?,
+            regions: Vec::decode(input)?,
+        })
+    }
+}
+
+/// The cells of a region at a checkpoint, to compare with another peer's.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionState {
+    pub generation: u64,
+    pub region: Region,
+    /// Row major.
+    pub cells: Vec<(cell::State, Option<Tribe>)>,
+}
+
+impl Encode for RegionState {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.generation.encode(out);
+        self.region.encode(out);
+        self.cells.encode(out);
+    }
+}
+
+impl Decode for RegionState {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let generation = u64::decode(input)?;
+        let region = Region::decode(input)?;
+        let cells = Vec::decode(input)?;
+        Ok(RegionState {
+            generation,
+            region,
+            cells,
+        })
+    }
+}
+
+/// A cell differing between two peers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct CellDiff {
+    pub x: u32,
+    pub y: u32,
+    pub ours: (cell::State, Option<Tribe>),
+    pub theirs: (cell::State, Option<Tribe>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DesyncEvent {
+    /// A peer's checkpoint differs from ours. `region` is the first differing one, if the boards have the same size.
+    Detected {
+        peer: u8,
+        generation: u64,
+        ours: u64,
+        theirs: u64,
+        region: Option<Region>,
+    },
+    /// Cells of a region differing between a peer and us.
+    Diff {
+        peer: u8,
+        generation: u64,
+        region: Region,
+        cells: Vec<CellDiff>,
+    },
+}
+
+impl Display for DesyncEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DesyncEvent::Detected {
+                peer,
+                generation,
+                ours,
+                theirs,
+                region,
+            } => {
+                write!(
+                    f,
+                    "desync with peer {} at generation {}: checksum {:#018x} instead of {:#018x}",
+                    peer, generation, theirs, ours
+                )?;
+                match region {
+                    Some(region) => write!(f, ", first in region {}", region),
+                    None => write!(f, ", boards differ in size"),
+                }
+            }
+            DesyncEvent::Diff {
+                peer,
+                generation,
+                region,
+                cells,
+            } => {
+                write!(
+                    f,
+                    "{} cells differ with peer {} at generation {} in region {}",
+                    cells.len(),
+                    peer,
+                    generation,
+                    region
+                )?;
+                for c in cells.iter().take(8) {
+                    write!(
+                        f,
+                        "\n  ({}, {}): {:?} instead of {:?}",
+                        c.x, c.y, c.theirs, c.ours
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Our state at a checkpoint.
+struct Snapshot {
+    checkpoint: Checkpoint,
+    cells: Grid<cell::State>,
+    owners: Grid<Option<Tribe>>,
+}
+
+/// Compares the checkpoints of peers with ours.
+/// Each desync is reported once per peer : after it, states only drift further apart.
+pub struct DesyncDetector {
+    interval: u64,
+    ours: BTreeMap<u64, Snapshot>,
+    /// Checkpoints received before we reached their generation, `KEPT_CHECKPOINTS` per peer at most.
+    theirs: BTreeMap<(u64, u8), Checkpoint>,
+    desynced: BTreeMap<u8, u64>,
+    events: Vec<DesyncEvent>,
+}
+
+impl DesyncDetector {
+    /// An interval of 0 is the same as 1 : every generation.
+    pub fn new(interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            ours: BTreeMap::new(),
+            theirs: BTreeMap::new(),
+            desynced: BTreeMap::new(),
+            events: vec![],
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Whether a peer was found out of sync.
+    pub fn is_desynced(&self, peer: u8) -> bool {
+        self.desynced.contains_key(&peer)
+    }
+
+    /// To be called with each generation reached : returns our checkpoint to send to the peers, on checkpoint generations.
+    pub fn observe(&mut self, quad: &Quad) -> Option<Checkpoint> {
+        let generation = quad.generation();
+        if generation == 0
+            || !generation.is_multiple_of(self.interval)
+            || self.ours.contains_key(&generation)
+        {
+            return None;
+        }
+        let checkpoint = Checkpoint::of(quad);
+        self.ours.insert(
+            generation,
+            Snapshot {
+                checkpoint: checkpoint.clone(),
+                cells: quad.cells().clone(),
+                owners: quad.owners().clone(),
+            },
+        );
+        while self.ours.len() > KEPT_CHECKPOINTS {
+            self.ours.pop_first();
+        }
+
+        let received: Vec<(u64, u8)> = self
+            .theirs
+            .range((generation, 0)..=(generation, u8::MAX))
+            .map(|(k, _)| *k)
+            .collect();
+        for key in received {
+            let theirs = self.theirs.remove(&key).unwrap();
+            self.compare(key.1, &theirs);
+        }
+        // generations we went past without observing them : never compared
+        self.theirs.retain(|(g, _), _| *g > generation);
+        Some(checkpoint)
+    }
+
+    /// A peer's checkpoint : compared with ours now, or once we reach its generation.
+    pub fn receive(&mut self, peer: u8, checkpoint: Checkpoint) {
+        let generation = checkpoint.generation;
+        if self.ours.contains_key(&generation) {
+            self.compare(peer, &checkpoint);
+        } else if self
+            .ours
+            .last_key_value()
+            .is_none_or(|(g, _)| *g < generation)
+        {
+            self.theirs.insert((generation, peer), checkpoint);
+            // a peer far ahead : its first checkpoints are the ones we reach first
+            let pending: Vec<(u64, u8)> = self
+                .theirs
+                .keys()
+                .filter(|(_, p)| *p == peer)
+                .copied()
+                .collect();
+            if pending.len() > KEPT_CHECKPOINTS {
+                self.theirs.remove(pending.last().unwrap());
+            }
+        }
+        // else too old to be compared
+    }
+
+    fn compare(&mut self, peer: u8, theirs: &Checkpoint) {
+        let ours = &self.ours[&theirs.generation].checkpoint;
+        if ours.checksum == theirs.checksum || self.desynced.contains_key(&peer) {
+            return;
+        }
+        let region = if ours.regions.len() == theirs.regions.len() {
+            let snapshot = &self.ours[&theirs.generation];
+            ours.regions
+                .iter()
+                .zip(theirs.regions.iter())
+                .position(|(a, b)| a != b)
+                .map(|i| Region::tiling(snapshot.cells.cols(), snapshot.cells.rows())[i])
+        } else {
+            None
+        };
+        self.desynced.insert(peer, theirs.generation);
+        self.events.push(DesyncEvent::Detected {
+            peer,
+            generation: theirs.generation,
+            ours: ours.checksum,
+            theirs: theirs.checksum,
+            region,
+        });
+    }
+
+    /// Our cells of a region at a checkpoint, if we still have it. The region is clamped to the board.
+    pub fn region_state(&self, generation: u64, region: Region) -> Option<RegionState> {
+        let snapshot = self.ours.get(&generation)?;
+        let region = region.clamped(snapshot.cells.cols(), snapshot.cells.rows());
+        let cells = region
+            .cells()
+            .map(|(col, row)| {
+                Some((
+                    *snapshot.cells.get(row, col)?,
+                    *snapshot.owners.get(row, col)?,
+                ))
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(RegionState {
+            generation,
+            region,
+            cells,
+        })
+    }
+
+    /// Compares a peer's region with ours, reporting the differing cells as a `Diff` event.
+    pub fn diff(&mut self, peer: u8, theirs: &RegionState) {
+        let Some(ours) = self.region_state(theirs.generation, theirs.region) else {
+            return;
+        };
+        if ours.region != theirs.region || ours.cells.len() != theirs.cells.len() {
+            return;
+        }
+        let cells = theirs
+            .region
+            .cells()
+            .zip(ours.cells.iter().zip(theirs.cells.iter()))
+            .filter(|(_, (a, b))| a != b)
+            .map(|((x, y), (a, b))| CellDiff {
+                x: x as u32,
+                y: y as u32,
+                ours: *a,
+                theirs: *b,
+            })
+            .collect();
+        self.events.push(DesyncEvent::Diff {
+            peer,
+            generation: theirs.generation,
+            region: theirs.region,
+            cells,
+        });
+    }
+
+    /// Events since the last call.
+    pub fn take_events(&mut self) -> Vec<DesyncEvent> {
+        std::mem::take(&mut self.events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::codec::{Decode, Encode};
+    use crate::desync::{
+        Checkpoint, DesyncDetector, DesyncEvent, Region, RegionState, KEPT_CHECKPOINTS,
+    };
+    use crate::quad::Quad;
+    use crate::tribe::Tribe;
+    use figment::compute;
+
+    #[test]
+    fn check_tiling() {
+        let regions = Region::tiling(70, 40);
+        assert_eq!(regions.len(), 6);
+        assert_eq!(
+            regions[5],
+            Region {
+                x: 64,
+                y: 32,
+                width: 6,
+                height: 8
+            }
+        );
+        let cells: u32 = regions.iter().map(|r| r.width * r.height).sum();
+        assert_eq!(cells, 70 * 40);
+    }
+
+    #[test]
+    fn check_checkpoint_is_stable() {
+        let q = Quad::gen(State::Dead, 70, 40).with_seed(3);
+        let checkpoint = Checkpoint::of(&q);
+        assert_eq!(
+            checkpoint,
+            Checkpoint::of(&Quad::gen(State::Dead, 70, 40).with_seed(3))
+        );
+        assert_eq!(
+            Checkpoint::from_bytes(&checkpoint.to_bytes()),
+            Ok(checkpoint)
+        );
+    }
+
+    fn step(q: &mut Quad) {
+        compute::compute(q);
+    }
+
+    #[test]
+    fn check_detects_first_differing_region() {
+        let mut a = Quad::gen(State::Dead, 70, 40).with_seed(3);
+        let mut b = Quad::gen(State::Dead, 70, 40).with_seed(3);
+        let mut da = DesyncDetector::new(2);
+        let mut db = DesyncDetector::new(2);
+
+        for g in 1..=6 {
+            if g == 3 {
+                b.place([(40, 35), (50, 10)], Tribe(4));
+            }
+            step(&mut a);
+            step(&mut b);
+            // a is ahead : b's checkpoints are compared when a gets them
+            if let Some(c) = da.observe(&a) {
+                db.receive(0, c);
+            }
+            if let Some(c) = db.observe(&b) {
+                da.receive(1, c);
+            }
+        }
+
+        let events = da.take_events();
+        assert_eq!(events.len(), 1);
+        let DesyncEvent::Detected {
+            peer,
+            generation,
+            region: Some(region),
+            ..
+        } = events[0]
+        else {
+            panic!("no desync region in {:?}", events);
+        };
+        assert_eq!((peer, generation), (1, 4));
+        // (50, 10) is in the second region of the first row
+        assert_eq!((region.x, region.y), (32, 0));
+        assert_eq!(db.take_events().len(), 1);
+
+        // b sends its region, a finds the cells
+        let theirs = db.region_state(generation, region).unwrap();
+        let theirs = RegionState::from_bytes(&theirs.to_bytes()).unwrap();
+        da.diff(1, &theirs);
+        let DesyncEvent::Diff { cells, .. } = &da.take_events()[0] else {
+            panic!("no diff");
+        };
+        assert!(!cells.is_empty());
+        assert!(cells.iter().all(|c| c.x >= 32 && c.x < 64 && c.y < 32));
+        assert!(cells.iter().any(|c| c.theirs.1 == Some(Tribe(4))));
+    }
+
+    #[test]
+    fn check_untrusted_regions_and_checkpoints() {
+        let mut a = Quad::gen(State::Dead, 40, 20).with_seed(9);
+        let mut d = DesyncDetector::new(1);
+        step(&mut a);
+        let ours = d.observe(&a).unwrap();
+
+        let region = |x, y, width, height| Region {
+            x,
+            y,
+            width,
+            height,
+        };
+        let state = d
+            .region_state(1, region(u32::MAX, 10, u32::MAX, u32::MAX))
+            .unwrap();
+        assert_eq!(state.region, region(40, 10, 0, 10));
+        assert!(state.cells.is_empty());
+        let state = d.region_state(1, region(30, 15, u32::MAX, 3)).unwrap();
+        assert_eq!(state.region, region(30, 15, 10, 3));
+        assert_eq!(state.cells.len(), 30);
+        // a peer's region out of the board is not diffed
+        d.diff(
+            1,
+            &RegionState {
+                region: region(30, 15, u32::MAX, 3),
+                ..state
+            },
+        );
+        assert!(d.take_events().is_empty());
+
+        // checkpoints of a peer far ahead
+        for generation in 2..1000 {
+            d.receive(
+                1,
+                Checkpoint {
+                    generation,
+                    ..ours.clone()
+                },
+            );
+        }
+        assert_eq!(d.theirs.len(), KEPT_CHECKPOINTS);
+        // and the ones we skipped
+        for _ in 0..5 {
+            step(&mut a);
+        }
+        d.observe(&a);
+        assert_eq!(d.theirs.len(), KEPT_CHECKPOINTS - 5);
+    }
+
+    #[test]
+    fn check_in_sync() {
+        let mut a = Quad::gen(State::Dead, 20, 20).with_seed(9);
+        let mut d = DesyncDetector::new(3);
+        let mut checkpoints = vec![];
+        for _ in 0..9 {
+            step(&mut a);
+            checkpoints.extend(d.observe(&a));
+        }
+        assert_eq!(checkpoints.len(), 3);
+        for c in checkpoints {
+            d.receive(1, c);
+        }
+        assert!(d.take_events().is_empty());
+        assert!(!d.is_desynced(1));
+    }
+}
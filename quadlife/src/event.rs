@@ -0,0 +1,597 @@
+use crate::cell;
+use crate::codec::{take, Decode, DecodeError, Encode};
+use crate::placement::{Placements, Policy, Rejection};
+use crate::quad::{self, Quad, QuadUpdate};
+use crate::rule::{Rule, Topology};
+use crate::save;
+use crate::tribe::Tribe;
+use figment::compute::Computable;
+use std::fmt::{Display, Formatter};
+use std::iter::Peekable;
+use std::time::Duration;
+
+/// Something a player does to the game.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Live cells of a tribe, subject to the placement policy.
+    Place {
+        tribe: Tribe,
+        cells: Vec<(i64, i64)>,
+    },
+    Erase {
+        cells: Vec<(i64, i64)>,
+    },
+    SetRule(Rule),
+    SetTopology(Topology),
+}
+
+/// An action, applied once `generation` is reached, before computing the next one.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub generation: u64,
+    pub action: Action,
+}
+
+impl Encode for Action {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            Action::Place { tribe, cells } => {
+                0u8.encode(out);
+                tribe.encode(out);
+                cells.encode(out);
+            }
+            Action::Erase { cells } => {
+                1u8.encode(out);
+                cells.encode(out);
+            }
+            Action::SetRule(rule) => {
+                2u8.encode(out);
+                rule.encode(out);
+            }
+            Action::SetTopology(topology) => {
+                3u8.encode(out);
+                topology.encode(out);
+            }
+        }
+    }
+}
+
+impl Decode for Action {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match u8::decode(input)? {
+            0 => Ok(Action::Place {
+                tribe: Tribe::decode(input)?,
+                cells: Vec::decode(input)?,
+            }),
+            1 => Ok(Action::Erase {
+                cells: Vec::decode(input)?,
+            }),
+            2 => Ok(Action::SetRule(Rule::decode(input)?)),
+            3 => Ok(Action::SetTopology(Topology::decode(input)?)),
+            tag => Err(DecodeError::InvalidTag("Action", tag)),
+        }
+    }
+}
+
+impl Encode for Event {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.generation.encode(out);
+        self.action.encode(out);
+    }
+}
+
+impl Decode for Event {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Event {
+            generation: u64::decode(input)?,
+            action: Action::decode(input)?,
+        })
+    }
+}
+
+const LOG_MAGIC: &[u8; 4] = b"QLOG";
+const LOG_VERSION: u8 = 1;
+
+/// Everything that happened in a game : with the initial quad, enough to replay it exactly.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Log {
+    /// In the order they were applied.
+    pub events: Vec<Event>,
+    /// (generation, `Quad::checksum`) when each generation was reached, before applying its events.
+    pub checksums: Vec<(u64, u64)>,
+}
+
+impl Encode for Log {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(LOG_MAGIC);
+        LOG_VERSION.encode(out);
+        self.events.encode(out);
+        self.checksums.encode(out);
+    }
+}
+
+impl Decode for Log {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        if take(input, LOG_MAGIC.len())? != LOG_MAGIC {
+            return Err(DecodeError::Invalid("not an event log".to_string()));
+        }
+        let version = u8::decode(input)?;
+        if version != LOG_VERSION {
+            return Err(DecodeError::Invalid(format!(
+                "unsupported event log version {}",
+                version
+            )));
+        }
+        Ok(Log {
+            events: Vec::decode(input)?,
+            checksums: Vec::decode(input)?,
+        })
+    }
+}
+
+/// The replayed state differs from the recorded one.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Desync {
+    pub generation: u64,
+    pub expected: u64,
+    pub actual: u64,
+}
+
+impl Display for Desync {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "desync at generation {}: checksum {:#018x} instead of {:#018x}",
+            self.generation, self.actual, self.expected
+        )
+    }
+}
+
+impl std::error::Error for Desync {}
+
+/// A generation is being computed : finish it with its own stepper first.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InFlight {
+    pub generation: u64,
+}
+
+impl Display for InFlight {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "generation {} is being computed", self.generation)
+    }
+}
+
+impl std::error::Error for InFlight {}
+
+/// A game, as it was at a generation boundary : see `Game::rollback`.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    quad: quad::Snapshot,
+    placements: Placements,
+    pending: Vec<Event>,
+    /// The log only grows : its lengths are enough to rewind it.
+    events: usize,
+    checksums: usize,
+}
+
+impl Snapshot {
+    pub fn generation(&self) -> u64 {
+        self.quad.generation()
+    }
+}
+
+/// A quad, with player actions applied at generation boundaries only, and logged.
+/// The quad is computed from outside (see `quad_mut`), `sync` must be called after each compute.
+pub struct Game {
+    quad: Quad,
+    placements: Placements,
+    /// Submitted events, not applied yet, in submission order.
+    pending: Vec<Event>,
+    log: Log,
+}
+
+impl Game {
+    pub fn new(quad: Quad, policy: Policy) -> Self {
+        let mut game = Self {
+            quad,
+            placements: Placements::new(policy),
+            pending: vec![],
+            log: Log::default(),
+        };
+        game.sync();
+        game
+    }
+
+    pub fn quad(&self) -> &Quad {
+        &self.quad
+    }
+
+    /// To compute the quad. Edits made directly on it are not logged, and will break replays.
+    pub fn quad_mut(&mut self) -> &mut Quad {
+        &mut self.quad
+    }
+
+    pub fn placements(&self) -> &Placements {
+        &self.placements
+    }
+
+    /// Events submitted or scheduled, not applied yet.
+    pub fn pending(&self) -> &[Event] {
+        &self.pending
+    }
+
+    pub fn log(&self) -> &Log {
+        &self.log
+    }
+
+    /// Generation an action submitted now is applied at : the current one, or the one being computed.
+    pub fn next_boundary(&self) -> u64 {
+        self.quad.generation() + self.quad.is_computing() as u64
+    }
+
+    /// Schedules an action for the next generation boundary, applying it right away if there is one now.
+    /// Returns the rejected placements, if any were applied.
+    pub fn submit(&mut self, action: Action) -> Vec<Rejection> {
+        let generation = self.next_boundary();
+        self.schedule(Event { generation, action });
+        self.sync()
+    }
+
+    /// Schedules an event, ie. received from another peer. Late events are applied at the next boundary.
+    pub fn schedule(&mut self, event: Event) {
+        self.pending.push(event);
+    }
+
+    /// Records the checksum of a newly reached generation, and applies events due, unless a generation is in flight.
+    /// Returns the placements rejected by the policy.
+    pub fn sync(&mut self) -> Vec<Rejection> {
+        let generation = self.quad.generation();
+        if self.log.checksums.last().map(|(g, _)| *g) != Some(generation) {
+            self.log.checksums.push((generation, self.quad.checksum()));
+        }
+        if self.quad.is_computing() {
+            return vec![];
+        }
+
+        let (due, later): (Vec<Event>, Vec<Event>) = std::mem::take(&mut self.pending)
+            .into_iter()
+            .partition(|e| e.generation <= generation);
+        self.pending = later;
+
+        let mut rejections = vec![];
+        for event in due {
+            if let Err(rejection) = self.apply(&event.action) {
+                rejections.push(rejection);
+            }
+            // logged with the generation actually applied at, rejected or not
+            self.log.events.push(Event {
+                generation,
+                ..event
+            });
+        }
+        rejections
+    }
+
+    fn apply(&mut self, action: &Action) -> Result<(), Rejection> {
+        match action {
+            Action::Place { tribe, cells } => self
+                .placements
+                .place(&mut self.quad, *tribe, &cells.iter().copied().collect())
+                .map(|_| ()),
+            Action::Erase { cells } => {
+                self.quad.paint(cells.iter().copied(), cell::State::Dead);
+                Ok(())
+            }
+            Action::SetRule(rule) => {
+                self.quad.set_rule(*rule);
+                Ok(())
+            }
+            Action::SetTopology(topology) => {
+                self.quad.set_topology(*topology);
+                Ok(())
+            }
+        }
+    }
+
+    /// Computes one complete generation.
+    /// Refused if a generation is in flight : events may be due on its partial state, the game is left as it is.
+    pub fn step(&mut self) -> Result<(), InFlight> {
+        if self.quad.is_computing() {
+            return Err(InFlight {
+                generation: self.next_boundary(),
+            });
+        }
+        let mut stepper = self.quad.compute_reset();
+        self.quad.compute(Duration::ZERO, &mut stepper);
+        self.sync();
+        Ok(())
+    }
+
+    /// Computes whole generations, applying the events due on the way, with the fast path of `Quad::resimulate`.
+    /// # Panics
+    /// If a generation is in flight.
+    pub fn resimulate(&mut self, generations: u64) {
+        for _ in 0..generations {
+            self.quad.resimulate(1);
+            self.sync();
+        }
+    }
+
+    /// Saves the quad, the placement budgets, the pending events and the log position.
+    /// # Panics
+    /// If a generation is in flight.
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            quad: self.quad.snapshot(),
+            placements: self.placements.clone(),
+            pending: self.pending.clone(),
+            events: self.log.events.len(),
+            checksums: self.log.checksums.len(),
+        }
+    }
+
+    /// Rewinds the game to a snapshot of it : whatever happened since is forgotten, log included.
+    pub fn rollback(&mut self, snapshot: &Snapshot) {
+        self.quad.rollback(&snapshot.quad);
+        self.placements = snapshot.placements.clone();
+        self.pending = snapshot.pending.clone();
+        self.log.events.truncate(snapshot.events);
+        self.log.checksums.truncate(snapshot.checksums);
+    }
+
+    /// A save of the whole game (see `save`) : quad, placement budgets, pending events and log.
+    /// # Panics
+    /// If a generation is in flight, and its stepper is not given.
+    pub fn save(&self, stepper: Option<&Peekable<QuadUpdate>>) -> Vec<u8> {
+        let mut writer = save::Writer::default();
+        writer.quad(&self.quad, stepper);
+        writer.section(save::PLACEMENTS, &self.placements);
+        writer.section(save::PENDING, &self.pending);
+        writer.section(save::LOG, &self.log);
+        writer.finish()
+    }
+
+    /// Loads a game saved with `save`.
+    /// If it was saved mid-generation, the stepper to finish that generation with is returned too.
+    pub fn load(bytes: &[u8]) -> Result<(Game, Option<Peekable<QuadUpdate>>), DecodeError> {
+        let sections = save::Sections::read(bytes)?;
+        let (quad, stepper) = sections.quad()?;
+        let game = Game {
+            quad,
+            placements: sections.require(save::PLACEMENTS, "placements")?,
+            pending: sections.get(save::PENDING)?.unwrap_or_default(),
+            log: sections.get(save::LOG)?.unwrap_or_default(),
+        };
+        Ok((game, stepper))
+    }
+
+    /// Replays a log from the initial quad, checking every recorded checksum.
+    /// Returns the game as it was at the last recorded generation.
+    pub fn replay(initial: Quad, policy: Policy, log: &Log) -> Result<Game, Desync> {
+        let mut game = Game::new(initial, policy);
+        game.pending = log.events.clone();
+        game.log.events.clear();
+        game.log.checksums.clear();
+
+        for (generation, expected) in log.checksums.iter().copied() {
+            while game.quad.generation() < generation {
+                game.step();
+            }
+            game.sync();
+            // as recorded when the generation was reached, before its events were applied
+            let actual = game.log.checksums.last().map_or(0, |(_, c)| *c);
+            if game.quad.generation() != generation || actual != expected {
+                return Err(Desync {
+                    generation,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::codec::{Decode, Encode};
+    use crate::event::{Action, Event, Game, InFlight, Log};
+    use crate::placement::{Policy, Rejection};
+    use crate::quad::Quad;
+    use crate::rule::{Rule, Topology};
+    use crate::tribe::Tribe;
+    use figment::compute::Computable;
+
+    fn soup(seed: u64) -> Quad {
+        Quad::gen(State::Dead, 32, 24).with_seed(seed)
+    }
+
+    fn policy() -> Policy {
+        Policy {
+            cooldown: 1,
+            ..Policy::default()
+        }
+    }
+
+    /// A game with a few players acting every now and then.
+    fn play(seed: u64, generations: u64) -> Game {
+        let mut game = Game::new(soup(seed), policy());
+        for g in 0..generations {
+            match g % 7 {
+                0 => {
+                    game.submit(Action::Place {
+                        tribe: Tribe((g % 3) as u8),
+                        cells: vec![(g as i64 % 32, 3), (g as i64 % 32, 4), (g as i64 % 32, 5)],
+                    });
+                }
+                3 => {
+                    game.submit(Action::Erase {
+                        cells: vec![(5, (g % 24) as i64)],
+                    });
+                }
+                _ => {}
+            }
+            if g == 20 {
+                game.submit(Action::SetRule(Rule::new(&[3, 6], &[2, 3])));
+            }
+            if g == 30 {
+                game.submit(Action::SetTopology(Topology::Torus));
+            }
+            game.step().unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn check_seed_reproduces() {
+        assert_eq!(soup(3).cells(), soup(3).cells());
+        assert_ne!(soup(3).cells(), soup(4).cells());
+    }
+
+    #[test]
+    fn check_replay_is_exact() {
+        let played = play(11, 50);
+        assert_eq!(played.log().checksums.len(), 51);
+
+        let replayed = Game::replay(soup(11), policy(), played.log()).unwrap();
+
+        assert_eq!(replayed.log(), played.log());
+        assert_eq!(replayed.quad().cells(), played.quad().cells());
+        assert_eq!(replayed.quad().owners(), played.quad().owners());
+        assert_eq!(replayed.quad().rule(), played.quad().rule());
+    }
+
+    #[test]
+    fn check_replay_detects_desync() {
+        let played = play(11, 20);
+        let mut log = played.log().clone();
+        // the first placement, at generation 0
+        log.events.remove(0);
+
+        let desync = Game::replay(soup(11), policy(), &log).err().unwrap();
+        assert_eq!(desync.generation, 1);
+        assert_eq!(desync.expected, played.log().checksums[1].1);
+
+        // another seed diverges right away
+        assert_eq!(
+            Game::replay(soup(12), policy(), played.log())
+                .err()
+                .unwrap()
+                .generation,
+            0
+        );
+    }
+
+    #[test]
+    fn check_rollback_forgets_the_future() {
+        let mut game = play(11, 10);
+        let snapshot = game.snapshot();
+        let log = game.log().clone();
+        let checksum = game.quad().checksum();
+
+        game.submit(Action::Place {
+            tribe: Tribe(1),
+            cells: vec![(10, 10), (11, 10), (12, 10)],
+        });
+        game.schedule(Event {
+            generation: 14,
+            action: Action::Erase {
+                cells: vec![(0, 0)],
+            },
+        });
+        game.resimulate(5);
+        assert_eq!(game.quad().generation(), 15);
+
+        game.rollback(&snapshot);
+        assert_eq!(game.quad().generation(), 10);
+        assert_eq!(game.quad().checksum(), checksum);
+        assert_eq!(game.log(), &log);
+
+        // the same future, with the fast path
+        let mut stepped = play(11, 10);
+        for _ in 0..5 {
+            stepped.step().unwrap();
+        }
+        game.resimulate(5);
+        assert_eq!(game.log(), stepped.log());
+    }
+
+    #[test]
+    fn check_log_encoding() {
+        let log = play(5, 15).log().clone();
+
+        let decoded = Log::from_bytes(&log.to_bytes()).unwrap();
+
+        assert_eq!(decoded, log);
+        assert!(Log::from_bytes(b"QLOX\x01").is_err());
+        assert!(Log::from_bytes(&log.to_bytes()[..20]).is_err());
+    }
+
+    #[test]
+    fn check_actions_wait_for_generation_boundary() {
+        let mut game = Game::new(Quad::gen(State::Dead, 8, 8), Policy::UNLIMITED);
+        let mut stepper = game.quad().stepper().peekable();
+        assert_eq!(game.next_boundary(), 1);
+
+        let place = Action::Place {
+            tribe: Tribe(0),
+            cells: vec![(1, 1)],
+        };
+        assert_eq!(game.submit(place.clone()), vec![]);
+        assert_eq!(game.quad().population(), 0);
+
+        game.quad_mut()
+            .compute(std::time::Duration::ZERO, &mut stepper);
+        game.sync();
+
+        assert_eq!(game.quad().population(), 1);
+        assert_eq!(
+            game.log().events,
+            vec![Event {
+                generation: 1,
+                action: place
+            }]
+        );
+    }
+
+    #[test]
+    fn check_step_refuses_a_generation_in_flight() {
+        let mut game = Game::new(soup(1), Policy::UNLIMITED);
+        let mut stepper = game.quad().compute_reset();
+        game.quad_mut()
+            .compute_until(std::time::Duration::ZERO, &mut stepper, || true);
+        let checksum = game.quad().checksum();
+
+        assert_eq!(game.step(), Err(InFlight { generation: 1 }));
+        assert_eq!(game.quad().checksum(), checksum);
+
+        game.quad_mut()
+            .compute(std::time::Duration::ZERO, &mut stepper);
+        game.sync();
+        assert_eq!(game.step(), Ok(()));
+        assert_eq!(game.quad().generation(), 2);
+    }
+
+    #[test]
+    fn check_rejections_are_reported_and_logged() {
+        let mut game = Game::new(
+            Quad::gen(State::Dead, 8, 8),
+            Policy {
+                cooldown: 10,
+                ..Policy::default()
+            },
+        );
+        let place = |x| Action::Place {
+            tribe: Tribe(1),
+            cells: vec![(x, 0)],
+        };
+        assert_eq!(game.submit(place(0)), vec![]);
+        assert_eq!(
+            game.submit(place(1)),
+            vec![Rejection::Cooldown { remaining: 10 }]
+        );
+        assert_eq!(game.quad().population(), 1);
+        assert_eq!(game.log().events.len(), 2);
+    }
+}
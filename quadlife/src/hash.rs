@@ -0,0 +1,52 @@
+/// 64 bits FNV-1a : a simple hash, fully specified, so it gives the same result on every platform and every run,
+/// unlike `std::collections::hash_map::DefaultHasher`. Not meant to resist an adversary.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct StableHasher(u64);
+
+const OFFSET: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(OFFSET)
+    }
+}
+
+impl StableHasher {
+    pub fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(PRIME);
+        }
+    }
+
+    pub fn write_u8(&mut self, v: u8) {
+        self.write(&[v]);
+    }
+
+    /// Integers are hashed in little endian, whatever the platform.
+    pub fn write_u64(&mut self, v: u64) {
+        self.write(&v.to_le_bytes());
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hash::StableHasher;
+
+    #[test]
+    fn check_known_values() {
+        // reference values of FNV-1a 64
+        assert_eq!(StableHasher::default().finish(), 0xcbf29ce484222325);
+        let mut h = StableHasher::default();
+        h.write(b"a");
+        assert_eq!(h.finish(), 0xaf63dc4c8601ec8c);
+        let mut h = StableHasher::default();
+        h.write(b"foobar");
+        assert_eq!(h.finish(), 0x85944171f73967e8);
+    }
+}
@@ -1,6 +1,18 @@
 #![feature(test)]
 extern crate test;
 
+pub mod brush;
 pub mod cell;
+pub mod chunk;
+pub mod codec;
+pub mod delta;
+pub mod desync;
+pub mod event;
+pub mod hash;
+pub mod placement;
 pub mod quad;
+pub mod rule;
+pub mod save;
+pub mod score;
+pub mod tribe;
 mod world;
@@ -0,0 +1,404 @@
+use crate::codec::{Decode, DecodeError, Encode};
+use crate::quad::Quad;
+use crate::tribe::Tribe;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+/// Limits on what a player may place on a quad, and when.
+/// Time is counted in generations, so that all peers agree on it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Policy {
+    /// Energy a player starts with, and cannot exceed.
+    pub max_energy: u32,
+    /// Energy regained at each generation.
+    pub regeneration: u32,
+    /// Energy spent per placed cell.
+    pub cost_per_cell: u32,
+    /// Generations to wait after a placement before the next one.
+    pub cooldown: u64,
+    /// If set, each placed cell must be within this (chessboard) distance of a cell owned by the player.
+    /// A player that owns nothing yet may place anywhere.
+    pub proximity: Option<u32>,
+}
+
+impl Policy {
+    /// No limit at all, like a sandbox.
+    pub const UNLIMITED: Policy = Policy {
+        max_energy: u32::MAX,
+        regeneration: u32::MAX,
+        cost_per_cell: 0,
+        cooldown: 0,
+        proximity: None,
+    };
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            max_energy: 256,
+            regeneration: 2,
+            cost_per_cell: 1,
+            cooldown: 4,
+            proximity: None,
+        }
+    }
+}
+
+/// Why a placement was refused.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+    /// No cell of the placement is inside the quad.
+    OutOfBounds,
+    NotEnoughEnergy {
+        needed: u32,
+        available: u32,
+    },
+    /// Generations left before the player may place again.
+    Cooldown {
+        remaining: u64,
+    },
+    /// This cell is too far from the player's territory.
+    TooFar {
+        x: i64,
+        y: i64,
+    },
+}
+
+impl Display for Rejection {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Rejection::OutOfBounds => write!(f, "out of bounds"),
+            Rejection::NotEnoughEnergy { needed, available } => {
+                write!(f, "not enough energy ({} / {})", available, needed)
+            }
+            Rejection::Cooldown { remaining } => {
+                write!(f, "cooling down ({} generations left)", remaining)
+            }
+            Rejection::TooFar { x, y } => write!(f, "({}, {}) is too far from territory", x, y),
+        }
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Accepted {
+    /// Cells set in the quad.
+    pub cells: usize,
+    /// Energy spent.
+    pub cost: u32,
+    /// Energy left.
+    pub energy: u32,
+}
+
+pub type Placement = Result<Accepted, Rejection>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Budget {
+    energy: u32,
+    /// Generation `energy` was measured at.
+    at: u64,
+    last_placement: Option<u64>,
+}
+
+/// Energy budgets and cooldowns of all players, enforcing a `Policy` on their placements.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Placements {
+    policy: Policy,
+    budgets: BTreeMap<Tribe, Budget>,
+}
+
+impl Placements {
+    pub fn new(policy: Policy) -> Self {
+        Self {
+            policy,
+            budgets: BTreeMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    fn budget(&self, tribe: Tribe, generation: u64) -> Budget {
+        match self.budgets.get(&tribe) {
+            // newcomers start with a full budget
+            None => Budget {
+                energy: self.policy.max_energy,
+                at: generation,
+                last_placement: None,
+            },
+            Some(b) => {
+                let elapsed = u32::try_from(generation.saturating_sub(b.at)).unwrap_or(u32::MAX);
+                Budget {
+                    energy: b
+                        .energy
+                        .saturating_add(elapsed.saturating_mul(self.policy.regeneration))
+                        .min(self.policy.max_energy),
+                    at: generation.max(b.at),
+                    ..*b
+                }
+            }
+        }
+    }
+
+    /// Energy of a player, at the given generation.
+    pub fn energy(&self, tribe: Tribe, generation: u64) -> u32 {
+        self.budget(tribe, generation).energy
+    }
+
+    /// Checks whether `tribe` may place these cells in the quad now, without placing them.
+    /// Cells out of bounds are ignored, and not paid for.
+    pub fn check(&self, quad: &Quad, tribe: Tribe, cells: &BTreeSet<(i64, i64)>) -> Placement {
+        let generation = quad.generation();
+        let budget = self.budget(tribe, generation);
+
+        if let Some(last) = budget.last_placement {
+            let ready = last + self.policy.cooldown;
+            if generation < ready {
+                return Err(Rejection::Cooldown {
+                    remaining: ready - generation,
+                });
+            }
+        }
+
+        let inside: Vec<(i64, i64)> = cells
+            .iter()
+            .copied()
+            .filter(|(x, y)| {
+                (0..quad.width() as i64).contains(x) && (0..quad.height() as i64).contains(y)
+            })
+            .collect();
+        if inside.is_empty() {
+            return Err(Rejection::OutOfBounds);
+        }
+
+        let cost = (inside.len() as u32).saturating_mul(self.policy.cost_per_cell);
+        if cost > budget.energy {
+            return Err(Rejection::NotEnoughEnergy {
+                needed: cost,
+                available: budget.energy,
+            });
+        }
+
+        if let Some(radius) = self.policy.proximity {
+            let owns_any = quad.owners().iter().any(|o| *o == Some(tribe));
+            if owns_any {
+                if let Some((x, y)) = inside
+                    .iter()
+                    .find(|(x, y)| !owned_within(quad, tribe, *x, *y, radius))
+                {
+                    return Err(Rejection::TooFar { x: *x, y: *y });
+                }
+            }
+        }
+
+        Ok(Accepted {
+            cells: inside.len(),
+            cost,
+            energy: budget.energy - cost,
+        })
+    }
+
+    /// Places live cells of `tribe` in the quad, if the policy allows it.
+    /// A rejected placement changes nothing.
+    pub fn place(
+        &mut self,
+        quad: &mut Quad,
+        tribe: Tribe,
+        cells: &BTreeSet<(i64, i64)>,
+    ) -> Placement {
+        let accepted = self.check(quad, tribe, cells)?;
+
+        quad.place(cells.iter().copied(), tribe);
+        self.budgets.insert(
+            tribe,
+            Budget {
+                energy: accepted.energy,
+                at: quad.generation(),
+                last_placement: Some(quad.generation()),
+            },
+        );
+        Ok(accepted)
+    }
+}
+
+impl Encode for Policy {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.max_energy.encode(out);
+        self.regeneration.encode(out);
+        self.cost_per_cell.encode(out);
+        self.cooldown.encode(out);
+        self.proximity.encode(out);
+    }
+}
+
+impl Decode for Policy {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Policy {
+            max_energy: u32::decode(input)?,
+            regeneration: u32::decode(input)?,
+            cost_per_cell: u32::decode(input)?,
+            cooldown: u64::decode(input)?,
+            proximity: Option::decode(input)?,
+        })
+    }
+}
+
+/// The policy, and the budgets of the players who placed cells.
+impl Encode for Placements {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.policy.encode(out);
+        (self.budgets.len() as u32).encode(out);
+        for (tribe, budget) in &self.budgets {
+            tribe.encode(out);
+            budget.energy.encode(out);
+            budget.at.encode(out);
+            budget.last_placement.encode(out);
+        }
+    }
+}
+
+impl Decode for Placements {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let policy = Policy::decode(input)?;
+        let mut budgets = BTreeMap::new();
+        for _ in 0..u32::decode(input)? {
+            budgets.insert(
+                Tribe::decode(input)?,
+                Budget {
+                    energy: u32::decode(input)?,
+                    at: u64::decode(input)?,
+                    last_placement: Option::decode(input)?,
+                },
+            );
+        }
+        Ok(Placements { policy, budgets })
+    }
+}
+
+/// Whether a cell owned by `tribe` lies within `radius` of (x, y).
+fn owned_within(quad: &Quad, tribe: Tribe, x: i64, y: i64, radius: u32) -> bool {
+    let r = radius as i64;
+    (y - r..=y + r).any(|j| (x - r..=x + r).any(|i| quad.owner(i, j) == Some(tribe)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::placement::{Accepted, Placements, Policy, Rejection};
+    use crate::quad::Quad;
+    use crate::tribe::Tribe;
+    use figment::compute::Computable;
+    use std::collections::BTreeSet;
+    use std::time::Duration;
+
+    fn cells(c: &[(i64, i64)]) -> BTreeSet<(i64, i64)> {
+        c.iter().copied().collect()
+    }
+
+    fn next_generation(q: &mut Quad) {
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+    }
+
+    const POLICY: Policy = Policy {
+        max_energy: 10,
+        regeneration: 1,
+        cost_per_cell: 2,
+        cooldown: 2,
+        proximity: None,
+    };
+
+    #[test]
+    fn check_placement_spends_energy() {
+        let mut q = Quad::gen(State::Dead, 8, 8);
+        let mut p = Placements::new(POLICY);
+
+        assert_eq!(
+            p.place(&mut q, Tribe(0), &cells(&[(0, 0), (1, 0), (9, 9)])),
+            Ok(Accepted {
+                cells: 2,
+                cost: 4,
+                energy: 6
+            })
+        );
+        assert_eq!(q.population(), 2);
+        assert_eq!(q.owner(1, 0), Some(Tribe(0)));
+        // other players have their own budget
+        assert_eq!(p.energy(Tribe(1), 0), 10);
+    }
+
+    #[test]
+    fn check_cooldown_and_regeneration() {
+        let mut q = Quad::gen(State::Dead, 8, 8);
+        let mut p = Placements::new(POLICY);
+        p.place(&mut q, Tribe(0), &cells(&[(0, 0), (1, 0), (2, 0), (3, 0)]))
+            .unwrap();
+
+        assert_eq!(
+            p.place(&mut q, Tribe(0), &cells(&[(5, 5)])),
+            Err(Rejection::Cooldown { remaining: 2 })
+        );
+        next_generation(&mut q);
+        next_generation(&mut q);
+
+        assert_eq!(p.energy(Tribe(0), q.generation()), 4);
+        assert_eq!(
+            p.place(&mut q, Tribe(0), &cells(&[(5, 5), (6, 5), (7, 5)])),
+            Err(Rejection::NotEnoughEnergy {
+                needed: 6,
+                available: 4
+            })
+        );
+        assert!(p.place(&mut q, Tribe(0), &cells(&[(5, 5), (6, 5)])).is_ok());
+        assert_eq!(p.energy(Tribe(0), 1000), 10);
+    }
+
+    #[test]
+    fn check_rejection_changes_nothing() {
+        let mut q = Quad::gen(State::Dead, 4, 4);
+        let mut p = Placements::new(POLICY);
+
+        assert_eq!(
+            p.place(&mut q, Tribe(0), &cells(&[(-1, 0), (4, 4)])),
+            Err(Rejection::OutOfBounds)
+        );
+        assert_eq!(q.population(), 0);
+        // no cooldown started
+        assert!(p.place(&mut q, Tribe(0), &cells(&[(0, 0)])).is_ok());
+    }
+
+    #[test]
+    fn check_proximity() {
+        let mut q = Quad::gen(State::Dead, 16, 16);
+        let mut p = Placements::new(Policy {
+            cooldown: 0,
+            proximity: Some(2),
+            ..POLICY
+        });
+
+        // first placement anywhere
+        assert!(p.place(&mut q, Tribe(0), &cells(&[(4, 4)])).is_ok());
+        assert!(p.place(&mut q, Tribe(1), &cells(&[(12, 12)])).is_ok());
+
+        assert_eq!(
+            p.place(&mut q, Tribe(0), &cells(&[(6, 6), (7, 7)])),
+            Err(Rejection::TooFar { x: 7, y: 7 })
+        );
+        assert_eq!(
+            p.place(&mut q, Tribe(0), &cells(&[(11, 11)])),
+            Err(Rejection::TooFar { x: 11, y: 11 })
+        );
+        assert!(p.place(&mut q, Tribe(0), &cells(&[(6, 6)])).is_ok());
+    }
+
+    #[test]
+    fn check_unlimited() {
+        let mut q = Quad::gen(State::Dead, 4, 4);
+        let mut p = Placements::new(Policy::UNLIMITED);
+        for _ in 0..10 {
+            assert!(p.place(&mut q, Tribe(0), &cells(&[(0, 0), (1, 1)])).is_ok());
+        }
+    }
+}
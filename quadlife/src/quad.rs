@@ -1,50 +1,127 @@
 use crate::cell;
+use crate::codec::DecodeError;
+use crate::hash::StableHasher;
+use crate::rule::{Rule, Topology};
+use crate::save;
+use crate::tribe::{self, Tribe};
 use figment::compute::Computable;
+use figment::graphics::bitmap;
+use figment::graphics::color::RGBA8;
+use figment::graphics::dirty::{DirtyRect, DirtyRects};
+use figment::graphics::image::RGBAImage;
 use figment::graphics::Viewable;
 use grid::Grid;
 use itertools::iproduct;
-use macroquad::color::Color;
-use macroquad::prelude::Image;
 use macroquad::rand::ChooseRandom;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
 use std::iter::Peekable;
-use std::ops::DerefMut;
+use std::path::Path;
 use std::time::Duration;
 
+/// A cell computed by a `QuadUpdate` : (x, y, new state and owner).
+pub type CellUpdate = (usize, usize, Option<(cell::State, Option<Tribe>)>);
+
+#[derive(Clone)]
 pub struct QuadUpdate {
     original: Grid<cell::State>,
+    owners: Grid<Option<Tribe>>,
     left_over: Vec<(usize, usize)>,
+    rule: Rule,
+    topology: Topology,
+    /// Salt for the tie breaks between tribes, so they differ from one generation to the next.
+    salt: u64,
+    /// Updates computed before a save, handed out before `left_over` : see `Quad::resume`.
+    computed: Vec<CellUpdate>,
 }
 
 impl QuadUpdate {
     pub fn new(cells: &Grid<cell::State>) -> Self {
+        let mut update = Self::in_order(cells);
+        update.left_over.shuffle();
+        update
+    }
+
+    /// Updates cells in (reverse) row order, not shuffled : for whole generations nobody sees in progress,
+    /// ie. resimulations.
+    pub fn in_order(cells: &Grid<cell::State>) -> Self {
         let original = cells.clone(); // because we need to own our copy for later compute
+        let owners = Grid::init(cells.rows(), cells.cols(), None);
 
-        //TODO : separate to not always require reshuffling... only if/when we have time...
-        let mut left_over: Vec<(usize, usize)> =
-            iproduct!(0..cells.rows(), 0..cells.cols()).collect();
-        left_over.shuffle();
+        // popped from the end : backwards, still cache friendly
+        let left_over: Vec<(usize, usize)> = iproduct!(0..cells.rows(), 0..cells.cols()).collect();
 
         Self {
             original,
+            owners,
             left_over,
+            rule: Rule::default(),
+            topology: Topology::default(),
+            salt: 0,
+            computed: vec![],
+        }
+    }
+
+    pub fn with_owners(self, owners: &Grid<Option<Tribe>>) -> Self {
+        Self {
+            owners: owners.clone(),
+            ..self
         }
     }
 
+    pub fn with_salt(self, salt: u64) -> Self {
+        Self { salt, ..self }
+    }
+
+    pub fn with_rule(self, rule: Rule) -> Self {
+        Self { rule, ..self }
+    }
+
+    pub fn with_topology(self, topology: Topology) -> Self {
+        Self { topology, ..self }
+    }
+
     fn completed(&self) -> bool {
-        self.left_over.is_empty()
+        self.left_over.is_empty() && self.computed.is_empty()
     }
 }
 
 //TODO : EXactSizedIterator
 impl Iterator for QuadUpdate {
-    type Item = (usize, usize, Option<cell::State>);
+    type Item = CellUpdate;
 
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(update) = self.computed.pop() {
+            return Some(update);
+        }
         match self.left_over.pop() {
             None => None,
             Some((y, x)) => {
-                let updated = cell::update(&self.original, x as i32, y as i32);
+                let updated = cell::update_with(
+                    &self.original,
+                    x as i32,
+                    y as i32,
+                    &self.rule,
+                    self.topology,
+                )
+                .map(|state| {
+                    // a birth takes its owner from its neighbours, otherwise the cell keeps its territory.
+                    let owner = if state == cell::State::Alive
+                        && self.original[(y, x)] == cell::State::Dead
+                    {
+                        tribe::inherit(
+                            &self.original,
+                            &self.owners,
+                            x as i32,
+                            y as i32,
+                            self.topology,
+                            self.salt,
+                        )
+                    } else {
+                        self.owners[(y, x)]
+                    };
+                    (state, owner)
+                });
                 // println!("{:?} => {:?}", self.original[y as usize *self.width as usize+ x as usize], updated);
                 Some((x, y, updated))
             } //CAREFUL : grid computation here must be exactly same as image...
@@ -52,30 +129,210 @@ impl Iterator for QuadUpdate {
     }
 }
 
-fn to_colors(state: &Grid<cell::State>) -> Vec<Color> {
-    state.iter().map(|p| cell::color(*p)).collect()
+/// Live cells are drawn in their tribe colour, if they have one.
+fn to_color(state: cell::State, owner: Option<Tribe>) -> RGBA8 {
+    match (state, owner) {
+        (cell::State::Alive, Some(tribe)) => tribe.color().into(),
+        _ => cell::color(state).into(),
+    }
+}
+
+fn to_colors(state: &Grid<cell::State>, owners: &Grid<Option<Tribe>>) -> Vec<RGBA8> {
+    state
+        .iter()
+        .zip(owners.iter())
+        .map(|(s, o)| to_color(*s, *o))
+        .collect()
+}
+
+/// How a cell contributes to checksums.
+pub(crate) fn hash_cell(hasher: &mut StableHasher, state: cell::State, owner: Option<Tribe>) {
+    hasher.write_u8(match state {
+        cell::State::Dead => 0,
+        cell::State::Alive => 1,
+    });
+    match owner {
+        None => hasher.write_u8(0),
+        Some(tribe) => hasher.write(&[1, tribe.0]),
+    }
+}
+
+/// Everything a quad computes from : restoring it rewinds the quad to the generation it was taken at.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    cells: Grid<cell::State>,
+    owners: Grid<Option<Tribe>>,
+    rule: Rule,
+    topology: Topology,
+    generation: u64,
+}
+
+impl Snapshot {
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 }
 
 pub struct Quad {
     progress: Grid<cell::State>,
-    image: RefCell<Image>,
+    /// Tribe owning each cell. Ownership outlives the cell : dead cells remain the territory of their last owner.
+    owners: Grid<Option<Tribe>>,
+    image: RefCell<RGBAImage>,
+    /// Cells changed since the image was last rendered.
+    dirty: RefCell<DirtyRects>,
+    /// Pixels rendered again since the changes were last taken (see `Viewable::take_changes`).
+    rendered: RefCell<DirtyRects>,
+    rule: Rule,
+    topology: Topology,
+    generation: u64,
+    /// Whether a stepper has been handed out, and its generation is not completed yet.
+    in_flight: Cell<bool>,
+    /// Cells edited while a generation is in flight : the stepper's (stale) results for these are dropped.
+    edited: RefCell<HashSet<(usize, usize)>>,
 }
 
 impl Quad {
+    /// # Panics
+    /// If the grid is larger than an image can be : u32::MAX cells wide or high.
     pub fn new(state_grid: Grid<cell::State>) -> Self {
         let update = QuadUpdate::new(&state_grid);
 
-        let mut img = Image::gen_image_color(
-            state_grid.cols() as u16,
-            state_grid.rows() as u16,
-            cell::color(cell::State::Dead),
-        );
-        img.update(to_colors(&state_grid).as_slice());
+        let owners = Grid::init(state_grid.rows(), state_grid.cols(), None);
+        let (Ok(width), Ok(height)) = (
+            u32::try_from(state_grid.cols()),
+            u32::try_from(state_grid.rows()),
+        ) else {
+            panic!(
+                "{}x{} grid does not fit in an image",
+                state_grid.cols(),
+                state_grid.rows()
+            );
+        };
+        let img = RGBAImage::from_pixels(width, height, to_colors(&state_grid, &owners));
+        // all new to whoever takes the changes
+        let rendered = DirtyRects::all(width, height);
 
         Self {
             progress: state_grid,
+            owners,
             image: RefCell::new(img),
+            dirty: RefCell::new(DirtyRects::default()),
+            rendered: RefCell::new(rendered),
+            rule: Rule::default(),
+            topology: Topology::default(),
+            generation: 0,
+            in_flight: Cell::new(false),
+            edited: RefCell::new(HashSet::new()),
+        }
+    }
+
+    pub fn with_rule(self, rule: Rule) -> Self {
+        Self { rule, ..self }
+    }
+
+    pub fn with_topology(self, topology: Topology) -> Self {
+        Self { topology, ..self }
+    }
+
+    /// Starts counting generations from `generation`, ie. for a quad loaded from a save.
+    pub fn with_generation(self, generation: u64) -> Self {
+        Self { generation, ..self }
+    }
+
+    /// # Panics
+    /// If the owners grid does not have the same size as the cells grid.
+    pub fn with_owners(self, owners: Grid<Option<Tribe>>) -> Self {
+        assert_eq!(
+            (owners.rows(), owners.cols()),
+            (self.height(), self.width())
+        );
+        Self { owners, ..self }.all_changed()
+    }
+
+    /// Everything is rendered again.
+    fn all_changed(mut self) -> Self {
+        self.change_all();
+        self
+    }
+
+    fn change_all(&mut self) {
+        *self.dirty.get_mut() = DirtyRects::all(self.width() as u32, self.height() as u32);
+    }
+
+    fn change(&mut self, col: usize, row: usize) {
+        self.dirty
+            .get_mut()
+            .add(DirtyRect::pixel(col as u32, row as u32));
+    }
+
+    /// Takes effect from the next generation on.
+    pub fn set_rule(&mut self, rule: Rule) {
+        self.rule = rule;
+    }
+
+    /// Takes effect from the next generation on.
+    pub fn set_topology(&mut self, topology: Topology) {
+        self.topology = topology;
+    }
+
+    pub fn rule(&self) -> Rule {
+        self.rule
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
+    }
+
+    /// Number of complete generations computed since creation.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Whether a generation is being computed, ie. a stepper has been handed out and is not exhausted yet.
+    pub fn is_computing(&self) -> bool {
+        self.in_flight.get()
+    }
+
+    /// A hash of the cells and their owners, the same on every platform.
+    /// Rule, topology and generation are not part of it.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        hasher.write_u64(self.width() as u64);
+        hasher.write_u64(self.height() as u64);
+        // row major, whatever the grid memory layout
+        for row in 0..self.height() {
+            for col in 0..self.width() {
+                hash_cell(
+                    &mut hasher,
+                    self.progress[(row, col)],
+                    self.owners[(row, col)],
+                );
+            }
         }
+        hasher.finish()
+    }
+
+    pub fn cells(&self) -> &Grid<cell::State> {
+        &self.progress
+    }
+
+    pub fn owners(&self) -> &Grid<Option<Tribe>> {
+        &self.owners
+    }
+
+    pub fn owner(&self, x: i64, y: i64) -> Option<Tribe> {
+        let (Ok(col), Ok(row)) = (usize::try_from(x), usize::try_from(y)) else {
+            return None;
+        };
+        self.owners.get(row, col).copied().flatten()
+    }
+
+    /// Number of live cells.
+    pub fn population(&self) -> usize {
+        self.progress
+            .iter()
+            .filter(|s| **s == cell::State::Alive)
+            .count()
     }
 
     pub fn width(&self) -> usize {
@@ -86,16 +343,53 @@ impl Quad {
         self.progress.rows()
     }
 
-    pub fn gen(state: cell::State, width: u16, height: u16) -> Self {
-        let progress: Grid<cell::State> = Grid::init(width as usize, height as usize, state);
+    pub fn gen(state: cell::State, width: u32, height: u32) -> Self {
+        let progress: Grid<cell::State> = Grid::init(height as usize, width as usize, state);
 
         Self::new(progress)
     }
 
+    /// Builds a quad from a bitmap, pixel colours are mapped to cell states via `cell::state`,
+    /// except tribe colours, which are live cells owned by that tribe.
+    pub fn from_image(image: &RGBAImage) -> Self {
+        let (states, owners): (Vec<cell::State>, Vec<Option<Tribe>>) = image
+            .get_image_data()
+            .iter()
+            .map(|p| <[u8; 4]>::from(*p))
+            .map(|p| match Tribe::from_color(&p) {
+                Some(tribe) => (cell::State::Alive, Some(tribe)),
+                None => (cell::state(&p), None),
+            })
+            .unzip();
+
+        Self::new(Grid::from_vec(states, image.width()))
+            .with_owners(Grid::from_vec(owners, image.width()))
+    }
+
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self, bitmap::BitmapError> {
+        let image = bitmap::load_png(path)?;
+        Ok(Self::from_image(&image))
+    }
+
+    /// A save of this quad (see `save`), with the generation in flight if any : give its stepper.
+    /// # Panics
+    /// If a generation is in flight, and its stepper is not given.
+    pub fn save(&self, stepper: Option<&Peekable<QuadUpdate>>) -> Vec<u8> {
+        let mut writer = save::Writer::default();
+        writer.quad(self, stepper);
+        writer.finish()
+    }
+
+    /// Loads a quad from a save, of a game or of a quad alone.
+    /// If it was saved mid-generation, the stepper to finish that generation with is returned too.
+    pub fn load(bytes: &[u8]) -> Result<(Self, Option<Peekable<QuadUpdate>>), DecodeError> {
+        save::Sections::read(bytes)?.quad()
+    }
+
     pub fn with_random_cells(self) -> Self {
         //TODO : generator as parameter
         let mut progress: Grid<cell::State> =
-            Grid::init(self.width(), self.height(), cell::State::Dead);
+            Grid::init(self.height(), self.width(), cell::State::Dead);
 
         for s in progress.iter_mut() {
             if macroquad::prelude::rand::gen_range(0, 5) == 0 {
@@ -105,25 +399,245 @@ impl Quad {
             }
         }
 
-        Self { progress, ..self }
+        Self { progress, ..self }.all_changed()
+    }
+
+    /// Random soup, reproducible from `seed` on any platform (unlike `with_random_cells`).
+    /// About one cell in five is alive.
+    pub fn with_seed(self, seed: u64) -> Self {
+        let mut state = seed;
+        let mut progress: Grid<cell::State> =
+            Grid::init(self.height(), self.width(), cell::State::Dead);
+        // row major, whatever the grid memory layout
+        for row in 0..self.height() {
+            for col in 0..self.width() {
+                // splitmix64 sequence
+                state = state.wrapping_add(0x9e3779b97f4a7c15);
+                if tribe::mix(state).is_multiple_of(5) {
+                    progress[(row, col)] = cell::State::Alive;
+                }
+            }
+        }
+
+        Self { progress, ..self }.all_changed()
+    }
+
+    /// Sets the state of one cell, from outside the simulation (ie. a player).
+    /// Safe to call while a partial update is in flight : the edit will not be overwritten by this generation,
+    /// but its neighbours only take it into account from the next generation on.
+    /// Returns false if the cell is out of bounds.
+    pub fn set_cell(&mut self, x: i64, y: i64, state: cell::State) -> bool {
+        let (Ok(col), Ok(row)) = (usize::try_from(x), usize::try_from(y)) else {
+            return false;
+        };
+        match self.progress.get_mut(row, col) {
+            None => false,
+            Some(s) => {
+                *s = state;
+                if self.in_flight.get() {
+                    self.edited.borrow_mut().insert((col, row));
+                }
+                self.change(col, row);
+                true
+            }
+        }
+    }
+
+    /// Sets the state of all given cells, ignoring the ones out of bounds. Painted cells belong to no tribe.
+    /// Returns the number of cells actually set.
+    pub fn paint(
+        &mut self,
+        cells: impl IntoIterator<Item = (i64, i64)>,
+        state: cell::State,
+    ) -> usize {
+        cells
+            .into_iter()
+            .filter(|(x, y)| {
+                let set = self.set_cell(*x, *y, state);
+                if set {
+                    self.owners[(*y as usize, *x as usize)] = None;
+                }
+                set
+            })
+            .count()
+    }
+
+    /// Sets live cells owned by `tribe`, ignoring the ones out of bounds.
+    /// Returns the number of cells actually set.
+    /// This does not check any placement rule, see `placement::Placements` for that.
+    pub fn place(&mut self, cells: impl IntoIterator<Item = (i64, i64)>, tribe: Tribe) -> usize {
+        cells
+            .into_iter()
+            .filter(|(x, y)| {
+                let set = self.set_cell(*x, *y, cell::State::Alive);
+                if set {
+                    self.owners[(*y as usize, *x as usize)] = Some(tribe);
+                }
+                set
+            })
+            .count()
+    }
+
+    /// Overwrites cells, owners and generation, ie. with a snapshot received from a peer.
+    /// # Panics
+    /// If a generation is in flight, or if the grids do not have the size of this quad.
+    pub fn restore(
+        &mut self,
+        cells: Grid<cell::State>,
+        owners: Grid<Option<Tribe>>,
+        generation: u64,
+    ) {
+        assert!(!self.in_flight.get(), "restoring a quad while computing");
+        assert_eq!((cells.rows(), cells.cols()), (self.height(), self.width()));
+        assert_eq!(
+            (owners.rows(), owners.cols()),
+            (self.height(), self.width())
+        );
+        self.progress = cells;
+        self.owners = owners;
+        self.generation = generation;
+        self.change_all();
+    }
+
+    /// Overwrites a rectangle of cells and owners at (x, y), and the generation, ie. with a chunk received.
+    /// # Panics
+    /// If a generation is in flight, or if the rectangle is not inside this quad.
+    pub fn restore_region(
+        &mut self,
+        x: usize,
+        y: usize,
+        cells: &Grid<cell::State>,
+        owners: &Grid<Option<Tribe>>,
+        generation: u64,
+    ) {
+        assert!(!self.in_flight.get(), "restoring a quad while computing");
+        assert!(x + cells.cols() <= self.width() && y + cells.rows() <= self.height());
+        assert_eq!((owners.rows(), owners.cols()), (cells.rows(), cells.cols()));
+        for row in 0..cells.rows() {
+            for col in 0..cells.cols() {
+                self.progress[(y + row, x + col)] = cells[(row, col)];
+                self.owners[(y + row, x + col)] = owners[(row, col)];
+            }
+        }
+        self.dirty.get_mut().add(DirtyRect::new(
+            x as u32,
+            y as u32,
+            cells.cols() as u32,
+            cells.rows() as u32,
+        ));
+        self.generation = generation;
+    }
+
+    /// A copy of the state, to rewind to later : two grid copies, no image.
+    /// # Panics
+    /// If a generation is in flight : its state is neither the previous generation nor the next.
+    pub fn snapshot(&self) -> Snapshot {
+        assert!(!self.in_flight.get(), "snapshot of a quad while computing");
+        Snapshot {
+            cells: self.progress.clone(),
+            owners: self.owners.clone(),
+            rule: self.rule,
+            topology: self.topology,
+            generation: self.generation,
+        }
+    }
+
+    /// Rewinds to a snapshot, ie. to resimulate from there with other inputs.
+    /// The generation in flight, if any, is dropped : its stepper must not be used anymore.
+    pub fn rollback(&mut self, snapshot: &Snapshot) {
+        self.progress = snapshot.cells.clone();
+        self.owners = snapshot.owners.clone();
+        self.rule = snapshot.rule;
+        self.topology = snapshot.topology;
+        self.generation = snapshot.generation;
+        self.in_flight.set(false);
+        self.edited.get_mut().clear();
+        self.change_all();
+    }
+
+    /// Computes whole generations at once, without shuffling the cells : the fast path for resimulations.
+    /// # Panics
+    /// If a generation is in flight.
+    pub fn resimulate(&mut self, generations: u64) {
+        assert!(!self.in_flight.get(), "resimulating a quad while computing");
+        for _ in 0..generations {
+            self.in_flight.set(true);
+            let mut stepper = QuadUpdate::in_order(&self.progress)
+                .with_rule(self.rule)
+                .with_topology(self.topology)
+                .with_owners(&self.owners)
+                .with_salt(self.generation)
+                .peekable();
+            self.compute(Duration::ZERO, &mut stepper);
+        }
+    }
+
+    /// Cells edited during the generation in flight, sorted.
+    pub(crate) fn edits_in_flight(&self) -> Vec<(usize, usize)> {
+        let mut edited: Vec<(usize, usize)> = self.edited.borrow().iter().copied().collect();
+        edited.sort();
+        edited
+    }
+
+    /// Puts a generation back in flight, with the updates left to apply, in order, and the cells edited so far.
+    /// Returns the stepper to go on with.
+    pub(crate) fn resume(
+        &mut self,
+        remaining: Vec<CellUpdate>,
+        edited: impl IntoIterator<Item = (usize, usize)>,
+    ) -> Peekable<QuadUpdate> {
+        self.in_flight.set(true);
+        *self.edited.get_mut() = edited.into_iter().collect();
+        let mut computed = remaining;
+        // popped from the end
+        computed.reverse();
+        QuadUpdate {
+            original: Grid::init(0, 0, cell::State::Dead),
+            owners: Grid::init(0, 0, None),
+            left_over: vec![],
+            rule: self.rule,
+            topology: self.topology,
+            salt: self.generation,
+            computed,
+        }
+        .peekable()
     }
 
     /// Attempt an update step.
     /// Returns false if the iterator has ended.
     fn update_step(&mut self, _elapsed: Duration, remainder: &mut Peekable<QuadUpdate>) -> bool {
         //attempt an update step
-        match remainder.next() {
+        let progressed = match remainder.next() {
             None => false,
             Some((_, _, None)) => true, // out of bounds ?
-            Some((x, y, Some(cell_state))) => {
-                self.progress[(x as usize, y as usize)] = cell_state;
+            Some((x, y, Some((cell_state, owner)))) => {
+                let changed = (self.progress[(y, x)], self.owners[(y, x)]) != (cell_state, owner);
+                if changed && !self.edited.get_mut().contains(&(x, y)) {
+                    self.progress[(y, x)] = cell_state;
+                    self.owners[(y, x)] = owner;
+                    self.change(x, y);
+                }
                 true
             }
+        };
+        if progressed && remainder.peek().is_none() {
+            // last step of this generation
+            self.generation += 1;
+            self.in_flight.set(false);
+            self.edited.get_mut().clear();
         }
+        progressed
     }
 
     pub(crate) fn stepper(&self) -> QuadUpdate {
+        // the new stepper starts from the current cells, previous edits included.
+        self.in_flight.set(true);
+        self.edited.borrow_mut().clear();
         QuadUpdate::new(&self.progress)
+            .with_rule(self.rule)
+            .with_topology(self.topology)
+            .with_owners(&self.owners)
+            .with_salt(self.generation)
     }
 }
 
@@ -155,13 +669,26 @@ impl Computable for Quad {
 }
 
 impl Viewable for Quad {
-    fn render(&self) -> &RefCell<Image> {
-        self.image
-            .borrow_mut()
-            .deref_mut()
-            .update(to_colors(&self.progress).as_slice());
+    /// Only the cells changed since the last rendering are drawn again.
+    fn render(&self) -> &RefCell<RGBAImage> {
+        let mut image = self.image.borrow_mut();
+        let mut rendered = self.rendered.borrow_mut();
+        for rect in self.dirty.borrow_mut().take() {
+            for row in rect.y..rect.y + rect.height {
+                for col in rect.x..rect.x + rect.width {
+                    let cell = (row as usize, col as usize);
+                    image.set_pixel(col, row, to_color(self.progress[cell], self.owners[cell]));
+                }
+            }
+            rendered.add(rect);
+        }
         &self.image
     }
+
+    /// Meant for a single consumer : the changes it takes are not seen by any other.
+    fn take_changes(&self) -> Option<Vec<DirtyRect>> {
+        Some(self.rendered.borrow_mut().take())
+    }
 }
 
 #[cfg(test)]
@@ -169,11 +696,18 @@ mod tests {
     use crate::cell;
     use crate::cell::State;
     use crate::quad::Quad;
+    use crate::rule::Topology;
+    use crate::tribe::Tribe;
+    use figment::graphics::color::RGBA8;
+    use figment::graphics::dirty::DirtyRect;
+    use figment::graphics::image::RGBAImage;
+    use std::ops::Deref;
     use std::time::Duration;
 
     use figment::compute::Computable;
+    use figment::graphics::{bitmap, golden, Viewable};
 
-    use grid::grid;
+    use grid::{grid, Grid};
     use test::Bencher;
 
     #[test]
@@ -231,6 +765,296 @@ mod tests {
         )
     }
 
+    #[test]
+    fn check_from_image_maps_colors() {
+        let mut img = RGBAImage::generate(3, 2, cell::DEAD.into());
+        img.set_pixel(2, 0, cell::ALIVE.into());
+        img.set_pixel(0, 1, cell::ALIVE.into());
+
+        let q = Quad::from_image(&img);
+
+        assert_eq!(q.width(), 3);
+        assert_eq!(q.height(), 2);
+        assert_eq!(
+            q.progress,
+            grid![[State::Dead, State::Dead, State::Alive][State::Alive, State::Dead, State::Dead]]
+        )
+    }
+
+    #[test]
+    fn check_png_roundtrip() {
+        let q = Quad::new(
+            grid![[State::Alive, State::Dead, State::Dead][State::Alive, State::Dead, State::Alive]],
+        );
+
+        let mut encoded: Vec<u8> = vec![];
+        bitmap::write_png(q.render().borrow().deref(), &mut encoded).unwrap();
+        let decoded = Quad::from_image(&bitmap::read_png(encoded.as_slice()).unwrap());
+
+        assert_eq!(decoded.progress, q.progress)
+    }
+
+    #[test]
+    fn check_non_square_update() {
+        // horizontal blinker, in a wide quad
+        let d = State::Dead;
+        let a = State::Alive;
+        let mut q = Quad::new(grid![[d, d, d, d, d][d, a, a, a, d][d, d, d, d, d]]);
+
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+
+        assert_eq!(
+            q.progress,
+            grid![[d, d, a, d, d][d, d, a, d, d][d, d, a, d, d]]
+        )
+    }
+
+    #[test]
+    fn check_generation_counted_once_per_update() {
+        let mut q = Quad::gen(State::Dead, 3, 2);
+        assert_eq!(q.generation(), 0);
+
+        let mut stepper = q.compute_reset();
+        q.compute_until(Duration::new(0, 0), &mut stepper, || true);
+        assert_eq!(q.generation(), 0);
+        q.compute(Duration::new(0, 0), &mut stepper);
+        assert_eq!(q.generation(), 1);
+        q.compute(Duration::new(0, 0), &mut stepper);
+        assert_eq!(q.generation(), 1);
+    }
+
+    #[test]
+    fn check_torus_glider_comes_back() {
+        let d = State::Dead;
+        let a = State::Alive;
+        let mut glider = Grid::init(8, 8, d);
+        glider[(0, 1)] = a;
+        glider[(1, 2)] = a;
+        glider[(2, 0)] = a;
+        glider[(2, 1)] = a;
+        glider[(2, 2)] = a;
+        let mut q = Quad::new(glider.clone()).with_topology(Topology::Torus);
+
+        // a glider moves one cell diagonally every 4 generations
+        for _ in 0..32 {
+            let mut stepper = q.compute_reset();
+            q.compute(Duration::new(0, 0), &mut stepper);
+        }
+
+        assert_eq!(q.generation(), 32);
+        assert_eq!(q.cells(), &glider);
+        assert_eq!(q.population(), 5);
+    }
+
+    #[test]
+    fn check_paint_ignores_out_of_bounds() {
+        let mut q = Quad::gen(State::Dead, 3, 2);
+
+        assert_eq!(q.paint([(0, 0), (2, 1), (3, 0), (-1, 1)], State::Alive), 2);
+        assert_eq!(q.population(), 2);
+        assert_eq!(q.cells()[(1, 2)], State::Alive);
+
+        // neutral cells, over owned ones
+        q.place([(1, 1), (2, 1)], Tribe(3));
+        q.paint([(1, 1)], State::Alive);
+        q.paint([(2, 1)], State::Dead);
+        assert_eq!(q.owner(1, 1), None);
+        assert_eq!(q.owner(2, 1), None);
+    }
+
+    #[test]
+    fn check_edit_between_generations_is_computed() {
+        let mut q = Quad::gen(State::Dead, 3, 3);
+        q.set_cell(1, 1, State::Alive);
+
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+
+        // alone, it dies at the next generation
+        assert_eq!(q.population(), 0);
+    }
+
+    #[test]
+    fn check_edit_survives_update_in_flight() {
+        let mut q = Quad::gen(State::Dead, 4, 4);
+
+        let mut stepper = q.compute_reset();
+        q.compute_until(Duration::new(0, 0), &mut stepper, || true);
+        // every cell will be set to dead by this update, still our edits stay.
+        q.paint([(0, 0), (3, 3)], State::Alive);
+        q.compute(Duration::new(0, 0), &mut stepper);
+        assert_eq!(q.generation(), 1);
+        assert_eq!(q.population(), 2);
+
+        // then the next generation applies rules as usual
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+        assert_eq!(q.population(), 0);
+    }
+
+    #[test]
+    fn check_births_inherit_majority_owner() {
+        let d = State::Dead;
+        let a = State::Alive;
+        // vertical blinker, two cells of tribe 1, one of tribe 0
+        let mut q = Quad::new(grid![[d, a, d][d, a, d][d, a, d]]);
+        q.place([(1, 0)], Tribe(0));
+        q.place([(1, 1), (1, 2)], Tribe(1));
+
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+
+        assert_eq!(q.cells(), &grid![[d, d, d][a, a, a][d, d, d]]);
+        // the middle cell survives, the new ones each have 2 tribe 1 neighbours out of 3
+        assert_eq!(q.owner(1, 1), Some(Tribe(1)));
+        assert_eq!(q.owner(0, 1), Some(Tribe(1)));
+        assert_eq!(q.owner(2, 1), Some(Tribe(1)));
+        // dead cells stay the territory of their last owner
+        assert_eq!(q.owner(1, 0), Some(Tribe(0)));
+    }
+
+    #[test]
+    fn check_tribe_colors_roundtrip() {
+        let d = State::Dead;
+        let a = State::Alive;
+        let mut q = Quad::new(grid![[a, d][d, a]]);
+        q.place([(1, 0)], Tribe(3));
+
+        let decoded = Quad::from_image(q.render().borrow().deref());
+
+        assert_eq!(decoded.cells(), &grid![[a, a][d, a]]);
+        assert_eq!(decoded.owner(1, 0), Some(Tribe(3)));
+        assert_eq!(decoded.owner(0, 0), None);
+    }
+
+    #[test]
+    fn check_render_golden() {
+        let d = State::Dead;
+        let a = State::Alive;
+        let mut q = Quad::new(grid![
+            [d, a, d, d, d, d, d, d]
+            [d, d, a, d, d, d, d, d]
+            [a, a, a, d, d, a, a, d]
+            [d, d, d, d, d, a, a, d]
+            [d, d, d, d, d, d, d, d]
+            [d, d, d, d, d, d, d, d]
+        ]);
+        q.place([(1, 0), (2, 1), (0, 2), (1, 2), (2, 2)], Tribe(0));
+        q.place([(5, 2), (6, 2), (5, 3), (6, 3)], Tribe(1));
+        for _ in 0..2 {
+            let mut stepper = q.compute_reset();
+            q.compute(Duration::new(0, 0), &mut stepper);
+        }
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/golden/quad.png");
+        golden::assert_golden(path, q.render().borrow().deref(), 0);
+    }
+
+    #[test]
+    fn check_rollback_and_resimulate() {
+        let mut q = Quad::gen(State::Dead, 24, 16).with_seed(3);
+        q.place([(4, 4), (5, 4), (6, 4)], Tribe(1));
+        let start = q.snapshot();
+
+        for _ in 0..10 {
+            let mut stepper = q.compute_reset();
+            q.compute(Duration::new(0, 0), &mut stepper);
+        }
+        let expected = q.checksum();
+
+        // an edit, rolled back
+        q.rollback(&start);
+        q.place([(10, 10), (11, 10)], Tribe(2));
+        q.rollback(&start);
+        assert_eq!(q.generation(), 0);
+        assert_eq!(q.snapshot(), start);
+
+        // in order or shuffled, the same generations
+        q.resimulate(10);
+        assert_eq!(q.generation(), 10);
+        assert_eq!(q.checksum(), expected);
+        assert!(!q.is_computing());
+    }
+
+    #[test]
+    fn check_rollback_drops_generation_in_flight() {
+        let mut q = Quad::gen(State::Dead, 8, 8).with_seed(1);
+        let start = q.snapshot();
+
+        let mut stepper = q.compute_reset();
+        q.compute_until(Duration::new(0, 0), &mut stepper, || true);
+        assert!(q.is_computing());
+        q.rollback(&start);
+
+        assert!(!q.is_computing());
+        assert_eq!(q.snapshot(), start);
+    }
+
+    #[test]
+    fn check_render_only_redraws_changes() {
+        let mut q = Quad::gen(State::Dead, 6, 4);
+        q.render();
+        assert_eq!(q.take_changes(), Some(vec![DirtyRect::new(0, 0, 6, 4)]));
+
+        // a marker, that a full redraw would erase
+        q.image.borrow_mut().set_pixel(0, 0, RGBA8::default());
+        q.set_cell(2, 1, State::Alive);
+        q.restore_region(4, 2, &grid![[State::Alive]], &grid![[Some(Tribe(1))]], 0);
+        let rendered = q.render().borrow().clone();
+
+        assert_eq!(
+            q.take_changes(),
+            Some(vec![DirtyRect::pixel(2, 1), DirtyRect::pixel(4, 2)])
+        );
+        assert_eq!(q.take_changes(), Some(vec![]));
+        assert_eq!(rendered.get_pixel(0, 0), RGBA8::default());
+        assert_eq!(
+            rendered.get_pixel(2, 1),
+            RGBA8::from(cell::color(State::Alive))
+        );
+        assert_eq!(rendered.get_pixel(4, 2), RGBA8::from(Tribe(1).color()));
+    }
+
+    #[test]
+    fn check_wider_than_u16() {
+        let mut q = Quad::gen(State::Dead, 70_000, 3);
+        q.paint([(69_997, 1), (69_998, 1), (69_999, 1)], State::Alive);
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+
+        let image = q.render().borrow().clone();
+        assert_eq!((image.width(), image.height()), (70_000, 3));
+        assert_eq!(
+            image.get_pixel(69_998, 0),
+            RGBA8::from(cell::color(State::Alive))
+        );
+        assert_eq!(
+            image.get_pixel(69_999, 1),
+            RGBA8::from(cell::color(State::Dead))
+        );
+        assert_eq!(Quad::from_image(&image).cells(), q.cells());
+    }
+
+    #[test]
+    fn check_changes_of_a_generation() {
+        let mut q = Quad::gen(State::Dead, 8, 8);
+        q.paint([(1, 2), (2, 2), (3, 2)], State::Alive);
+        q.render();
+        q.take_changes();
+
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+        q.render();
+
+        // the blinker turns : 2 births and 2 deaths, around its still middle cell
+        assert_eq!(q.take_changes(), Some(vec![DirtyRect::new(1, 1, 3, 3)]));
+
+        q.rollback(&q.snapshot());
+        q.render();
+        assert_eq!(q.take_changes(), Some(vec![DirtyRect::new(0, 0, 8, 8)]));
+    }
+
     // TODO : check blinking !
 
     #[bench]
@@ -262,4 +1086,22 @@ mod tests {
             q.compute(Duration::new(0, 0), &mut stepper);
         });
     }
+
+    #[bench]
+    fn bench_resimulate_256_256(b: &mut Bencher) {
+        let mut q = Quad::gen(State::Dead, 256, 256).with_random_cells();
+
+        b.iter(|| q.resimulate(1));
+    }
+
+    #[bench]
+    fn bench_snapshot_rollback_256_256(b: &mut Bencher) {
+        let mut q = Quad::gen(State::Dead, 256, 256).with_random_cells();
+        let snapshot = q.snapshot();
+
+        b.iter(|| {
+            q.rollback(&snapshot);
+            q.snapshot()
+        });
+    }
 }
@@ -0,0 +1,176 @@
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// A life-like rule, in B/S notation (ie. "B3/S23" for Conway's game of life).
+/// Neighbour counts are stored as bitmasks, bit n set meaning n neighbours.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rule {
+    pub(crate) birth: u16,
+    pub(crate) survival: u16,
+}
+
+impl Rule {
+    pub const CONWAY: Rule = Rule {
+        birth: 1 << 3,
+        survival: 1 << 2 | 1 << 3,
+    };
+
+    /// # Panics
+    /// If a neighbour count is above 8 : parse a rule to check it.
+    pub fn new(birth: &[u8], survival: &[u8]) -> Self {
+        let mask = |counts: &[u8]| {
+            counts.iter().fold(0u16, |m, c| {
+                assert!(*c <= 8, "{} neighbours, out of 0 to 8", c);
+                m | 1 << c
+            })
+        };
+        Self {
+            birth: mask(birth),
+            survival: mask(survival),
+        }
+    }
+
+    /// Whether a dead cell with `neighbours` live neighbours becomes alive.
+    pub fn born(&self, neighbours: i32) -> bool {
+        (0..=8).contains(&neighbours) && self.birth & 1 << neighbours != 0
+    }
+
+    /// Whether a live cell with `neighbours` live neighbours stays alive.
+    pub fn survives(&self, neighbours: i32) -> bool {
+        (0..=8).contains(&neighbours) && self.survival & 1 << neighbours != 0
+    }
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule::CONWAY
+    }
+}
+
+impl Display for Rule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let digits = |mask: u16| -> String {
+            (0..=8)
+                .filter(|n| mask & 1 << n != 0)
+                .map(|n| char::from(b'0' + n as u8))
+                .collect()
+        };
+        write!(f, "B{}/S{}", digits(self.birth), digits(self.survival))
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String; // TODO : proper Error type
+
+    /// Accepts "B3/S23" (case insensitive, any part order) and the older "23/3" S/B notation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split('/').collect();
+        if parts.len() != 2 {
+            return Err(format!("{:?} is not a B/S rule", s));
+        }
+
+        let counts = |digits: &str| -> Result<Vec<u8>, String> {
+            digits
+                .chars()
+                .map(|c| match c.to_digit(10) {
+                    Some(d) if d <= 8 => Ok(d as u8),
+                    _ => Err(format!("{:?} is not a neighbour count in {:?}", c, s)),
+                })
+                .collect()
+        };
+
+        let (mut birth, mut survival) = (None, None);
+        for (i, part) in parts.iter().enumerate() {
+            let upper = part.to_ascii_uppercase();
+            if let Some(b) = upper.strip_prefix('B') {
+                birth = Some(counts(b)?);
+            } else if let Some(s) = upper.strip_prefix('S') {
+                survival = Some(counts(s)?);
+            } else if i == 0 {
+                survival = Some(counts(&upper)?);
+            } else {
+                birth = Some(counts(&upper)?);
+            }
+        }
+
+        match (birth, survival) {
+            (Some(b), Some(s)) => Ok(Rule::new(b.as_slice(), s.as_slice())),
+            _ => Err(format!("{:?} is not a B/S rule", s)),
+        }
+    }
+}
+
+/// How the borders of a quad behave.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Topology {
+    /// Cells beyond the borders are always dead.
+    #[default]
+    Bounded,
+    /// Opposite borders are connected.
+    Torus,
+}
+
+impl Display for Topology {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Topology::Bounded => write!(f, "bounded"),
+            Topology::Torus => write!(f, "torus"),
+        }
+    }
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "bounded" | "plane" => Ok(Topology::Bounded),
+            "torus" | "wrap" => Ok(Topology::Torus),
+            _ => Err(format!("{:?} is not a topology (bounded or torus)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rule::{Rule, Topology};
+    use std::str::FromStr;
+
+    #[test]
+    fn check_conway_notation() {
+        assert_eq!(Rule::from_str("B3/S23").unwrap(), Rule::CONWAY);
+        assert_eq!(Rule::from_str("s23/b3").unwrap(), Rule::CONWAY);
+        assert_eq!(Rule::from_str("23/3").unwrap(), Rule::CONWAY);
+        assert_eq!(Rule::CONWAY.to_string(), "B3/S23");
+    }
+
+    #[test]
+    fn check_highlife() {
+        let highlife = Rule::from_str("B36/S23").unwrap();
+        assert!(highlife.born(6));
+        assert!(!highlife.survives(6));
+        assert_eq!(highlife.to_string(), "B36/S23");
+    }
+
+    #[test]
+    fn check_invalid_rules() {
+        assert!(Rule::from_str("B3S23").is_err());
+        assert!(Rule::from_str("B9/S23").is_err());
+        assert!(Rule::from_str("B3/S239").is_err());
+        assert!(Rule::from_str("239/3").is_err());
+        assert!(Rule::from_str("B3/B23").is_err());
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_new_refuses_counts_above_8() {
+        Rule::new(&[3], &[2, 9]);
+    }
+
+    #[test]
+    fn check_topology_parse() {
+        assert_eq!(Topology::from_str("Torus").unwrap(), Topology::Torus);
+        assert_eq!(Topology::Bounded.to_string(), "bounded");
+        assert!(Topology::from_str("sphere").is_err());
+    }
+}
@@ -0,0 +1,455 @@
+//! Save files : the whole state of a game, to go on with it later, exactly where it stopped.
+//!
+//! A save is a magic, a format version, then tagged sections : a tag byte, a length (u32), and the encoded section.
+//! Readers skip the sections they do not know : a section added by a later version does not break older readers,
+//! as long as the sections they know keep their layout. A change of layout needs a new version,
+//! and the fixtures of the previous ones (see `fixtures/`) must still load.
+//!
+//! The simulation draws no random number (tie breaks between tribes are hashed from the generation),
+//! so there is no generator state to save : the only randomness is the shuffled order of a generation in flight,
+//! which is saved as the updates left to apply, in order.
+//! There is no terrain layer, nor multi quad world yet (see `world`) : the board is one quad, with its tribe layer.
+use crate::cell;
+use crate::codec::{take, Decode, DecodeError, Encode};
+use crate::delta;
+use crate::quad::{CellUpdate, Quad, QuadUpdate};
+use crate::rule::{Rule, Topology};
+use crate::tribe::Tribe;
+use grid::Grid;
+use std::collections::BTreeMap;
+use std::iter::Peekable;
+
+const SAVE_MAGIC: &[u8; 4] = b"QSAV";
+/// Version of the saves written. Every version up to this one can be loaded.
+pub const SAVE_VERSION: u8 = 1;
+
+// Section tags.
+pub(crate) const BOARD: u8 = 1;
+pub(crate) const RULES: u8 = 2;
+pub(crate) const PROGRESS: u8 = 3;
+pub(crate) const PLACEMENTS: u8 = 4;
+pub(crate) const PENDING: u8 = 5;
+pub(crate) const LOG: u8 = 6;
+
+/// Cells and owners, as run-length encoded planes (see `delta`).
+struct Board {
+    cells: Grid<cell::State>,
+    owners: Grid<Option<Tribe>>,
+}
+
+impl Encode for Board {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.cells.cols() as u32).encode(out);
+        (self.cells.rows() as u32).encode(out);
+        // a quad only to reuse the planes layout of frames
+        let quad = Quad::new(self.cells.clone()).with_owners(self.owners.clone());
+        let data = delta::rle_encode(&delta::planes(&quad, delta::board(&quad)));
+        (data.len() as u32).encode(out);
+        out.extend_from_slice(&data);
+    }
+}
+
+impl Decode for Board {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let width = u32::decode(input)? as usize;
+        let height = u32::decode(input)? as usize;
+        let len = u32::decode(input)? as usize;
+        let data = take(input, len)?;
+        // a run is 2 bytes for at most 130 : do not trust the size for the allocation
+        let planes_len = delta::planes_len(width, height)
+            .filter(|len| *len <= data.len() * 65)
+            .ok_or_else(|| {
+                DecodeError::Invalid(format!(
+                    "{}x{} board in {} bytes",
+                    width,
+                    height,
+                    data.len()
+                ))
+            })?;
+        let planes =
+            delta::rle_decode(data, planes_len).map_err(|e| DecodeError::Invalid(e.to_string()))?;
+        let (cells, owners) = delta::from_planes(&planes, width, height);
+        Ok(Board { cells, owners })
+    }
+}
+
+struct Rules {
+    rule: Rule,
+    topology: Topology,
+    generation: u64,
+}
+
+impl Encode for Rules {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.rule.encode(out);
+        self.topology.encode(out);
+        self.generation.encode(out);
+    }
+}
+
+impl Decode for Rules {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(Rules {
+            rule: Rule::decode(input)?,
+            topology: Topology::decode(input)?,
+            generation: u64::decode(input)?,
+        })
+    }
+}
+
+/// A generation in flight : what is left of it, in the order it is applied, and the cells edited meanwhile.
+struct Progress {
+    remaining: Vec<CellUpdate>,
+    edited: Vec<(usize, usize)>,
+}
+
+impl Encode for Progress {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (self.remaining.len() as u32).encode(out);
+        for (x, y, update) in &self.remaining {
+            (*x as u32).encode(out);
+            (*y as u32).encode(out);
+            update.encode(out);
+        }
+        let edited: Vec<(u32, u32)> = self
+            .edited
+            .iter()
+            .map(|(x, y)| (*x as u32, *y as u32))
+            .collect();
+        edited.encode(out);
+    }
+}
+
+impl Decode for Progress {
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = u32::decode(input)? as usize;
+        let mut remaining = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            let x = u32::decode(input)? as usize;
+            let y = u32::decode(input)? as usize;
+            remaining.push((x, y, Option::decode(input)?));
+        }
+        let edited = Vec::<(u32, u32)>::decode(input)?
+            .into_iter()
+            .map(|(x, y)| (x as usize, y as usize))
+            .collect();
+        Ok(Progress { remaining, edited })
+    }
+}
+
+/// Writes a save, section by section.
+pub(crate) struct Writer {
+    out: Vec<u8>,
+}
+
+impl Default for Writer {
+    fn default() -> Self {
+        let mut out = SAVE_MAGIC.to_vec();
+        SAVE_VERSION.encode(&mut out);
+        Self { out }
+    }
+}
+
+impl Writer {
+    pub(crate) fn section(&mut self, tag: u8, value: &impl Encode) {
+        let bytes = value.to_bytes();
+        tag.encode(&mut self.out);
+        (bytes.len() as u32).encode(&mut self.out);
+        self.out.extend_from_slice(&bytes);
+    }
+
+    /// The sections of a quad, with the generation in flight if any.
+    /// # Panics
+    /// If a generation is in flight, and its stepper is not given.
+    pub(crate) fn quad(&mut self, quad: &Quad, stepper: Option<&Peekable<QuadUpdate>>) {
+        self.section(
+            BOARD,
+            &Board {
+                cells: quad.cells().clone(),
+                owners: quad.owners().clone(),
+            },
+        );
+        self.section(
+            RULES,
+            &Rules {
+                rule: quad.rule(),
+                topology: quad.topology(),
+                generation: quad.generation(),
+            },
+        );
+        if quad.is_computing() {
+            let stepper = stepper.expect("saving a quad while computing, without its stepper");
+            self.section(
+                PROGRESS,
+                &Progress {
+                    // computes the rest of the generation, on a copy
+                    remaining: stepper.clone().collect(),
+                    edited: quad.edits_in_flight(),
+                },
+            );
+        }
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+/// The sections of a save, checked and indexed by tag.
+pub(crate) struct Sections<'a> {
+    sections: BTreeMap<u8, &'a [u8]>,
+}
+
+impl<'a> Sections<'a> {
+    pub(crate) fn read(mut input: &'a [u8]) -> Result<Self, DecodeError> {
+        if take(&mut input, SAVE_MAGIC.len())? != SAVE_MAGIC {
+            return Err(DecodeError::Invalid("not a save".to_string()));
+        }
+        let version = u8::decode(&mut input)?;
+        // version 1 is the first : sections have kept their layout since
+        if version == 0 || version > SAVE_VERSION {
+            return Err(DecodeError::Invalid(format!(
+                "unsupported save version {}",
+                version
+            )));
+        }
+        let mut sections = BTreeMap::new();
+        while !input.is_empty() {
+            let tag = u8::decode(&mut input)?;
+            let len = u32::decode(&mut input)? as usize;
+            if sections.insert(tag, take(&mut input, len)?).is_some() {
+                return Err(DecodeError::Invalid(format!("section {} twice", tag)));
+            }
+        }
+        Ok(Self { sections })
+    }
+
+    pub(crate) fn get<T: Decode>(&self, tag: u8) -> Result<Option<T>, DecodeError> {
+        self.sections
+            .get(&tag)
+            .map(|bytes| T::from_bytes(bytes))
+            .transpose()
+    }
+
+    pub(crate) fn require<T: Decode>(&self, tag: u8, name: &str) -> Result<T, DecodeError> {
+        self.get(tag)?
+            .ok_or_else(|| DecodeError::Invalid(format!("no {} in save", name)))
+    }
+
+    /// The quad, and the stepper of its generation in flight if it was saved computing.
+    pub(crate) fn quad(&self) -> Result<(Quad, Option<Peekable<QuadUpdate>>), DecodeError> {
+        let board: Board = self.require(BOARD, "board")?;
+        let rules: Rules = self.require(RULES, "rules")?;
+        let (width, height) = (board.cells.cols(), board.cells.rows());
+        let mut quad = Quad::new(board.cells)
+            .with_owners(board.owners)
+            .with_rule(rules.rule)
+            .with_topology(rules.topology)
+            .with_generation(rules.generation);
+
+        let stepper = match self.get::<Progress>(PROGRESS)? {
+            None => None,
+            Some(progress) => {
+                let inside = |x: usize, y: usize| x < width && y < height;
+                if !progress.remaining.iter().all(|(x, y, _)| inside(*x, *y))
+                    || !progress.edited.iter().all(|(x, y)| inside(*x, *y))
+                {
+                    return Err(DecodeError::Invalid(
+                        "generation in flight out of the board".to_string(),
+                    ));
+                }
+                Some(quad.resume(progress.remaining, progress.edited))
+            }
+        };
+        Ok((quad, stepper))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::codec::DecodeError;
+    use crate::event::Event;
+    use crate::event::{Action, Game};
+    use crate::placement::Policy;
+    use crate::quad::{Quad, QuadUpdate};
+    use crate::rule::{Rule, Topology};
+    use crate::save::SAVE_VERSION;
+    use crate::tribe::Tribe;
+    use figment::compute::Computable;
+    use std::iter::Peekable;
+    use std::time::Duration;
+
+    /// The game saved in `fixtures/save_v1_game.qsav` : replayable from its seed and actions.
+    fn game() -> Game {
+        let mut game = Game::new(
+            Quad::gen(State::Dead, 48, 40)
+                .with_seed(7)
+                .with_topology(Topology::Torus),
+            Policy::default(),
+        );
+        for g in 0..30u8 {
+            if g % 5 == 0 {
+                let x = g as i64;
+                game.submit(Action::Place {
+                    tribe: Tribe(g % 3),
+                    cells: vec![(x, 10), (x + 1, 10), (x + 2, 10)],
+                });
+            }
+            if g == 12 {
+                game.submit(Action::SetRule(Rule::new(&[3, 6], &[2, 3])));
+            }
+            game.step().unwrap();
+        }
+        // still pending when saved
+        game.schedule(Event {
+            generation: 40,
+            action: Action::Erase {
+                cells: vec![(1, 1), (2, 2)],
+            },
+        });
+        game
+    }
+
+    /// The quad saved in `fixtures/save_v1_in_flight.qsav` : 100 cells into its fourth generation,
+    /// with a cell edited since.
+    fn in_flight() -> Quad {
+        Quad::gen(State::Dead, 24, 16).with_seed(3)
+    }
+
+    const EDITED: (i64, i64) = (5, 6);
+
+    /// `in_flight`, stopped where it was saved.
+    fn stopped() -> (Quad, Peekable<QuadUpdate>) {
+        let mut q = in_flight();
+        q.resimulate(3);
+        let mut stepper = q.compute_reset();
+        for _ in 0..100 {
+            q.compute_until(Duration::ZERO, &mut stepper, || true);
+        }
+        q.set_cell(EDITED.0, EDITED.1, State::Alive);
+        (q, stepper)
+    }
+
+    fn assert_same_game(a: &Game, b: &Game) {
+        assert_eq!(a.quad().cells(), b.quad().cells());
+        assert_eq!(a.quad().owners(), b.quad().owners());
+        assert_eq!(a.quad().rule(), b.quad().rule());
+        assert_eq!(a.quad().topology(), b.quad().topology());
+        assert_eq!(a.quad().generation(), b.quad().generation());
+        assert_eq!(a.placements(), b.placements());
+        assert_eq!(a.pending(), b.pending());
+        assert_eq!(a.log(), b.log());
+    }
+
+    /// Checks a loaded mid-generation save of `in_flight` finishes its generation as the original would have.
+    fn check_resumes(loaded: &mut Quad, stepper: &mut Peekable<QuadUpdate>) {
+        let mut expected = in_flight();
+        expected.resimulate(4);
+        // the edit survives the generation it was made in, which does not see it
+        expected.set_cell(EDITED.0, EDITED.1, State::Alive);
+
+        assert!(loaded.is_computing());
+        assert_eq!(loaded.generation(), 3);
+        loaded.compute(Duration::ZERO, stepper);
+        assert!(!loaded.is_computing());
+        assert_eq!(loaded.generation(), 4);
+        assert_eq!(loaded.cells(), expected.cells());
+    }
+
+    #[test]
+    fn check_game_roundtrip() {
+        let saved = game();
+        let (loaded, stepper) = Game::load(&saved.save(None)).unwrap();
+        assert!(stepper.is_none());
+        assert_same_game(&loaded, &saved);
+    }
+
+    #[test]
+    fn check_save_mid_generation_resumes_exactly() {
+        let (mut q, mut stepper) = stopped();
+
+        let save = q.save(Some(&stepper));
+        let (mut loaded, loaded_stepper) = Quad::load(&save).unwrap();
+        let mut loaded_stepper = loaded_stepper.unwrap();
+
+        // both go on the same, cell by cell
+        for _ in 0..50 {
+            q.compute_until(Duration::ZERO, &mut stepper, || true);
+            loaded.compute_until(Duration::ZERO, &mut loaded_stepper, || true);
+            assert_eq!(loaded.cells(), q.cells());
+            assert_eq!(loaded.owners(), q.owners());
+        }
+        check_resumes(&mut loaded, &mut loaded_stepper);
+    }
+
+    #[test]
+    #[should_panic]
+    fn check_save_mid_generation_needs_the_stepper() {
+        let q = in_flight();
+        let _stepper = q.compute_reset();
+        q.save(None);
+    }
+
+    #[test]
+    fn check_v1_fixtures_load() {
+        let (loaded, stepper) =
+            Game::load(include_bytes!("../fixtures/save_v1_game.qsav")).unwrap();
+        assert!(stepper.is_none());
+        assert_same_game(&loaded, &game());
+
+        let (mut loaded, stepper) =
+            Quad::load(include_bytes!("../fixtures/save_v1_in_flight.qsav")).unwrap();
+        check_resumes(&mut loaded, &mut stepper.unwrap());
+    }
+
+    #[test]
+    fn check_unknown_sections_are_skipped() {
+        let mut save = game().save(None);
+        // a section from a later version
+        save.extend_from_slice(&[200, 3, 0, 0, 0, 1, 2, 3]);
+        let (loaded, _) = Game::load(&save).unwrap();
+        assert_same_game(&loaded, &game());
+    }
+
+    #[test]
+    fn check_invalid_saves() {
+        let save = game().save(None);
+
+        let mut newer = save.clone();
+        newer[4] = SAVE_VERSION + 1;
+        assert!(matches!(Game::load(&newer), Err(DecodeError::Invalid(_))));
+        assert!(matches!(
+            Game::load(b"QLOG\x01"),
+            Err(DecodeError::Invalid(_))
+        ));
+        assert_eq!(
+            Game::load(&save[..save.len() - 1]).err(),
+            Some(DecodeError::UnexpectedEnd)
+        );
+        // a quad alone is not a game
+        assert!(Game::load(&in_flight().save(None)).is_err());
+        assert!(Quad::load(&save).is_ok());
+    }
+
+    /// Writes the fixtures of the current version, to keep them once the format changes.
+    /// `cargo test -p quadlife write_save_fixtures -- --ignored`
+    #[test]
+    #[ignore]
+    fn write_save_fixtures() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join(format!("save_v{}_game.qsav", SAVE_VERSION)),
+            game().save(None),
+        )
+        .unwrap();
+
+        let (q, stepper) = stopped();
+        std::fs::write(
+            dir.join(format!("save_v{}_in_flight.qsav", SAVE_VERSION)),
+            q.save(Some(&stepper)),
+        )
+        .unwrap();
+    }
+}
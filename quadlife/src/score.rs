@@ -0,0 +1,363 @@
+use crate::cell;
+use crate::quad::Quad;
+use crate::tribe::Tribe;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Standing of one tribe at a given generation.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Score {
+    pub tribe: Tribe,
+    pub live_cells: usize,
+    /// Cells owned by the tribe, dead or alive.
+    pub territory: usize,
+    /// Share of all live cells on the board, in [0, 1].
+    pub live_share: f32,
+    /// Share of the whole board owned by the tribe, in [0, 1].
+    pub terrain_share: f32,
+}
+
+impl Score {
+    /// What ranks tribes when the time is up : the territory, live cells breaking ties.
+    pub fn points(&self) -> (usize, usize) {
+        (self.territory, self.live_cells)
+    }
+}
+
+/// Scores of all tribes present on a quad.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scoreboard {
+    pub generation: u64,
+    pub total_cells: usize,
+    /// Live cells, tribeless ones included.
+    pub total_live: usize,
+    /// One entry per tribe owning at least a cell, ordered by tribe.
+    pub scores: Vec<Score>,
+}
+
+impl Scoreboard {
+    pub fn of(quad: &Quad) -> Self {
+        let mut counts: BTreeMap<Tribe, (usize, usize)> = BTreeMap::new();
+        let mut total_live = 0;
+
+        for (state, owner) in quad.cells().iter().zip(quad.owners().iter()) {
+            let alive = *state == cell::State::Alive;
+            if alive {
+                total_live += 1;
+            }
+            if let Some(tribe) = owner {
+                let (live, territory) = counts.entry(*tribe).or_default();
+                *territory += 1;
+                if alive {
+                    *live += 1;
+                }
+            }
+        }
+
+        let total_cells = quad.width() * quad.height();
+        let share = |n: usize, total: usize| {
+            if total == 0 {
+                0.
+            } else {
+                n as f32 / total as f32
+            }
+        };
+        Self {
+            generation: quad.generation(),
+            total_cells,
+            total_live,
+            scores: counts
+                .into_iter()
+                .map(|(tribe, (live_cells, territory))| Score {
+                    tribe,
+                    live_cells,
+                    territory,
+                    live_share: share(live_cells, total_live),
+                    terrain_share: share(territory, total_cells),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn get(&self, tribe: Tribe) -> Option<&Score> {
+        self.scores.iter().find(|s| s.tribe == tribe)
+    }
+
+    /// Tribes with at least one live cell.
+    pub fn alive(&self) -> impl Iterator<Item = Tribe> + '_ {
+        self.scores
+            .iter()
+            .filter(|s| s.live_cells > 0)
+            .map(|s| s.tribe)
+    }
+
+    /// The single tribe with the most points, if there is no tie.
+    pub fn leader(&self) -> Option<Tribe> {
+        let best = self.scores.iter().map(|s| s.points()).max()?;
+        let mut leaders = self.scores.iter().filter(|s| s.points() == best);
+        match (leaders.next(), leaders.next()) {
+            (Some(s), None) => Some(s.tribe),
+            _ => None,
+        }
+    }
+}
+
+/// A way to end the game.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Victory {
+    /// A tribe owns at least this share of the board, in [0, 1].
+    Domination(f32),
+    /// Only one tribe still has live cells (once at least two have played).
+    LastTribeAlive,
+    /// After this many generations, the leader wins.
+    TimeLimit(u64),
+}
+
+impl Display for Victory {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Victory::Domination(share) => write!(f, "domination:{}", share * 100.),
+            Victory::LastTribeAlive => write!(f, "last-alive"),
+            Victory::TimeLimit(generations) => write!(f, "time:{}", generations),
+        }
+    }
+}
+
+impl FromStr for Victory {
+    type Err = String;
+
+    /// Accepts "domination:<percent>", "last-alive" and "time:<generations>".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower = s.trim().to_ascii_lowercase();
+        let (kind, value) = match lower.split_once(':') {
+            Some((k, v)) => (k, Some(v)),
+            None => (lower.as_str(), None),
+        };
+        match (kind, value) {
+            ("last-alive", None) => Ok(Victory::LastTribeAlive),
+            ("domination", Some(v)) => match v.parse::<f32>() {
+                Ok(percent) if percent > 0. && percent <= 100. => {
+                    Ok(Victory::Domination(percent / 100.))
+                }
+                _ => Err(format!("{:?} is not a percentage in {:?}", v, s)),
+            },
+            ("time", Some(v)) => v
+                .parse::<u64>()
+                .map(Victory::TimeLimit)
+                .map_err(|_| format!("{:?} is not a number of generations in {:?}", v, s)),
+            _ => Err(format!(
+                "{:?} is not a victory condition (domination:<percent>, last-alive or time:<generations>)",
+                s
+            )),
+        }
+    }
+}
+
+/// Emitted once, when a victory condition is met.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameOver {
+    pub generation: u64,
+    /// None for a draw.
+    pub winner: Option<Tribe>,
+    pub victory: Victory,
+}
+
+impl Display for GameOver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let reason = match self.victory {
+            Victory::Domination(_) => "domination",
+            Victory::LastTribeAlive => "last tribe alive",
+            Victory::TimeLimit(_) => "time limit",
+        };
+        match self.winner {
+            Some(tribe) => write!(
+                f,
+                "GAME OVER at generation {}: tribe {} wins by {}",
+                self.generation, tribe.0, reason
+            ),
+            None => write!(
+                f,
+                "GAME OVER at generation {}: draw by {}",
+                self.generation, reason
+            ),
+        }
+    }
+}
+
+/// Checks victory conditions against successive scoreboards, in the order the conditions are given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Referee {
+    conditions: Vec<Victory>,
+    /// Tribes that had live cells at some point.
+    players: BTreeSet<Tribe>,
+    over: Option<GameOver>,
+}
+
+impl Referee {
+    pub fn new(conditions: Vec<Victory>) -> Self {
+        Self {
+            conditions,
+            ..Self::default()
+        }
+    }
+
+    pub fn game_over(&self) -> Option<&GameOver> {
+        self.over.as_ref()
+    }
+
+    /// Returns the game over event, the first time a condition is met only.
+    pub fn judge(&mut self, scores: &Scoreboard) -> Option<GameOver> {
+        if self.over.is_some() {
+            return None;
+        }
+        self.players.extend(scores.alive());
+
+        let over = self
+            .conditions
+            .iter()
+            .find_map(|victory| Self::check(*victory, scores, &self.players));
+        self.over.clone_from(&over);
+        over
+    }
+
+    fn check(victory: Victory, scores: &Scoreboard, players: &BTreeSet<Tribe>) -> Option<GameOver> {
+        let winner = match victory {
+            Victory::Domination(share) => Some(
+                scores
+                    .scores
+                    .iter()
+                    .find(|s| s.terrain_share >= share)?
+                    .tribe,
+            ),
+            Victory::LastTribeAlive => {
+                if players.len() < 2 {
+                    return None;
+                }
+                let mut alive = scores.alive();
+                match (alive.next(), alive.next()) {
+                    (Some(last), None) => Some(last),
+                    (None, _) => None, // everyone died at once
+                    _ => return None,
+                }
+            }
+            Victory::TimeLimit(generations) => {
+                if scores.generation < generations {
+                    return None;
+                }
+                scores.leader()
+            }
+        };
+        Some(GameOver {
+            generation: scores.generation,
+            winner,
+            victory,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::quad::Quad;
+    use crate::score::{Referee, Scoreboard, Victory};
+    use crate::tribe::Tribe;
+    use figment::compute::Computable;
+    use std::time::Duration;
+
+    fn next_generation(q: &mut Quad) {
+        let mut stepper = q.compute_reset();
+        q.compute(Duration::new(0, 0), &mut stepper);
+    }
+
+    #[test]
+    fn check_scoreboard() {
+        let mut q = Quad::gen(State::Dead, 4, 4);
+        q.place([(0, 0), (1, 0), (0, 1), (1, 1)], Tribe(0));
+        q.place([(3, 3)], Tribe(1));
+        q.paint([(3, 0)], State::Alive);
+
+        let s = Scoreboard::of(&q);
+        assert_eq!(s.total_cells, 16);
+        assert_eq!(s.total_live, 6);
+        assert_eq!(s.scores.len(), 2);
+
+        let red = s.get(Tribe(0)).unwrap();
+        assert_eq!((red.live_cells, red.territory), (4, 4));
+        assert_eq!(red.live_share, 4. / 6.);
+        assert_eq!(red.terrain_share, 0.25);
+        assert_eq!(s.leader(), Some(Tribe(0)));
+
+        // the lone cell dies, its territory remains
+        next_generation(&mut q);
+        let s = Scoreboard::of(&q);
+        let green = s.get(Tribe(1)).unwrap();
+        assert_eq!((green.live_cells, green.territory), (0, 1));
+        assert_eq!(s.alive().collect::<Vec<_>>(), vec![Tribe(0)]);
+    }
+
+    #[test]
+    fn check_domination() {
+        let mut q = Quad::gen(State::Dead, 4, 4);
+        q.place((0..4).flat_map(|y| (0..3).map(move |x| (x, y))), Tribe(2));
+        let mut referee = Referee::new(vec![Victory::Domination(0.8)]);
+        assert_eq!(referee.judge(&Scoreboard::of(&q)), None);
+
+        q.place([(3, 0), (3, 1)], Tribe(2));
+        let over = referee.judge(&Scoreboard::of(&q)).unwrap();
+        assert_eq!(over.winner, Some(Tribe(2)));
+        assert_eq!(over.victory, Victory::Domination(0.8));
+
+        // emitted once only
+        assert_eq!(referee.judge(&Scoreboard::of(&q)), None);
+        assert_eq!(referee.game_over(), Some(&over));
+    }
+
+    #[test]
+    fn check_last_tribe_alive() {
+        let mut q = Quad::gen(State::Dead, 8, 8);
+        let mut referee = Referee::new(vec![Victory::LastTribeAlive]);
+        // a block lives on, a single cell dies
+        q.place([(0, 0), (1, 0), (0, 1), (1, 1)], Tribe(0));
+        assert_eq!(referee.judge(&Scoreboard::of(&q)), None);
+        q.place([(6, 6)], Tribe(1));
+        assert_eq!(referee.judge(&Scoreboard::of(&q)), None);
+
+        next_generation(&mut q);
+        let over = referee.judge(&Scoreboard::of(&q)).unwrap();
+        assert_eq!(over.winner, Some(Tribe(0)));
+        assert_eq!(over.generation, 1);
+    }
+
+    #[test]
+    fn check_time_limit() {
+        let mut q = Quad::gen(State::Dead, 8, 8);
+        q.place([(0, 0), (1, 0), (0, 1), (1, 1)], Tribe(0));
+        q.place([(5, 5), (6, 5), (5, 6), (6, 6)], Tribe(1));
+        let mut referee = Referee::new(vec![Victory::TimeLimit(2)]);
+
+        next_generation(&mut q);
+        assert_eq!(referee.judge(&Scoreboard::of(&q)), None);
+        next_generation(&mut q);
+        let over = referee.judge(&Scoreboard::of(&q)).unwrap();
+        assert_eq!(over.winner, None);
+        assert_eq!(
+            over.to_string(),
+            "GAME OVER at generation 2: draw by time limit"
+        );
+    }
+
+    #[test]
+    fn check_parse_victory() {
+        assert_eq!(
+            "domination:75".parse::<Victory>(),
+            Ok(Victory::Domination(0.75))
+        );
+        assert_eq!("Last-Alive".parse::<Victory>(), Ok(Victory::LastTribeAlive));
+        assert_eq!("time:500".parse::<Victory>(), Ok(Victory::TimeLimit(500)));
+        assert!("domination:150".parse::<Victory>().is_err());
+        assert!("time".parse::<Victory>().is_err());
+        assert!("chess".parse::<Victory>().is_err());
+        assert_eq!(Victory::TimeLimit(500).to_string(), "time:500");
+    }
+}
@@ -0,0 +1,147 @@
+use crate::cell;
+use crate::rule::Topology;
+use grid::Grid;
+use macroquad::color;
+
+/// A player's colour. Live cells belong to a tribe, or to none (neutral cells).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tribe(pub u8);
+
+/// Tribes have distinct colours : there are at most this many, `Tribe(0)` to `Tribe(MAX_TRIBES - 1)`.
+pub const MAX_TRIBES: usize = COLORS.len();
+
+const COLORS: [color::Color; 6] = [
+    color::RED,
+    color::GREEN,
+    color::BLUE,
+    color::ORANGE,
+    color::PURPLE,
+    color::SKYBLUE,
+];
+
+impl Tribe {
+    /// Tribes past `MAX_TRIBES` reuse colours : games refuse them.
+    pub fn color(&self) -> color::Color {
+        COLORS[self.0 as usize % COLORS.len()]
+    }
+
+    /// Maps a pixel colour to the tribe drawn with it, if any.
+    pub fn from_color(color: &[u8; 4]) -> Option<Tribe> {
+        COLORS
+            .iter()
+            .position(|c| <color::Color as Into<[u8; 4]>>::into(*c) == *color)
+            .map(|i| Tribe(i as u8))
+    }
+}
+
+/// splitmix64 finalizer : a cheap, well distributed and portable hash.
+pub(crate) fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// Owner of a cell being born at (x, y) : the majority among its live neighbours' owners.
+/// Ties are broken pseudo-randomly, but deterministically from `salt` and the position,
+/// so the result never depends on the order cells are updated in.
+pub(crate) fn inherit(
+    cells: &Grid<cell::State>,
+    owners: &Grid<Option<Tribe>>,
+    x: i32,
+    y: i32,
+    topology: Topology,
+    salt: u64,
+) -> Option<Tribe> {
+    // tiny vote table : at most 8 distinct candidates
+    let mut votes: Vec<(Option<Tribe>, u8)> = Vec::with_capacity(8);
+
+    for j in -1i32..=1 {
+        for i in -1i32..=1 {
+            if i == 0 && j == 0 {
+                continue;
+            }
+            let (row, col) = match topology {
+                Topology::Bounded => (y + j, x + i),
+                Topology::Torus => (
+                    (y + j).rem_euclid(cells.rows() as i32),
+                    (x + i).rem_euclid(cells.cols() as i32),
+                ),
+            };
+            if cells.get(row, col) == Some(&cell::State::Alive) {
+                let owner = owners.get(row, col).copied().flatten();
+                match votes.iter_mut().find(|(o, _)| *o == owner) {
+                    Some((_, count)) => *count += 1,
+                    None => votes.push((owner, 1)),
+                }
+            }
+        }
+    }
+
+    let best = votes.iter().map(|(_, c)| *c).max()?;
+    let mut candidates: Vec<Option<Tribe>> = votes
+        .iter()
+        .filter(|(_, c)| *c == best)
+        .map(|(o, _)| *o)
+        .collect();
+    candidates.sort();
+
+    let pick = mix(salt ^ (x as u32 as u64) ^ ((y as u32 as u64) << 32));
+    candidates[(pick % candidates.len() as u64) as usize]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cell::State;
+    use crate::rule::Topology;
+    use crate::tribe::{inherit, Tribe, MAX_TRIBES};
+    use grid::grid;
+
+    #[test]
+    fn check_color_roundtrip() {
+        let c: [u8; 4] = Tribe(2).color().into();
+        assert_eq!(Tribe::from_color(&c), Some(Tribe(2)));
+        assert_eq!(Tribe::from_color(&[0, 0, 0, 255]), None);
+        for t in 0..MAX_TRIBES as u8 {
+            let c: [u8; 4] = Tribe(t).color().into();
+            assert_eq!(Tribe::from_color(&c), Some(Tribe(t)));
+        }
+    }
+
+    #[test]
+    fn check_majority_wins() {
+        let a = State::Alive;
+        let d = State::Dead;
+        let r = Some(Tribe(0));
+        let g = Some(Tribe(1));
+
+        let cells = grid![[a, a, d][d, d, d][a, d, d]];
+        let owners = grid![[r, g, None][None, None, None][g, None, None]];
+
+        for salt in 0..16 {
+            assert_eq!(
+                inherit(&cells, &owners, 1, 1, Topology::Bounded, salt),
+                Some(Tribe(1))
+            );
+        }
+    }
+
+    #[test]
+    fn check_tie_is_deterministic_but_varies() {
+        let a = State::Alive;
+        let d = State::Dead;
+        let cells = grid![[a, d, a][d, d, d][a, d, d]];
+        let owners = grid![[Some(Tribe(0)), None, Some(Tribe(1))][None, None, None][Some(Tribe(2)), None, None]];
+
+        let picks: Vec<Option<Tribe>> = (0..32)
+            .map(|salt| inherit(&cells, &owners, 1, 1, Topology::Bounded, salt))
+            .collect();
+        let again: Vec<Option<Tribe>> = (0..32)
+            .map(|salt| inherit(&cells, &owners, 1, 1, Topology::Bounded, salt))
+            .collect();
+
+        assert_eq!(picks, again);
+        assert!(picks.contains(&Some(Tribe(0))));
+        assert!(picks.contains(&Some(Tribe(1))));
+        assert!(picks.contains(&Some(Tribe(2))));
+    }
+}